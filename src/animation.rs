@@ -0,0 +1,158 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::error::{LimitError, LimitErrorKind};
+use image::{AnimationDecoder, DynamicImage, Frames, ImageError, ImageResult};
+use std::io::Read;
+use std::time::Duration;
+
+// --- ANIMATED INPUT ---
+
+/// Frames beyond this are dropped.
+pub const MAX_FRAMES: usize = 300;
+/// Pixels decoded across all frames, 512 MiB as RGBA; larger animations are refused.
+const MAX_TOTAL_PIXELS: u64 = 128 * 1024 * 1024;
+/// Browsers treat shorter GIF delays (often 0) as 100 ms; do the same.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// One still of an animation and how long it is shown.
+pub struct Frame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+/// The frames of any input, animated or not; a still image is a single frame with no delay.
+pub struct FrameSequence {
+    pub frames: Vec<Frame>,
+}
+
+impl FrameSequence {
+    pub fn still(image: DynamicImage) -> Self {
+        Self { frames: vec![Frame { image, delay: Duration::ZERO }] }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// Applies `f` to every frame's image.
+    pub fn map_images(self, mut f: impl FnMut(DynamicImage) -> anyhow::Result<DynamicImage>) -> anyhow::Result<Self> {
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|frame| Ok(Frame { image: f(frame.image)?, delay: frame.delay }))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { frames })
+    }
+}
+
+/// Container formats that may hold an animation.
+#[derive(Clone, Copy)]
+pub enum AnimatedFormat {
+    Gif,
+    Png,
+    WebP,
+}
+
+impl AnimatedFormat {
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"GIF8") {
+            Some(AnimatedFormat::Gif)
+        } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(AnimatedFormat::Png)
+        } else if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+            Some(AnimatedFormat::WebP)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decodes all frames of an animated GIF, APNG or WebP. Returns `None` for inputs with a
+/// single frame, which go through the regular decoder instead.
+pub fn decode<R: Read>(reader: R, format: AnimatedFormat) -> ImageResult<Option<FrameSequence>> {
+    let frames = match format {
+        AnimatedFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+        AnimatedFormat::Png => {
+            let decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        }
+        AnimatedFormat::WebP => {
+            let decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+    };
+    let frames = collect(frames, MAX_TOTAL_PIXELS)?;
+    Ok((frames.len() > 1).then_some(FrameSequence { frames }))
+}
+
+/// The first [`MAX_FRAMES`] frames, failing once they hold more than `max_pixels` in all.
+fn collect(frames: Frames<'_>, max_pixels: u64) -> ImageResult<Vec<Frame>> {
+    let mut pixels = 0u64;
+    frames
+        .take(MAX_FRAMES)
+        .map(|frame| {
+            let frame = frame?;
+            pixels += u64::from(frame.buffer().width()) * u64::from(frame.buffer().height());
+            if pixels > max_pixels {
+                return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)));
+            }
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_secs_f64(numer as f64 / denom.max(1) as f64 / 1000.0);
+            let delay = if delay < MIN_DELAY { DEFAULT_DELAY } else { delay };
+            Ok(Frame { image: DynamicImage::ImageRgba8(frame.into_buffer()), delay })
+        })
+        .collect()
+}
+
+/// One converted frame of an animation.
+pub struct AsciiFrame {
+    pub art: String,
+    pub delay: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, RgbaImage};
+
+    fn frames(count: usize, size: u32, delay_ms: u32) -> Frames<'static> {
+        Frames::new(Box::new((0..count).map(move |_| {
+            Ok(image::Frame::from_parts(RgbaImage::new(size, size), 0, 0, Delay::from_numer_denom_ms(delay_ms, 1)))
+        })))
+    }
+
+    #[test]
+    fn sniffs_containers() {
+        assert!(matches!(AnimatedFormat::sniff(b"GIF89a"), Some(AnimatedFormat::Gif)));
+        assert!(matches!(AnimatedFormat::sniff(b"\x89PNG\r\n\x1a\n"), Some(AnimatedFormat::Png)));
+        assert!(matches!(AnimatedFormat::sniff(b"RIFF\0\0\0\0WEBPVP8X"), Some(AnimatedFormat::WebP)));
+        assert!(AnimatedFormat::sniff(b"\xff\xd8\xff").is_none());
+    }
+
+    #[test]
+    fn keeps_delays_and_raises_short_ones() {
+        let collected = collect(frames(2, 4, 50), MAX_TOTAL_PIXELS).unwrap();
+        assert_eq!(collected[0].delay, Duration::from_millis(50));
+        let collected = collect(frames(2, 4, 0), MAX_TOTAL_PIXELS).unwrap();
+        assert_eq!(collected[0].delay, DEFAULT_DELAY);
+    }
+
+    #[test]
+    fn caps_the_frame_count() {
+        assert_eq!(collect(frames(MAX_FRAMES + 5, 1, 100), MAX_TOTAL_PIXELS).unwrap().len(), MAX_FRAMES);
+    }
+
+    #[test]
+    fn caps_the_pixels_across_frames() {
+        assert_eq!(collect(frames(4, 10, 100), 400).unwrap().len(), 4);
+        assert!(matches!(collect(frames(5, 10, 100), 400), Err(ImageError::Limits(_))));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::quantize;
+use image::RgbImage;
+use std::fmt::Write;
+
+// --- ANSI COLOR OUTPUT ---
+
+const RESET: &str = "\x1b[0m";
+
+/// Colors a terminal can show.
+#[derive(Clone, Copy, Default)]
+pub enum Palette {
+    #[default]
+    TrueColor,
+    /// The xterm 256-color palette.
+    Xterm256,
+    /// The 16 standard colors, for the oldest terminals.
+    Ansi16,
+}
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Some(Palette::TrueColor),
+            "256" => Some(Palette::Xterm256),
+            "16" => Some(Palette::Ansi16),
+            _ => None,
+        }
+    }
+
+    fn foreground(self, color: [u8; 3]) -> Foreground {
+        match self {
+            Palette::TrueColor => Foreground::Rgb(color),
+            Palette::Xterm256 => Foreground::Indexed(quantize::nearest_256(color)),
+            Palette::Ansi16 => Foreground::Standard(quantize::nearest_16(color)),
+        }
+    }
+}
+
+/// A foreground color as the terminal receives it.
+#[derive(Clone, Copy, PartialEq)]
+enum Foreground {
+    Rgb([u8; 3]),
+    Indexed(u8),
+    Standard(u8),
+}
+
+impl Foreground {
+    fn write(self, out: &mut String) {
+        let _ = match self {
+            Foreground::Rgb([r, g, b]) => write!(out, "\x1b[38;2;{};{};{}m", r, g, b),
+            Foreground::Indexed(index) => write!(out, "\x1b[38;5;{}m", index),
+            Foreground::Standard(index @ 0..=7) => write!(out, "\x1b[{}m", 30 + index),
+            Foreground::Standard(index) => write!(out, "\x1b[{}m", 90 + index - 8),
+        };
+    }
+}
+
+/// Colors each character of `ascii_art` with its cell color, quantized to `palette`.
+/// Spaces carry no color, and every line ends with a reset so a cut-off stream never
+/// leaves the terminal colored.
+pub fn colorize(ascii_art: &str, colors: &RgbImage, palette: Palette) -> String {
+    let mut out = String::with_capacity(ascii_art.len() * 8);
+    for (y, line) in ascii_art.lines().enumerate() {
+        let mut current = None;
+        for (x, ch) in line.chars().enumerate() {
+            if ch != ' ' {
+                let color = colors.get_pixel_checked(x as u32, y as u32).map(|pixel| palette.foreground(pixel.0));
+                if let Some(color) = color.filter(|&color| current != Some(color)) {
+                    color.write(&mut out);
+                    current = Some(color);
+                }
+            }
+            out.push(ch);
+        }
+        if current.is_some() {
+            out.push_str(RESET);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn red_then_blue() -> RgbImage {
+        RgbImage::from_fn(3, 1, |x, _| if x < 2 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) })
+    }
+
+    #[test]
+    fn parses_palette_names() {
+        assert!(matches!(Palette::from_name(" 24BIT "), Some(Palette::TrueColor)));
+        assert!(matches!(Palette::from_name("256"), Some(Palette::Xterm256)));
+        assert!(matches!(Palette::from_name("16"), Some(Palette::Ansi16)));
+        assert!(Palette::from_name("8").is_none());
+    }
+
+    #[test]
+    fn colors_each_change_once_and_resets_lines() {
+        assert_eq!(
+            colorize("ab#", &red_then_blue(), Palette::TrueColor),
+            "\x1b[38;2;255;0;0mab\x1b[38;2;0;0;255m#\x1b[0m\n"
+        );
+        assert_eq!(colorize("ab#", &red_then_blue(), Palette::Xterm256), "\x1b[38;5;196mab\x1b[38;5;21m#\x1b[0m\n");
+        assert_eq!(colorize("ab#", &red_then_blue(), Palette::Ansi16), "\x1b[91mab\x1b[34m#\x1b[0m\n");
+    }
+
+    #[test]
+    fn leaves_spaces_and_blank_lines_plain() {
+        let colors = RgbImage::from_pixel(2, 2, Rgb([255, 0, 0]));
+        assert_eq!(colorize("  \n", &colors, Palette::TrueColor), "  \n");
+        assert_eq!(colorize(" a\n", &colors, Palette::Ansi16), " \x1b[91ma\x1b[0m\n");
+    }
+
+    #[test]
+    fn standard_colors_use_their_own_codes() {
+        let mut out = String::new();
+        Foreground::Standard(1).write(&mut out);
+        Foreground::Standard(15).write(&mut out);
+        assert_eq!(out, "\x1b[31m\x1b[97m");
+    }
+}
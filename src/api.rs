@@ -0,0 +1,315 @@
+use crate::cache::ResultCache;
+use crate::concurrency::{self, ConversionLimiter, Overloaded, Priority};
+use crate::config::ServerConfig;
+use crate::charset;
+use crate::collage;
+use crate::http_cache::Validator;
+use crate::{convert_cached, AsciiConfig, AsciiConverter, ColorTheme};
+use crate::form::{UploadFields, UploadForm};
+use crate::recipe::RecipeStore;
+use crate::tile::TileQuery;
+use crate::logging;
+use actix_multipart::Multipart;
+use crate::output::{self, AsciiGrid, OutputRenderer};
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, mime, post, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::sync::LazyLock;
+use utoipa::ToSchema;
+
+// --- JSON API ---
+
+#[derive(Serialize, ToSchema)]
+pub struct ConvertResponse<'a> {
+    /// The art, one line per row.
+    ascii: &'a str,
+    /// Characters per line.
+    width: u32,
+    /// Number of lines.
+    height: u32,
+    /// Why the art is smaller than asked for, such as a full-resolution conversion scaled down to the server's cap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notice: Option<&'a str>,
+    /// All frames of an animated input, the first of which is `ascii`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    frames: Vec<FrameResponse<'a>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FrameResponse<'a> {
+    ascii: &'a str,
+    /// How long the frame is shown.
+    delay_ms: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+/// Representations `/api/convert` can return: its own JSON or one of the output formats.
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Rendered(&'static dyn OutputRenderer),
+}
+
+/// Picks the representation from `Accept` in the client's order of preference.
+/// Without the header, or for `*/*`, the answer is JSON.
+fn negotiate(req: &HttpRequest) -> Option<Format> {
+    let ranked = match req.get_header::<header::Accept>() {
+        Some(accept) => accept.ranked(),
+        None => return Some(Format::Json),
+    };
+    if ranked.is_empty() {
+        return Some(Format::Json);
+    }
+    ranked.iter().find_map(|accepted| {
+        let renderer = match (accepted.type_(), accepted.subtype()) {
+            (mime::STAR, mime::STAR) | (mime::APPLICATION, mime::STAR) | (mime::APPLICATION, mime::JSON) => {
+                return Some(Format::Json);
+            }
+            (mime::TEXT, mime::STAR) | (mime::TEXT, mime::PLAIN) => "text",
+            (mime::TEXT, mime::HTML) => "html",
+            (mime::IMAGE, mime::STAR) | (mime::IMAGE, mime::PNG) => "png",
+            (mime::IMAGE, mime::SVG) => "svg",
+            (mime::IMAGE, mime::GIF) => "gif",
+            (mime::IMAGE, subtype) if subtype == "apng" => "apng",
+            (mime::APPLICATION, mime::PDF) => "pdf",
+            _ if accepted.essence_str() == output::GRID_CONTENT_TYPE => "grid",
+            (mime::APPLICATION, subtype) if subtype == "zip" => "zip",
+            _ => return None,
+        };
+        output::renderer(renderer).map(Format::Rendered)
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CharsetPreset {
+    /// Value for the `preset` form field.
+    name: &'static str,
+    description: &'static str,
+    /// The ramp, from the least to the most ink.
+    characters: &'static str,
+    /// A shaded sphere drawn with the ramp.
+    sample: String,
+}
+
+/// Width and height of the sample render in characters.
+const SAMPLE_SIZE: (u32, u32) = (40, 16);
+
+static CHARSETS: LazyLock<String> = LazyLock::new(|| {
+    let sample = charset::sample_image(SAMPLE_SIZE.0, SAMPLE_SIZE.1);
+    let presets: Vec<CharsetPreset> = charset::PRESETS
+        .iter()
+        .map(|preset| {
+            let config = AsciiConfig { character_set: preset.chars.chars().collect(), ..AsciiConfig::new(ColorTheme::Dark, false) };
+            CharsetPreset {
+                name: preset.name,
+                description: preset.description,
+                characters: preset.chars,
+                sample: AsciiConverter::new(config).render_rows(&sample, None, None),
+            }
+        })
+        .collect();
+    serde_json::to_string(&presets).unwrap_or_default()
+});
+static CHARSETS_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| Validator::for_static(&CHARSETS));
+
+/// The built-in character set presets, each with a sample render.
+#[utoipa::path(
+    context_path = "/api",
+    tag = "api",
+    responses(
+        (status = 200, description = "Available presets", body = Vec<CharsetPreset>),
+        (status = 401, description = "Missing or invalid API key"),
+    ),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[get("/charsets")]
+async fn charsets(req: HttpRequest) -> HttpResponse {
+    if let Some(not_modified) = CHARSETS_VALIDATOR.not_modified(&req) {
+        return not_modified;
+    }
+    CHARSETS_VALIDATOR.ok().content_type("application/json").body(CHARSETS.as_str())
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    HttpResponse::build(status).json(ErrorResponse { error: message.into() })
+}
+
+/// Accepts the same multipart fields as `/upload` and returns the art in the
+/// representation named by `Accept`: JSON (the default), plain text, the HTML viewer, a PNG,
+/// an SVG, an animated GIF or APNG, the art cut into panels as a ZIP or PDF, or a JSON grid of cells.
+#[utoipa::path(
+    context_path = "/api",
+    tag = "api",
+    params(TileQuery),
+    request_body(content = UploadFields, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Converted image", content(
+            (ConvertResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "text/html"),
+            (Vec<u8> = "image/png"),
+            (String = "image/svg+xml"),
+            (Vec<u8> = "image/gif"),
+            (Vec<u8> = "image/apng"),
+            (Vec<u8> = "application/zip"),
+            (Vec<u8> = "application/pdf"),
+            (String = "application/vnd.ascii-grid+json"),
+        ), headers(("X-Conversion-Notice" = String, description = "Why the art is smaller than asked for"))),
+        (status = 400, description = "Missing or undecodable image", body = ErrorResponse),
+        (status = 406, description = "None of the accepted types can be produced", body = ErrorResponse),
+        (status = 422, description = "Too large to render as an image, or an emoji mosaic", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key"),
+        (status = 429, description = "Daily quota or rate limit exhausted"),
+        (status = 503, description = "Too many conversions waiting", body = ErrorResponse,
+            headers(("Retry-After" = u64, description = "Seconds to wait"))),
+    ),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[post("/convert")]
+async fn convert(
+    req: HttpRequest,
+    mut payload: Multipart,
+    tiles: web::Query<TileQuery>,
+    server_config: web::Data<ServerConfig>,
+    recipes: web::Data<RecipeStore>,
+    cache: web::Data<ResultCache>,
+    limiter: web::Data<ConversionLimiter>,
+) -> Result<HttpResponse, Error> {
+    let Some(format) = negotiate(&req) else {
+        return Ok(error(
+            StatusCode::NOT_ACCEPTABLE,
+            "Supported types are application/json, text/plain, text/html, image/png, image/svg+xml, image/gif, image/apng, application/zip, application/pdf and application/vnd.ascii-grid+json.",
+        ));
+    };
+    let mut form = UploadForm::read(&mut payload, &server_config, &recipes).await?;
+    // The grid reports each cell's color.
+    form.options.colors = matches!(format, Format::Rendered(renderer) if renderer.name() == "grid");
+    let Some(image_data) = form.image.take() else {
+        return Ok(error(StatusCode::BAD_REQUEST, "No image uploaded."));
+    };
+
+    let converted = if form.more_images.is_empty() {
+        convert_cached(image_data, &form.options, &cache, &limiter, Priority::Interactive, None).await
+    } else {
+        let images = form.take_images(image_data);
+        collage::convert(images, &form.options, &form.collage, &cache, &limiter, Priority::Interactive, None).await
+    };
+    let output = match converted {
+        Ok(output) => output,
+        Err(err) if err.is::<Overloaded>() => {
+            let mut response = error(StatusCode::SERVICE_UNAVAILABLE, format!("{}.", err));
+            let retry_after = header::HeaderValue::from(concurrency::RETRY_AFTER_SECS);
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after);
+            return Ok(response);
+        }
+        Err(err) => return Ok(error(StatusCode::BAD_REQUEST, format!("{:#}", err))),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::VARY, "Accept"));
+    if let Some(notice) = &output.notice {
+        response.insert_header((output::NOTICE_HEADER, notice.as_str()));
+    }
+    let response = match format {
+        Format::Json => {
+            let (width, height) = output.dimensions;
+            let frames = output
+                .frames
+                .iter()
+                .map(|frame| FrameResponse { ascii: &frame.art, delay_ms: frame.delay.as_millis() as u64 })
+                .collect();
+            response.json(ConvertResponse {
+                ascii: &output.ascii_art,
+                width,
+                height,
+                notice: output.notice.as_deref(),
+                frames,
+            })
+        }
+        Format::Rendered(renderer) if !renderer.supports(output.mode) => {
+            return Ok(error(StatusCode::UNPROCESSABLE_ENTITY, "Emoji mosaics are only available as JSON, text or HTML."));
+        }
+        Format::Rendered(renderer) => {
+            // Bitmaps take a while to draw.
+            let (art, tile_size) = (output.clone(), tiles.size());
+            match web::block(move || renderer.render(&AsciiGrid::new(&art).with_tile_size(tile_size))).await? {
+                Ok(rendered) => response.content_type(rendered.content_type).body(rendered.body),
+                Err(err) => return Ok(error(StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", err))),
+            }
+        }
+    };
+    logging::record_conversion(&form.options, output.ascii_art.len());
+    Ok(response)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(convert).service(charsets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+
+    fn chosen(accept: &str) -> Option<&'static str> {
+        let req = TestRequest::default().insert_header((header::ACCEPT, accept)).to_http_request();
+        negotiate(&req).map(|format| match format {
+            Format::Json => "json",
+            Format::Rendered(renderer) => renderer.name(),
+        })
+    }
+
+    #[test]
+    fn follows_quality_values() {
+        assert_eq!(chosen("image/png;q=0.5, text/plain"), Some("text"));
+        assert_eq!(chosen("text/html;q=0.2, image/svg+xml;q=0.9, application/json;q=0.4"), Some("svg"));
+        assert_eq!(chosen("application/pdf;q=0.1, application/vnd.ascii-grid+json"), Some("grid"));
+        // A type the client refuses is never picked.
+        assert_eq!(chosen("image/gif;q=0, image/apng"), Some("apng"));
+    }
+
+    #[test]
+    fn resolves_wildcards() {
+        assert!(matches!(negotiate(&TestRequest::default().to_http_request()), Some(Format::Json)));
+        assert_eq!(chosen("*/*"), Some("json"));
+        assert_eq!(chosen("application/*"), Some("json"));
+        assert_eq!(chosen("text/*"), Some("text"));
+        assert_eq!(chosen("image/*"), Some("png"));
+        assert_eq!(chosen("video/mp4, image/*;q=0.3"), Some("png"));
+    }
+
+    #[test]
+    fn finds_no_match_for_unknown_types() {
+        assert_eq!(chosen("video/mp4"), None);
+        assert_eq!(chosen("audio/*, application/xml"), None);
+    }
+
+    #[actix_web::test]
+    async fn answers_406_without_a_match() {
+        let config = ServerConfig::from_env();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ResultCache::new(config.cache_capacity, config.cache_ttl)))
+                .app_data(web::Data::new(ConversionLimiter::new(1, 1)))
+                .app_data(web::Data::new(RecipeStore::open(None).unwrap()))
+                .app_data(web::Data::new(config))
+                .configure(configure),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/convert")
+            .insert_header((header::ACCEPT, "video/mp4"))
+            .insert_header((header::CONTENT_TYPE, "multipart/form-data; boundary=xyz"))
+            .set_payload("--xyz--\r\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+        let body = test::read_body(resp).await;
+        assert!(std::str::from_utf8(&body).unwrap().contains("application/json"));
+    }
+}
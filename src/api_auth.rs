@@ -0,0 +1,267 @@
+use crate::config::ServerConfig;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::{Error, HttpResponse};
+use anyhow::{bail, Context, Result};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// --- API KEY AUTHENTICATION ---
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// Routes outside `/api` that convert images, guarded too unless `ASCII_API_KEYS_EVERYWHERE`
+/// is turned off.
+pub const CONVERSION_ROUTES: [&str; 5] = ["/upload", "/plain", "/jobs", "/history", "/ws/stream"];
+
+struct ApiKey {
+    /// Requests allowed per day; `None` is unlimited.
+    daily_quota: Option<u32>,
+    window_start: Instant,
+    used: u32,
+}
+
+enum Rejection {
+    Missing,
+    Invalid,
+    QuotaExceeded { retry_after: u64 },
+}
+
+/// The configured API keys and their quota usage. With no keys configured, `/api` is open.
+pub struct ApiKeyStore {
+    keys: Mutex<HashMap<String, ApiKey>>,
+}
+
+impl ApiKeyStore {
+    pub fn from_config(config: &ServerConfig) -> Result<Self> {
+        let mut entries = Vec::new();
+        if let Some(keys) = &config.api_keys {
+            for entry in keys.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                entries.push(parse_entry(entry, ':')?);
+            }
+        }
+        if let Some(path) = &config.api_keys_file {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read API key file {}", path.display()))?;
+            for line in contents.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                entries.push(parse_entry(line, ' ')?);
+            }
+        }
+
+        let now = Instant::now();
+        let keys = entries
+            .into_iter()
+            .map(|(key, daily_quota)| (key, ApiKey { daily_quota, window_start: now, used: 0 }))
+            .collect();
+        Ok(Self { keys: Mutex::new(keys) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.lock().unwrap().is_empty()
+    }
+
+    fn authorize(&self, presented: Option<&str>) -> Result<(), Rejection> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let presented = presented.ok_or(Rejection::Missing)?;
+        let key = keys.get_mut(presented).ok_or(Rejection::Invalid)?;
+
+        if key.window_start.elapsed() >= QUOTA_WINDOW {
+            key.window_start = Instant::now();
+            key.used = 0;
+        }
+        if key.daily_quota.is_some_and(|quota| key.used >= quota) {
+            let remaining = QUOTA_WINDOW.saturating_sub(key.window_start.elapsed());
+            return Err(Rejection::QuotaExceeded { retry_after: remaining.as_secs().max(1) });
+        }
+        key.used += 1;
+        Ok(())
+    }
+}
+
+/// Parses `key` or `key<separator>quota`.
+fn parse_entry(entry: &str, separator: char) -> Result<(String, Option<u32>)> {
+    match entry.split_once(separator) {
+        Some((key, quota)) => {
+            let quota = quota.trim().parse().with_context(|| format!("Invalid quota for API key entry {:?}", entry))?;
+            Ok((key.trim().to_string(), Some(quota)))
+        }
+        None if entry.chars().any(char::is_whitespace) => bail!("Invalid API key entry {:?}", entry),
+        None => Ok((entry.to_string(), None)),
+    }
+}
+
+/// Reads the key from `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+fn presented_key(req: &ServiceRequest) -> Option<&str> {
+    let headers = req.headers();
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim());
+    }
+    headers.get("X-API-Key").and_then(|value| value.to_str().ok()).map(str::trim)
+}
+
+/// Middleware factory enforcing a shared [`ApiKeyStore`].
+pub struct ApiKeyAuth {
+    store: Arc<ApiKeyStore>,
+    /// Path prefixes the check applies to; `None` checks every request.
+    paths: Option<&'static [&'static str]>,
+}
+
+impl ApiKeyAuth {
+    pub fn new(store: Arc<ApiKeyStore>) -> Self {
+        Self { store, paths: None }
+    }
+
+    /// Checks only requests under one of `paths`, letting the rest through.
+    pub fn for_paths(store: Arc<ApiKeyStore>, paths: &'static [&'static str]) -> Self {
+        Self { store, paths: Some(paths) }
+    }
+}
+
+/// Whether `path` is `prefix` or lies below it.
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyAuthMiddleware { service, store: self.store.clone(), paths: self.paths }))
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: S,
+    store: Arc<ApiKeyStore>,
+    paths: Option<&'static [&'static str]>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // CORS preflights carry no credentials; the CORS middleware answers them.
+        let checked = req.method() != Method::OPTIONS
+            && self.paths.is_none_or(|paths| paths.iter().any(|prefix| is_under(req.path(), prefix)));
+        let authorized = if checked { self.store.authorize(presented_key(&req)) } else { Ok(()) };
+        let response = match authorized {
+            Ok(()) => {
+                let fut = self.service.call(req);
+                return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+            }
+            Err(Rejection::Missing) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .body("An API key is required."),
+            Err(Rejection::Invalid) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .body("Invalid API key."),
+            Err(Rejection::QuotaExceeded { retry_after }) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .body("Daily quota for this API key exhausted."),
+        };
+        Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{web, App};
+
+    fn store(entries: &[(&str, Option<u32>)]) -> ApiKeyStore {
+        let now = Instant::now();
+        let keys = entries
+            .iter()
+            .map(|&(key, daily_quota)| (key.to_string(), ApiKey { daily_quota, window_start: now, used: 0 }))
+            .collect();
+        ApiKeyStore { keys: Mutex::new(keys) }
+    }
+
+    #[test]
+    fn parses_entries() {
+        assert_eq!(parse_entry("abc", ':').unwrap(), ("abc".to_string(), None));
+        assert_eq!(parse_entry("abc:5", ':').unwrap(), ("abc".to_string(), Some(5)));
+        assert_eq!(parse_entry("abc  100", ' ').unwrap(), ("abc".to_string(), Some(100)));
+        assert!(parse_entry("abc:lots", ':').is_err());
+        assert!(parse_entry("a b", ':').is_err());
+    }
+
+    #[test]
+    fn open_without_keys() {
+        assert!(store(&[]).authorize(None).is_ok());
+    }
+
+    #[test]
+    fn enforces_keys_and_quotas() {
+        let store = store(&[("open", None), ("metered", Some(1))]);
+        assert!(matches!(store.authorize(None), Err(Rejection::Missing)));
+        assert!(matches!(store.authorize(Some("other")), Err(Rejection::Invalid)));
+        assert!(store.authorize(Some("open")).is_ok());
+        assert!(store.authorize(Some("metered")).is_ok());
+        assert!(matches!(store.authorize(Some("metered")), Err(Rejection::QuotaExceeded { .. })));
+    }
+
+    #[actix_web::test]
+    async fn guards_the_conversion_routes() {
+        let store = Arc::new(store(&[("secret", None)]));
+        let app = test::init_service(
+            App::new()
+                .wrap(ApiKeyAuth::for_paths(store, &CONVERSION_ROUTES))
+                .route("/upload", web::post().to(HttpResponse::Ok))
+                .route("/jobs/{id}", web::get().to(HttpResponse::Ok))
+                .route("/uploads", web::post().to(HttpResponse::Ok))
+                .route("/healthz", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |req: TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+        assert_eq!(status(TestRequest::post().uri("/upload")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(TestRequest::get().uri("/jobs/1")).await, StatusCode::UNAUTHORIZED);
+        let keyed = TestRequest::post().uri("/upload").insert_header(("X-API-Key", "secret"));
+        assert_eq!(status(keyed).await, StatusCode::OK);
+        assert_eq!(status(TestRequest::post().uri("/uploads")).await, StatusCode::OK);
+        assert_eq!(status(TestRequest::default().method(Method::OPTIONS).uri("/upload")).await, StatusCode::NOT_FOUND);
+        assert_eq!(status(TestRequest::get().uri("/healthz")).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn reads_either_header() {
+        let bearer = TestRequest::default().insert_header((header::AUTHORIZATION, "Bearer abc ")).to_srv_request();
+        assert_eq!(presented_key(&bearer), Some("abc"));
+        let api_key = TestRequest::default().insert_header(("X-API-Key", "def")).to_srv_request();
+        assert_eq!(presented_key(&api_key), Some("def"));
+        assert_eq!(presented_key(&TestRequest::default().to_srv_request()), None);
+    }
+}
@@ -0,0 +1,85 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use std::path::Path;
+#[cfg(not(feature = "embed-assets"))]
+use std::path::PathBuf;
+
+// --- STATIC ASSETS ---
+
+/// Directory the pages, styles and scripts are served from.
+#[cfg(not(feature = "embed-assets"))]
+struct AssetDir(PathBuf);
+
+/// The contents of `static/`, compiled into the binary for single-file deploys.
+#[cfg(feature = "embed-assets")]
+#[derive(rust_embed::Embed)]
+#[folder = "static/"]
+struct Embedded;
+
+/// Serves `name` from the binary. The digest rust-embed computed at build time is the
+/// entity tag, and clients revalidate every time like the other compiled-in pages.
+#[cfg(feature = "embed-assets")]
+async fn serve(req: &HttpRequest, name: &str) -> HttpResponse {
+    let Some(file) = Embedded::get(name) else {
+        return HttpResponse::NotFound().body("Not found.");
+    };
+    let validator = crate::http_cache::Validator::for_digest(&file.metadata.sha256_hash());
+    if let Some(not_modified) = validator.not_modified(req) {
+        return not_modified;
+    }
+    validator.ok().content_type(file.metadata.mimetype()).body(file.data.into_owned())
+}
+
+/// Serves `name` from the asset directory, with `ETag` and `Last-Modified` validators.
+#[cfg(not(feature = "embed-assets"))]
+async fn serve(req: &HttpRequest, name: &str) -> HttpResponse {
+    use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+
+    let dir = req.app_data::<web::Data<AssetDir>>().expect("asset directory registered");
+    match actix_files::NamedFile::open_async(dir.0.join(name)).await {
+        Ok(file) => {
+            let mut response = file.into_response(req);
+            // Pages keep their URLs across releases, so they must be revalidated.
+            response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            response
+        }
+        Err(err) => {
+            tracing::warn!(asset = name, error = %err, "static asset missing");
+            HttpResponse::NotFound().body("Not found.")
+        }
+    }
+}
+
+#[get("/")]
+async fn index(req: HttpRequest) -> HttpResponse {
+    serve(&req, "index.html").await
+}
+
+#[get("/stream")]
+async fn stream_page(req: HttpRequest) -> HttpResponse {
+    serve(&req, "stream.html").await
+}
+
+/// Styles and scripts under `/static`.
+#[cfg(feature = "embed-assets")]
+#[get("/static/{name:.*}")]
+async fn asset(req: HttpRequest, name: web::Path<String>) -> HttpResponse {
+    serve(&req, &name).await
+}
+
+/// The directory is ignored when the assets are compiled in.
+#[cfg(feature = "embed-assets")]
+pub fn configure(cfg: &mut web::ServiceConfig, _dir: &Path) {
+    cfg.service(index).service(stream_page).service(asset);
+}
+
+#[cfg(not(feature = "embed-assets"))]
+pub fn configure(cfg: &mut web::ServiceConfig, dir: &Path) {
+    use actix_web::http::header;
+    use actix_web::middleware::DefaultHeaders;
+
+    let files = actix_files::Files::new("", dir).use_etag(true).use_last_modified(true);
+    cfg.app_data(web::Data::new(AssetDir(dir.to_path_buf())))
+        .service(index)
+        .service(stream_page)
+        .service(web::scope("/static").wrap(DefaultHeaders::new().add((header::CACHE_CONTROL, "no-cache"))).service(files));
+}
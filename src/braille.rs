@@ -0,0 +1,47 @@
+use crate::mapper::{CharMapper, Pixels};
+
+// --- BRAILLE DOTS ---
+
+/// First of the 256 braille patterns; the low eight bits of the rest say which dots are raised.
+const BRAILLE_BASE: u32 = 0x2800;
+/// Bit of each dot by its position in the 2x4 cell, row by row.
+const DOT_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Draws every 2x4 group of pixels as one braille pattern, with a dot for each pixel
+/// brighter than the image's mean brightness (darker with `invert`).
+pub struct Braille {
+    pub invert: bool,
+}
+
+impl CharMapper for Braille {
+    fn cell_size(&self) -> (u32, u32) {
+        (2, 4)
+    }
+
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        let mut bits = 0;
+        for (dy, row) in DOT_BITS.iter().enumerate() {
+            for (dx, bit) in row.iter().enumerate() {
+                if pixels.inked(x + dx as u32, y + dy as u32, self.invert) {
+                    bits |= bit;
+                }
+            }
+        }
+        char::from_u32(BRAILLE_BASE + bits).unwrap_or(' ')
+    }
+}
+
+/// An 8x8 bitmap of a braille pattern for the PNG renderer, in the font8x8 layout.
+pub fn glyph(ch: char) -> Option<[u8; 8]> {
+    let bits = u32::from(ch).checked_sub(BRAILLE_BASE).filter(|&bits| bits < 256)?;
+    let mut rows = [0u8; 8];
+    for (dy, row) in DOT_BITS.iter().enumerate() {
+        for (dx, bit) in row.iter().enumerate() {
+            if bits & bit != 0 {
+                // Two pixels wide, centered in each half of the cell.
+                rows[dy * 2] |= 0b11 << (1 + dx * 4);
+            }
+        }
+    }
+    Some(rows)
+}
@@ -0,0 +1,120 @@
+use crate::metrics::METRICS;
+use crate::animation::AsciiFrame;
+use crate::font::UserFont;
+use crate::Mode;
+use image::{GrayImage, RgbImage};
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// --- RESULT CACHE ---
+
+pub type CacheKey = [u8; 32];
+
+type Entries = Mutex<LruCache<CacheKey, (Instant, Arc<ConversionOutput>)>>;
+
+/// The result of one conversion, with the style the [`crate::output`] renderers draw it in.
+pub struct ConversionOutput {
+    pub ascii_art: String,
+    /// Width and height of the art in characters.
+    pub dimensions: (u32, u32),
+    /// One pixel per character, when the options asked for colors.
+    pub colors: Option<RgbImage>,
+    /// The brightness each character was chosen from, one pixel per character.
+    pub brightness: GrayImage,
+    /// Tells the user the art was scaled down below full resolution to keep it drawable.
+    pub notice: Option<String>,
+    /// The character set the art was drawn from.
+    pub charset: String,
+    /// The options that shaped the art, for the grid JSON format.
+    pub options: serde_json::Value,
+    /// Every frame of an animated input, the first of which is `ascii_art`; empty for stills.
+    pub frames: Vec<AsciiFrame>,
+    pub mode: Mode,
+    pub background_color: String,
+    pub text_color: String,
+    pub font: Option<UserFont>,
+}
+
+#[cfg(test)]
+impl ConversionOutput {
+    /// Uncolored `text` with a brightness of 200 under every character, for tests.
+    pub fn from_art(text: &str) -> Self {
+        let width = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let dimensions = (width as u32, text.lines().count() as u32);
+        ConversionOutput {
+            ascii_art: text.to_string(),
+            dimensions,
+            colors: None,
+            brightness: GrayImage::from_pixel(dimensions.0, dimensions.1, image::Luma([200])),
+            notice: None,
+            charset: String::new(),
+            options: serde_json::json!({}),
+            frames: Vec::new(),
+            mode: Mode::Ascii,
+            background_color: "#000000".to_string(),
+            text_color: "#ffffff".to_string(),
+            font: None,
+        }
+    }
+}
+
+/// An LRU cache of recent conversion results keyed by upload content and options.
+pub struct ResultCache {
+    entries: Option<Entries>,
+    ttl: Duration,
+}
+
+impl ResultCache {
+    /// Creates a cache holding up to `capacity` results. A capacity of zero disables caching.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(|cap| Mutex::new(LruCache::new(cap))),
+            ttl,
+        }
+    }
+
+    /// Combines the upload digest with a description of the conversion options.
+    pub fn key(content_digest: &[u8; 32], options: &str) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(content_digest);
+        hasher.update(options.as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<ConversionOutput>> {
+        let entries = self.entries.as_ref()?;
+        let mut entries = entries.lock().unwrap();
+        let found = match entries.get(key) {
+            Some((inserted, output)) if inserted.elapsed() < self.ttl => Some(output.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        let counter = if found.is_some() { &METRICS.cache_hits } else { &METRICS.cache_misses };
+        counter.inc();
+        found
+    }
+
+    pub fn insert(&self, key: CacheKey, output: Arc<ConversionOutput>) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put(key, (Instant::now(), output));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.as_ref().map_or(0, |entries| entries.lock().unwrap().len())
+    }
+
+    pub fn hits(&self) -> u64 {
+        METRICS.cache_hits.get()
+    }
+
+    pub fn misses(&self) -> u64 {
+        METRICS.cache_misses.get()
+    }
+}
@@ -0,0 +1,114 @@
+use crate::font::UserFont;
+use crate::render;
+use image::{GrayImage, Luma};
+
+// --- CUSTOM CHARACTER SETS ---
+
+/// A named, hand-ordered ramp from the least to the most ink.
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub chars: &'static str,
+}
+
+/// The built-in ramps, selectable by name. `classic` and `detailed` are the two sets
+/// chosen by the `detailed` flag.
+pub const PRESETS: [Preset; 10] = [
+    Preset { name: "classic", description: "The classic 10-level ASCII ramp", chars: crate::SIMPLE_CHARS },
+    Preset { name: "detailed", description: "70 ASCII levels for fine gradients", chars: crate::DETAILED_CHARS },
+    Preset { name: "minimal", description: "Four levels, for line-art and logos", chars: " .+#" },
+    Preset { name: "blocks", description: "Shade block elements", chars: " ░▒▓█" },
+    Preset { name: "braille", description: "Braille patterns with increasing dot counts", chars: "⠀⠁⠃⠇⠏⠟⠿⡿⣿" },
+    Preset { name: "dots", description: "Dots and circles", chars: " ·•●" },
+    Preset { name: "digits", description: "Digits only", chars: " 17328690" },
+    Preset { name: "letters", description: "Letters only, for text-like textures", chars: " ilcoaexsbdqpwmgMWB" },
+    Preset { name: "katakana", description: "Half-width katakana", chars: " ･ｰｨｧｼｯﾉｸﾂﾘﾝｹﾒﾍﾗﾁﾀﾏﾑﾎﾓﾛﾈﾔﾜ" },
+    Preset { name: "cyrillic", description: "Cyrillic letters", chars: " .:-гтпнкзлдфжшщШЖЩ" },
+];
+
+pub fn preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Longest ramp accepted; more levels than this are indistinguishable anyway.
+pub const MAX_CHARS: usize = 256;
+
+/// Turns a submitted ramp into characters, dropping control characters and repeats.
+/// Returns `None` for fewer than two distinct characters, which cannot form a ramp.
+pub fn parse(text: &str) -> Option<Vec<char>> {
+    let mut chars: Vec<char> = Vec::new();
+    for ch in text.chars().filter(|ch| !ch.is_control()) {
+        if !chars.contains(&ch) && chars.len() < MAX_CHARS {
+            chars.push(ch);
+        }
+    }
+    (chars.len() >= 2).then_some(chars)
+}
+
+/// Orders the ramp from the least to the most ink, as measured on `font` or else on the
+/// bitmap font the PNG renderer uses. Characters the font does not cover cannot be
+/// measured, so a ramp containing any of them is left as given.
+pub fn sort_by_density(chars: &mut [char], font: Option<&UserFont>) {
+    let densities: Option<Vec<f32>> = chars
+        .iter()
+        .map(|&ch| match font {
+            Some(font) => font.density(ch),
+            None => render::glyph(ch).map(|rows| rows.iter().map(|row| row.count_ones()).sum::<u32>() as f32 / 64.0),
+        })
+        .collect();
+    let Some(densities) = densities else { return };
+    let mut ranked: Vec<(f32, char)> = densities.into_iter().zip(chars.iter().copied()).collect();
+    // Stable, so glyphs with equal ink keep the order they were given in.
+    ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (slot, (_, ch)) in chars.iter_mut().zip(ranked) {
+        *slot = ch;
+    }
+}
+
+/// A sphere lit from the top left on a dark-to-light background, sized in characters,
+/// for showing off a ramp.
+pub fn sample_image(width: u32, height: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| {
+        // Cells are twice as tall as wide, so rows count double.
+        let (dx, dy) = ((x as f32 + 0.5) / width as f32 * 2.0 - 1.0, (y as f32 + 0.5) / height as f32 * 2.0 - 1.0);
+        let (dx, dy) = (dx * width as f32 / (height as f32 * 2.0), dy);
+        let depth = 1.0 - dx * dx - dy * dy;
+        let brightness = if depth > 0.0 {
+            let normal = [dx, dy, depth.sqrt()];
+            let light = [-0.5, -0.5, std::f32::consts::FRAC_1_SQRT_2];
+            normal.iter().zip(light).map(|(n, l)| n * l).sum::<f32>().max(0.0)
+        } else {
+            x as f32 / width as f32 * 0.3
+        };
+        Luma([(brightness * 255.0) as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_presets_by_name() {
+        assert_eq!(preset(" Blocks ").map(|preset| preset.chars), Some(" ░▒▓█"));
+        assert!(preset("unknown").is_none());
+        assert!(PRESETS.iter().all(|preset| parse(preset.chars).is_some_and(|chars| chars.len() == preset.chars.chars().count())));
+    }
+
+    #[test]
+    fn parses_ramps() {
+        assert_eq!(parse(" .\n:.#"), Some(vec![' ', '.', ':', '#']));
+        assert_eq!(parse("aaaa"), None);
+        assert_eq!(parse(&"ab".repeat(10)).map(|chars| chars.len()), Some(2));
+        let many: String = ('\u{4e00}'..).take(MAX_CHARS + 10).collect();
+        assert_eq!(parse(&many).map(|chars| chars.len()), Some(MAX_CHARS));
+    }
+
+    #[test]
+    fn sorts_by_ink() {
+        let mut chars = ['#', ' ', '.', ':'];
+        sort_by_density(&mut chars, None);
+        assert_eq!(chars, [' ', '.', ':', '#']);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::cache::{ConversionOutput, ResultCache};
+use crate::concurrency::{ConversionLimiter, Priority};
+use crate::overlay::Overlay;
+use crate::progress::Progress;
+use crate::spool::UploadBody;
+use crate::{convert_cached, filename_stem, render, ConversionOptions, Mode};
+use anyhow::{bail, Context, Result};
+use image::{GrayImage, RgbImage};
+use std::sync::Arc;
+
+// --- COLLAGES ---
+
+/// Images one request may combine.
+pub const MAX_IMAGES: usize = 16;
+/// Blank columns between neighbouring cells, and blank rows between rows of cells.
+const GAP_COLUMNS: u32 = 2;
+const GAP_ROWS: u32 = 1;
+
+/// How the images of a collage are arranged.
+#[derive(Clone, Debug)]
+pub struct CollageLayout {
+    pub columns: Option<u32>,
+    pub rows: Option<u32>,
+    /// Write each cell's label above its art.
+    pub labels: bool,
+    /// Labels of the cells in order, replacing their filenames.
+    pub names: Vec<String>,
+}
+
+impl Default for CollageLayout {
+    fn default() -> Self {
+        Self { columns: None, rows: None, labels: true, names: Vec::new() }
+    }
+}
+
+impl CollageLayout {
+    /// Columns and rows for `count` cells: as given, or as close to a square as possible.
+    fn grid(&self, count: usize) -> Result<(u32, u32)> {
+        let count = count as u32;
+        let (columns, rows) = match (self.columns, self.rows) {
+            (Some(columns), Some(rows)) => (columns, rows),
+            (Some(columns), None) => (columns, count.div_ceil(columns)),
+            (None, Some(rows)) => (count.div_ceil(rows), rows),
+            (None, None) => {
+                let columns = f64::from(count).sqrt().ceil() as u32;
+                (columns, count.div_ceil(columns))
+            }
+        };
+        if columns * rows < count {
+            bail!("A collage of {} by {} cells has no room for {} images", columns, rows, count);
+        }
+        Ok((columns, rows))
+    }
+}
+
+/// Converts every image with the same options and composes the results into one
+/// collage, row by row. Animated images contribute their first frame; the progress bar
+/// follows the last image.
+pub async fn convert(
+    images: Vec<(String, UploadBody)>,
+    options: &ConversionOptions,
+    layout: &CollageLayout,
+    cache: &ResultCache,
+    limiter: &ConversionLimiter,
+    priority: Priority,
+    mut progress: Option<Arc<Progress>>,
+) -> Result<Arc<ConversionOutput>> {
+    if options.mode == Mode::Emoji {
+        bail!("Emoji mosaics cannot be combined into a collage");
+    }
+    let grid = layout.grid(images.len())?;
+    // The caption and watermark go on the collage as a whole.
+    let cell_options = ConversionOptions { first_frame_only: true, overlay: Overlay::default(), ..options.clone() };
+    let count = images.len();
+    let mut cells = Vec::with_capacity(count);
+    for (index, (filename, image)) in images.into_iter().enumerate() {
+        let image_progress = if index + 1 == count { progress.take() } else { None };
+        let output = convert_cached(image, &cell_options, cache, limiter, priority, image_progress)
+            .await
+            .with_context(|| format!("Image {} ({})", index + 1, filename))?;
+        let label = layout.names.get(index).filter(|name| !name.trim().is_empty()).cloned();
+        cells.push((label.unwrap_or_else(|| filename_stem(&filename)), output));
+    }
+    let mut collage = compose(&cells, grid, layout.labels);
+    options.overlay.apply(&mut collage)?;
+    Ok(Arc::new(collage))
+}
+
+/// Lays the cells out left to right and top to bottom, each centered in a box as large
+/// as the largest art. Padding and labels get a brightness of 0.
+fn compose(cells: &[(String, Arc<ConversionOutput>)], (columns, rows): (u32, u32), labels: bool) -> ConversionOutput {
+    let first = &cells[0].1;
+    let art_width = cells.iter().map(|(_, output)| output.dimensions.0).max().unwrap_or(0);
+    let art_height = cells.iter().map(|(_, output)| output.dimensions.1).max().unwrap_or(0);
+    let label_rows = u32::from(labels);
+    let (cell_width, cell_height) = (art_width, art_height + label_rows);
+    // Rows left empty by the images are dropped.
+    let rows = rows.min((cells.len() as u32).div_ceil(columns));
+    let width = columns * cell_width + (columns - 1) * GAP_COLUMNS;
+    let height = rows * cell_height + (rows - 1) * GAP_ROWS;
+
+    let mut lines = vec![vec![' '; width as usize]; height as usize];
+    let text_color = render::parse_hex_color(&first.text_color).unwrap_or(image::Rgb([255, 255, 255]));
+    let mut colors = cells
+        .iter()
+        .any(|(_, output)| output.colors.is_some())
+        .then(|| RgbImage::from_pixel(width, height, text_color));
+    let mut brightness = GrayImage::new(width, height);
+    let mut notices = Vec::new();
+
+    for (index, (label, output)) in cells.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let (left, top) = (column * (cell_width + GAP_COLUMNS), row * (cell_height + GAP_ROWS));
+        if labels {
+            let label: Vec<char> = label.chars().take(cell_width as usize).collect();
+            let start = (left + (cell_width - label.len() as u32) / 2) as usize;
+            lines[top as usize][start..start + label.len()].copy_from_slice(&label);
+        }
+
+        let (columns, rows) = output.dimensions;
+        let x = left + (cell_width - columns) / 2;
+        let y = top + label_rows + (art_height - rows) / 2;
+        for (offset, line) in output.ascii_art.lines().enumerate() {
+            let target = &mut lines[y as usize + offset];
+            for (cell, ch) in target[x as usize..].iter_mut().zip(line.chars().take(columns as usize)) {
+                *cell = ch;
+            }
+        }
+        if let (Some(colors), Some(cell_colors)) = (&mut colors, &output.colors) {
+            image::imageops::replace(colors, cell_colors, i64::from(x), i64::from(y));
+        }
+        image::imageops::replace(&mut brightness, &output.brightness, i64::from(x), i64::from(y));
+        if let Some(notice) = &output.notice {
+            notices.push(format!("{}: {}", label, notice));
+        }
+    }
+
+    let mut ascii_art = String::with_capacity(lines.len() * (width as usize + 1));
+    for line in lines {
+        ascii_art.extend(line);
+        ascii_art.push('\n');
+    }
+    let mut options = first.options.clone();
+    options["collage"] = serde_json::json!({ "columns": columns, "rows": rows, "images": cells.len() });
+    ConversionOutput {
+        ascii_art,
+        dimensions: (width, height),
+        colors,
+        brightness,
+        notice: (!notices.is_empty()).then(|| notices.join(" ")),
+        charset: first.charset.clone(),
+        options,
+        frames: Vec::new(),
+        mode: first.mode,
+        background_color: first.background_color.clone(),
+        text_color: first.text_color.clone(),
+        font: first.font.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(columns: Option<u32>, rows: Option<u32>) -> CollageLayout {
+        CollageLayout { columns, rows, ..CollageLayout::default() }
+    }
+
+    #[test]
+    fn picks_the_grid() {
+        assert_eq!(layout(None, None).grid(5).unwrap(), (3, 2));
+        assert_eq!(layout(None, None).grid(4).unwrap(), (2, 2));
+        assert_eq!(layout(Some(1), None).grid(3).unwrap(), (1, 3));
+        assert_eq!(layout(None, Some(1)).grid(3).unwrap(), (3, 1));
+        assert_eq!(layout(Some(4), Some(4)).grid(3).unwrap(), (4, 4));
+        assert!(layout(Some(2), Some(1)).grid(3).is_err());
+    }
+
+    #[test]
+    fn centers_cells_under_their_labels() {
+        let cells = [("a", "ab\ncd\n"), ("b", "xyz\n"), ("c", "q\n")]
+            .map(|(label, text)| (label.to_string(), Arc::new(ConversionOutput::from_art(text))));
+        let collage = compose(&cells, (2, 5), true);
+        // The rows after the last image are dropped.
+        assert_eq!(collage.dimensions, (8, 7));
+        assert_eq!(
+            collage.ascii_art,
+            concat!(" a    b \n", "ab   xyz\n", "cd      \n", "        \n", " c      \n", " q      \n", "        \n")
+        );
+        assert_eq!(collage.brightness.get_pixel(0, 1)[0], 200);
+        assert_eq!(collage.brightness.get_pixel(3, 1)[0], 0);
+        assert_eq!(collage.brightness.get_pixel(1, 0)[0], 0);
+        assert_eq!(collage.options["collage"], serde_json::json!({ "columns": 2, "rows": 2, "images": 3 }));
+
+        let unlabeled = compose(&cells, (3, 1), false);
+        assert_eq!(unlabeled.ascii_art, concat!("ab   xyz   q \n", "cd           \n"));
+    }
+}
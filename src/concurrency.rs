@@ -0,0 +1,129 @@
+use crate::metrics::METRICS;
+use actix_web::{http::header, HttpResponse};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// --- CONVERSION CONCURRENCY ---
+
+/// `Retry-After` for conversions turned away because the backlog is full.
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// Who is waiting for a conversion slot.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Priority {
+    /// A request holding its connection open; refused once the backlog is full.
+    Interactive,
+    /// A background job, already admitted by the job queue; it always waits its turn.
+    Background,
+}
+
+/// The backlog of waiting conversions was full.
+#[derive(Debug)]
+pub struct Overloaded;
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The server is busy with other conversions, try again later")
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+/// Bounds how many conversions run at once across all routes, so a burst of large
+/// uploads queues up instead of every one competing for the CPU and memory.
+pub struct ConversionLimiter {
+    slots: Arc<Semaphore>,
+    /// Interactive conversions allowed to wait for a slot.
+    backlog: usize,
+    waiting: AtomicUsize,
+}
+
+/// A running conversion's slot, given back when dropped.
+pub struct Slot {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        METRICS.conversions_running.dec();
+    }
+}
+
+/// Counts a conversion as waiting until dropped, also when its request is cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+        METRICS.conversions_waiting.dec();
+    }
+}
+
+impl ConversionLimiter {
+    /// `max_running` of zero picks the number of CPUs.
+    pub fn new(max_running: usize, backlog: usize) -> Self {
+        let max_running = match max_running {
+            0 => std::thread::available_parallelism().map_or(1, usize::from),
+            max_running => max_running,
+        };
+        Self { slots: Arc::new(Semaphore::new(max_running)), backlog, waiting: AtomicUsize::new(0) }
+    }
+
+    /// Waits for a free slot. Interactive conversions that would wait behind a full
+    /// backlog fail with [`Overloaded`] instead.
+    pub async fn acquire(&self, priority: Priority) -> Result<Slot, Overloaded> {
+        let permit = match self.slots.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.backlog && priority == Priority::Interactive {
+                    self.waiting.fetch_sub(1, Ordering::SeqCst);
+                    return Err(Overloaded);
+                }
+                METRICS.conversions_waiting.inc();
+                let _waiting = Waiting(&self.waiting);
+                self.slots.clone().acquire_owned().await.expect("the semaphore is never closed")
+            }
+        };
+        METRICS.conversions_running.inc();
+        Ok(Slot { _permit: permit })
+    }
+}
+
+/// `503 Service Unavailable` with `Retry-After`, for conversions turned away by [`ConversionLimiter`].
+pub fn overloaded() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+        .content_type("text/plain; charset=utf-8")
+        .body(format!("{}.\n", Overloaded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn turns_interactive_work_away_beyond_the_backlog() {
+        let limiter = ConversionLimiter::new(1, 0);
+        let running = limiter.acquire(Priority::Interactive).await.unwrap();
+        assert!(limiter.acquire(Priority::Interactive).await.is_err());
+        drop(running);
+        assert!(limiter.acquire(Priority::Interactive).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn background_work_waits_its_turn() {
+        let limiter = Arc::new(ConversionLimiter::new(1, 0));
+        let running = limiter.acquire(Priority::Interactive).await.unwrap();
+        let waiting = actix_web::rt::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::Background).await.is_ok() }
+        });
+        actix_web::rt::task::yield_now().await;
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 1);
+        drop(running);
+        assert!(waiting.await.unwrap());
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+// --- SERVER CONFIGURATION ---
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:8080";
+const DEFAULT_TLS_BIND_ADDRESS: &str = "127.0.0.1:8443";
+/// Owner and group may connect, so a proxy sharing the group can reach the socket.
+const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
+/// Uploads larger than this are spooled to a temporary file instead of being kept in RAM.
+const DEFAULT_SPOOL_THRESHOLD: usize = 4 * 1024 * 1024;
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_CACHE_CAPACITY: usize = 64;
+const DEFAULT_CACHE_TTL_SECS: u64 = 600;
+/// About 2000 by 2000 characters, still drawable by a browser.
+const DEFAULT_MAX_FULL_RESOLUTION_CELLS: u64 = 4_000_000;
+/// Conversions waiting for a slot before further requests get 503.
+const DEFAULT_CONVERSION_BACKLOG: usize = 32;
+const DEFAULT_JOB_WORKERS: usize = 2;
+const DEFAULT_JOB_QUEUE_CAPACITY: usize = 32;
+const DEFAULT_JOB_RETENTION_SECS: u64 = 3600;
+const DEFAULT_ORIGINAL_RETENTION_SECS: u64 = 600;
+const DEFAULT_HISTORY_LENGTH: usize = 10;
+const DEFAULT_HISTORY_RETENTION_SECS: u64 = 86400;
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+const DEFAULT_STATIC_DIR: &str = "static";
+
+/// Runtime settings, read from `ASCII_*` environment variables at startup.
+pub struct ServerConfig {
+    /// Address of the plain HTTP listener.
+    pub bind_address: String,
+    /// Unix socket the plain HTTP listener binds instead of `bind_address`. The per-IP limits
+    /// then need `trust_proxy_headers`.
+    pub unix_socket: Option<PathBuf>,
+    /// Permissions of the socket file, read as octal.
+    pub unix_socket_mode: u32,
+    /// Address of the HTTPS listener, used when a certificate and key are configured.
+    pub tls_bind_address: String,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub spool_threshold: usize,
+    /// Largest uploaded image accepted, larger ones get 413; `0` lifts the cap.
+    pub max_upload_bytes: u64,
+    /// Maximum number of cached conversion results; `0` disables the cache.
+    pub cache_capacity: usize,
+    pub cache_ttl: Duration,
+    /// Conversions running at once; `0` means one per CPU.
+    pub max_conversions: usize,
+    /// Characters full-resolution art is downscaled to fit in; `0` lifts the cap.
+    pub max_full_resolution_cells: u64,
+    pub conversion_backlog: usize,
+    pub job_workers: usize,
+    /// Jobs waiting beyond this many are rejected with 503.
+    pub job_queue_capacity: usize,
+    /// How long finished jobs and their results are kept.
+    pub job_retention: Duration,
+    /// Key job callbacks are signed with; without one, `callback_url` is refused.
+    pub webhook_secret: Option<String>,
+    /// How long uploads are kept for the comparison slider; `0` turns the slider off.
+    pub original_retention: Duration,
+    /// Recent conversions listed per browser session; `0` turns the history off.
+    pub history_length: usize,
+    /// How long those conversions, their uploads and the session cookie are kept.
+    pub history_retention: Duration,
+    /// Conversion requests allowed per client IP and minute; `0` means unlimited.
+    pub rate_limit_per_minute: u32,
+    /// Simultaneous conversions allowed per client IP; `0` means unlimited.
+    pub max_concurrent_per_ip: u32,
+    /// Take the client IP from `X-Forwarded-For` when running behind a proxy.
+    /// Required for the per-IP limits on a Unix socket, whose clients have no address;
+    /// requests without one share a single budget.
+    pub trust_proxy_headers: bool,
+    /// Comma-separated addresses or CIDR ranges of the proxies whose `X-Forwarded-For` is
+    /// believed; loopback when unset.
+    pub trusted_proxies: Option<String>,
+    /// Comma-separated `key[:daily_quota]` entries protecting the `/api` routes.
+    pub api_keys: Option<String>,
+    /// Also require a key on the conversion routes outside `/api`, which the web page uses.
+    pub api_keys_everywhere: bool,
+    /// File with one `key [daily_quota]` entry per line, merged with `api_keys`.
+    pub api_keys_file: Option<PathBuf>,
+    /// JSON file saved conversion recipes are kept in; without one they last until restart.
+    pub recipes_file: Option<PathBuf>,
+    /// Directory the frontend's pages, styles and scripts are served from.
+    pub static_dir: PathBuf,
+    /// Comma-separated origins allowed to call the API routes, or `*`.
+    pub cors_origins: Option<String>,
+    pub cors_methods: Option<String>,
+    pub cors_headers: Option<String>,
+    /// Time in-flight requests, and then background jobs, get to finish on SIGTERM/SIGINT.
+    pub shutdown_grace: Duration,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            bind_address: env_or("ASCII_BIND", DEFAULT_BIND_ADDRESS.to_string()),
+            unix_socket: env_opt("ASCII_UNIX_SOCKET").map(PathBuf::from),
+            unix_socket_mode: env_octal("ASCII_UNIX_SOCKET_MODE", DEFAULT_UNIX_SOCKET_MODE),
+            tls_bind_address: env_or("ASCII_TLS_BIND", DEFAULT_TLS_BIND_ADDRESS.to_string()),
+            tls_cert: env_opt("ASCII_TLS_CERT").map(PathBuf::from),
+            tls_key: env_opt("ASCII_TLS_KEY").map(PathBuf::from),
+            spool_threshold: env_or("ASCII_SPOOL_THRESHOLD", DEFAULT_SPOOL_THRESHOLD),
+            max_upload_bytes: env_or("ASCII_MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES),
+            cache_capacity: env_or("ASCII_CACHE_CAPACITY", DEFAULT_CACHE_CAPACITY),
+            cache_ttl: Duration::from_secs(env_or("ASCII_CACHE_TTL_SECS", DEFAULT_CACHE_TTL_SECS)),
+            max_conversions: env_or("ASCII_MAX_CONVERSIONS", 0),
+            max_full_resolution_cells: env_or("ASCII_MAX_FULL_RESOLUTION_CELLS", DEFAULT_MAX_FULL_RESOLUTION_CELLS),
+            conversion_backlog: env_or("ASCII_CONVERSION_BACKLOG", DEFAULT_CONVERSION_BACKLOG),
+            job_workers: env_or("ASCII_JOB_WORKERS", DEFAULT_JOB_WORKERS),
+            job_queue_capacity: env_or("ASCII_JOB_QUEUE_CAPACITY", DEFAULT_JOB_QUEUE_CAPACITY),
+            job_retention: Duration::from_secs(env_or("ASCII_JOB_RETENTION_SECS", DEFAULT_JOB_RETENTION_SECS)),
+            webhook_secret: env_opt("ASCII_WEBHOOK_SECRET"),
+            original_retention: Duration::from_secs(env_or(
+                "ASCII_ORIGINAL_RETENTION_SECS",
+                DEFAULT_ORIGINAL_RETENTION_SECS,
+            )),
+            history_length: env_or("ASCII_HISTORY_LENGTH", DEFAULT_HISTORY_LENGTH),
+            history_retention: Duration::from_secs(env_or(
+                "ASCII_HISTORY_RETENTION_SECS",
+                DEFAULT_HISTORY_RETENTION_SECS,
+            )),
+            rate_limit_per_minute: env_or("ASCII_RATE_LIMIT_PER_MINUTE", 0),
+            max_concurrent_per_ip: env_or("ASCII_MAX_CONCURRENT_PER_IP", 0),
+            trust_proxy_headers: env_or("ASCII_TRUST_PROXY_HEADERS", false),
+            trusted_proxies: env_opt("ASCII_TRUSTED_PROXIES"),
+            api_keys: env_opt("ASCII_API_KEYS"),
+            api_keys_everywhere: env_or("ASCII_API_KEYS_EVERYWHERE", true),
+            api_keys_file: env_opt("ASCII_API_KEYS_FILE").map(PathBuf::from),
+            recipes_file: env_opt("ASCII_RECIPES_FILE").map(PathBuf::from),
+            static_dir: env_opt("ASCII_STATIC_DIR").map_or_else(|| PathBuf::from(DEFAULT_STATIC_DIR), PathBuf::from),
+            cors_origins: env_opt("ASCII_CORS_ORIGINS"),
+            cors_methods: env_opt("ASCII_CORS_METHODS"),
+            cors_headers: env_opt("ASCII_CORS_HEADERS"),
+            shutdown_grace: Duration::from_secs(env_or("ASCII_SHUTDOWN_GRACE_SECS", DEFAULT_SHUTDOWN_GRACE_SECS)),
+        }
+    }
+}
+
+fn env_opt(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!(variable = name, value, "ignoring invalid configuration value");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn env_octal(name: &str, default: u32) -> u32 {
+    match env_opt(name) {
+        Some(value) => u32::from_str_radix(value.trim(), 8).unwrap_or_else(|_| {
+            tracing::warn!(variable = name, value, "ignoring invalid configuration value");
+            default
+        }),
+        None => default,
+    }
+}
@@ -0,0 +1,53 @@
+use crate::config::ServerConfig;
+use actix_cors::Cors;
+use actix_web::http::header::{self, HeaderName};
+use actix_web::http::Method;
+use actix_web::middleware::Condition;
+
+// --- CROSS-ORIGIN REQUESTS ---
+
+const DEFAULT_METHODS: &str = "GET,POST";
+const DEFAULT_HEADERS: &str = "Authorization,Content-Type,X-API-Key";
+const PREFLIGHT_MAX_AGE_SECS: usize = 3600;
+
+/// CORS middleware for the machine-facing routes (`/api`, `/jobs`). It is a no-op unless
+/// `ASCII_CORS_ORIGINS` lists at least one origin (or `*`); HTML pages never get it.
+pub fn api_cors(config: &ServerConfig) -> Condition<Cors> {
+    let origins: Vec<&str> = config
+        .cors_origins
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .collect();
+
+    let mut cors = Cors::default()
+        .allowed_methods(parse_list::<Method>(config.cors_methods.as_deref().unwrap_or(DEFAULT_METHODS)))
+        .allowed_headers(parse_list::<HeaderName>(config.cors_headers.as_deref().unwrap_or(DEFAULT_HEADERS)))
+        .expose_headers([header::LOCATION, header::RETRY_AFTER, header::CONTENT_DISPOSITION])
+        .max_age(PREFLIGHT_MAX_AGE_SECS);
+    if origins.contains(&"*") {
+        cors = cors.allow_any_origin().send_wildcard();
+    } else {
+        for origin in &origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    Condition::new(!origins.is_empty(), cors)
+}
+
+/// Parses a comma-separated list, dropping (and logging) entries that are not valid.
+fn parse_list<T: std::str::FromStr>(list: &str) -> Vec<T> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| {
+            let parsed = item.parse().ok();
+            if parsed.is_none() {
+                tracing::warn!(value = item, "ignoring invalid CORS list entry");
+            }
+            parsed
+        })
+        .collect()
+}
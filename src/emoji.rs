@@ -0,0 +1,91 @@
+use crate::mapper::{CharMapper, Pixels};
+use crate::quantize;
+use std::sync::LazyLock;
+
+// --- EMOJI MOSAIC ---
+
+/// Emoji and the average color of their glyph, as drawn by the common emoji fonts.
+/// Each is a single code point shown as emoji by default, so one character is one cell.
+const EMOJI: [(char, [u8; 3]); 36] = [
+    ('🟥', [221, 46, 68]),
+    ('🟧', [244, 144, 12]),
+    ('🟨', [253, 203, 88]),
+    ('🟩', [120, 177, 89]),
+    ('🟦', [85, 172, 238]),
+    ('🟪', [170, 142, 214]),
+    ('🟫', [193, 105, 79]),
+    ('⬛', [41, 47, 51]),
+    ('⬜', [230, 231, 232]),
+    ('🔴', [200, 50, 60]),
+    ('🟠', [230, 140, 30]),
+    ('🟡', [240, 200, 80]),
+    ('🟢', [110, 165, 85]),
+    ('🔵', [70, 150, 215]),
+    ('🟣', [160, 130, 200]),
+    ('🟤', [175, 100, 75]),
+    ('⚫', [60, 65, 70]),
+    ('⚪', [210, 212, 214]),
+    ('🌑', [90, 95, 105]),
+    ('🌕', [245, 215, 120]),
+    ('🍫', [110, 60, 40]),
+    ('🌰', [140, 80, 50]),
+    ('🍊', [245, 150, 40]),
+    ('🍋', [245, 220, 70]),
+    ('🍏', [150, 200, 70]),
+    ('🥒', [90, 140, 60]),
+    ('🌲', [50, 110, 60]),
+    ('🍇', [120, 70, 140]),
+    ('🍆', [100, 60, 120]),
+    ('🌸', [245, 180, 200]),
+    ('🍑', [250, 160, 120]),
+    ('🧊', [180, 220, 240]),
+    ('🌊', [40, 110, 180]),
+    ('🐘', [150, 155, 165]),
+    ('🦴', [225, 215, 195]),
+    ('🍒', [170, 20, 40]),
+];
+
+/// Blank cell: an ideographic space, as wide as an emoji in a terminal.
+pub const BLANK: char = '\u{3000}';
+
+/// Bits kept per channel when indexing the lookup table.
+const LUT_BITS: u32 = 5;
+const LUT_LEVELS: usize = 1 << LUT_BITS;
+
+/// Index into [`EMOJI`] of the nearest emoji for every color, at 5 bits per channel,
+/// so the per-cell lookup is a table read instead of a search.
+static LOOKUP: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    let colors: Vec<[u8; 3]> = EMOJI.iter().map(|&(_, color)| color).collect();
+    // Each slot stands for the center of the colors it covers.
+    let level = |index: usize| ((index << (8 - LUT_BITS)) + (1 << (7 - LUT_BITS))) as u8;
+    (0..LUT_LEVELS.pow(3))
+        .map(|slot| {
+            let color = [level(slot / LUT_LEVELS / LUT_LEVELS), level(slot / LUT_LEVELS % LUT_LEVELS), level(slot % LUT_LEVELS)];
+            quantize::nearest(color, &colors) as u8
+        })
+        .collect()
+});
+
+/// The emoji whose color is closest to the (average) cell color.
+pub fn nearest([r, g, b]: [u8; 3]) -> char {
+    let shift = 8 - LUT_BITS;
+    let slot = ((r >> shift) as usize * LUT_LEVELS + (g >> shift) as usize) * LUT_LEVELS + (b >> shift) as usize;
+    EMOJI[LOOKUP[slot] as usize].0
+}
+
+/// Draws every pixel as the emoji nearest to its color.
+pub struct Emoji;
+
+impl CharMapper for Emoji {
+    fn needs_colors(&self) -> bool {
+        true
+    }
+
+    fn blank(&self) -> char {
+        BLANK
+    }
+
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        pixels.rgb.map_or(BLANK, |rgb| nearest(rgb.get_pixel(x, y).0))
+    }
+}
@@ -0,0 +1,74 @@
+use crate::output::AsciiGrid;
+use crate::render;
+use anyhow::{bail, Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbImage};
+use std::time::Duration;
+
+// --- ANIMATED BITMAP EXPORT ---
+
+/// Pixels drawn across all frames; each frame is encoded and dropped before the next is
+/// drawn, so this bounds the time spent rather than the memory.
+const MAX_TOTAL_PIXELS: u64 = 256 * 1024 * 1024;
+
+/// The art of every frame with its delay; a still is a single frame.
+fn frames<'a>(grid: &'a AsciiGrid) -> Vec<(&'a str, Duration)> {
+    if grid.frames.is_empty() {
+        return vec![(grid.art, Duration::ZERO)];
+    }
+    grid.frames.iter().map(|frame| (frame.art.as_str(), frame.delay)).collect()
+}
+
+/// Draws each frame in the grid's colors and font, like the PNG format, one at a time,
+/// refusing animations that would take too long to draw.
+fn rendered_frames<'a>(grid: &'a AsciiGrid) -> impl Iterator<Item = Result<(RgbImage, Duration)>> + 'a {
+    let frames = frames(grid);
+    let count = frames.len() as u64;
+    frames.into_iter().enumerate().map(move |(index, (art, delay))| {
+        let img = render::render_bitmap(art, grid.background_color, grid.text_color, grid.font)?;
+        if index == 0 && u64::from(img.width()) * u64::from(img.height()) * count > MAX_TOTAL_PIXELS {
+            bail!("The animation is too large to render ({} frames of {}x{} pixels)", count, img.width(), img.height());
+        }
+        Ok((img, delay))
+    })
+}
+
+/// A looping GIF of the frames with their original timing.
+pub fn gif(grid: &AsciiGrid) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut body);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in rendered_frames(grid) {
+            let (img, delay) = frame?;
+            let rgba = image::DynamicImage::ImageRgb8(img).into_rgba8();
+            let frame = Frame::from_parts(rgba, 0, 0, Delay::from_saturating_duration(delay));
+            encoder.encode_frame(frame).context("Failed to encode GIF frame")?;
+        }
+    }
+    Ok(body)
+}
+
+/// A looping APNG of the frames; unlike GIF it keeps every color exactly, and viewers
+/// without APNG support show the first frame.
+pub fn apng(grid: &AsciiGrid) -> Result<Vec<u8>> {
+    let count = frames(grid).len() as u32;
+    let mut frames = rendered_frames(grid);
+    let (first, first_delay) = frames.next().expect("there is always a first frame")?;
+    let mut body = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut body, first.width(), first.height());
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(count, 0)?;
+        let mut writer = encoder.write_header()?;
+        for frame in std::iter::once(Ok((first, first_delay))).chain(frames) {
+            let (img, delay) = frame?;
+            let delay_ms = delay.as_millis().min(u128::from(u16::MAX)) as u16;
+            writer.set_frame_delay(delay_ms, 1000)?;
+            writer.write_image_data(img.as_raw()).context("Failed to encode APNG frame")?;
+        }
+        writer.finish()?;
+    }
+    Ok(body)
+}
@@ -0,0 +1,108 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use anyhow::{bail, Result};
+use image::{Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+
+// --- USER-SUPPLIED FONTS ---
+
+/// Fonts larger than this are refused; monospace coding fonts are well below 1 MiB.
+pub const MAX_FONT_BYTES: usize = 16 * 1024 * 1024;
+/// Cell height the PNG renderer draws at, matching the bitmap font's doubled rows.
+pub const CELL_HEIGHT: f32 = 16.0;
+/// Glyphs are measured larger than they are drawn, for steadier density estimates.
+const MEASURE_HEIGHT: f32 = 48.0;
+
+/// A monospace TrueType/OpenType font the art is calibrated for and rendered with.
+#[derive(Clone)]
+pub struct UserFont {
+    font: Arc<FontVec>,
+    /// Short content digest, which stands in for the font in cache keys and logs.
+    tag: String,
+}
+
+impl fmt::Debug for UserFont {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UserFont({})", self.tag)
+    }
+}
+
+impl UserFont {
+    /// Parses face `index` of a TTF/OTF (or collection), refusing proportional fonts.
+    pub fn from_bytes(data: Vec<u8>, index: u32) -> Result<Self> {
+        if data.len() > MAX_FONT_BYTES {
+            bail!("The font is larger than {} MiB", MAX_FONT_BYTES / 1024 / 1024);
+        }
+        let digest = Sha256::digest(&data);
+        let tag = digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+        let Ok(font) = FontVec::try_from_vec_and_index(data, index) else { bail!("Not a TrueType or OpenType font") };
+
+        let advances: Vec<f32> = ['i', 'M', 'W', '.'].iter().map(|&ch| font.h_advance_unscaled(font.glyph_id(ch))).collect();
+        let widest = advances.iter().copied().fold(0.0, f32::max);
+        if widest <= 0.0 || advances.iter().any(|&advance| (widest - advance) / widest > 0.02) {
+            bail!("The font is not monospaced");
+        }
+        Ok(Self { font: Arc::new(font), tag })
+    }
+
+    /// The raw font file, for embedding.
+    pub fn data(&self) -> &[u8] {
+        self.font.as_slice()
+    }
+
+    /// Whether the font has a glyph for `ch`.
+    pub fn covers(&self, ch: char) -> bool {
+        ch == ' ' || self.font.glyph_id(ch).0 != 0
+    }
+
+    /// Width over height of a character cell; the converter squeezes rows by this.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.font.h_advance_unscaled(self.font.glyph_id('M')) / self.font.height_unscaled()
+    }
+
+    /// Width and height in pixels of a cell at [`CELL_HEIGHT`].
+    pub fn cell_size(&self) -> (u32, u32) {
+        let font = self.font.as_scaled(PxScale::from(CELL_HEIGHT));
+        (font.h_advance(font.glyph_id('M')).round().max(1.0) as u32, CELL_HEIGHT as u32)
+    }
+
+    /// Distance from the top of a cell to the baseline at [`CELL_HEIGHT`].
+    pub fn ascent(&self) -> f32 {
+        self.font.as_scaled(PxScale::from(CELL_HEIGHT)).ascent()
+    }
+
+    /// Share of the cell covered by ink, or `None` when the font lacks the glyph.
+    pub fn density(&self, ch: char) -> Option<f32> {
+        if !self.covers(ch) {
+            return None;
+        }
+        let font = self.font.as_scaled(PxScale::from(MEASURE_HEIGHT));
+        let glyph = font.scaled_glyph(ch);
+        let area = font.h_advance(glyph.id) * font.height();
+        let mut ink = 0.0;
+        if let Some(outline) = font.outline_glyph(glyph) {
+            outline.draw(|_, _, coverage| ink += coverage);
+        }
+        Some(ink / area.max(1.0))
+    }
+
+    /// Draws `ch` into the cell whose top-left corner is (`x`, `y`), blending by coverage.
+    pub fn draw(&self, img: &mut RgbImage, ch: char, x: u32, y: u32, color: Rgb<u8>) {
+        let font = self.font.as_scaled(PxScale::from(CELL_HEIGHT));
+        let mut glyph = font.scaled_glyph(ch);
+        glyph.position = point(x as f32, y as f32 + font.ascent());
+        let Some(outline) = font.outline_glyph(glyph) else { return };
+        let bounds = outline.px_bounds();
+        outline.draw(|dx, dy, coverage| {
+            let (px, py) = (bounds.min.x + dx as f32, bounds.min.y + dy as f32);
+            if px < 0.0 || py < 0.0 {
+                return;
+            }
+            let Some(pixel) = img.get_pixel_mut_checked(px as u32, py as u32) else { return };
+            for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+                *channel = (*channel as f32 + (target as f32 - *channel as f32) * coverage.min(1.0)).round() as u8;
+            }
+        });
+    }
+}
@@ -0,0 +1,463 @@
+use crate::charset;
+use crate::collage::{self, CollageLayout};
+use crate::config::ServerConfig;
+use crate::font::{self, UserFont};
+use crate::mapper::Threshold;
+use crate::overlay::{self, CaptionStyle, Corner};
+use crate::preprocess::{self, Crop, Flip, Grayscale, LocalThreshold};
+use crate::progress::ProgressRegistry;
+use crate::recipe::{Fields, RecipeStore};
+use crate::render;
+use crate::sauce::Sauce;
+use crate::spool::UploadBody;
+use crate::svg;
+use crate::webhook;
+use crate::{ColorTheme, ConversionOptions, Mode, Transparency};
+use actix_multipart::{Field, Multipart};
+use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge};
+use actix_web::Error;
+use futures_util::stream::StreamExt;
+use image::Rgb;
+use sanitize_filename::sanitize;
+use utoipa::ToSchema;
+
+// --- UPLOAD FORM PARSING ---
+
+/// Largest value a text field may carry; even a recipe fits in a fraction of it.
+const MAX_TEXT_FIELD_BYTES: usize = 16 * 1024;
+
+/// The fields of a conversion form submission.
+pub struct UploadForm {
+    pub image: Option<UploadBody>,
+    pub filename: String,
+    /// Further `image` fields with their filenames; with any, the upload is a collage.
+    pub more_images: Vec<(String, UploadBody)>,
+    pub collage: CollageLayout,
+    pub options: ConversionOptions,
+    pub job_id: Option<String>,
+    /// Where `/jobs` reports the outcome.
+    pub callback_url: Option<String>,
+    /// `page=all`: convert every page of a multi-page TIFF.
+    pub all_pages: bool,
+    /// Metadata for `.ans` exports; set when any `sauce_*` field was submitted.
+    pub sauce: Option<Sauce>,
+    /// `/upload` only: a conversion from the session's history whose upload to convert again.
+    pub rerun: Option<String>,
+}
+
+/// The multipart fields as documented in the OpenAPI specification; [`UploadForm::read`]
+/// is the actual parser.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadFields {
+    /// The image file. Send up to 16 to compose their art into a collage, in the order sent.
+    #[schema(value_type = String, format = Binary)]
+    image: Vec<u8>,
+    /// `dark` (default) or `light`.
+    #[schema(example = "dark")]
+    theme: Option<String>,
+    /// `ascii` (default), `dithered`, `edges`, `glyph`, `braille`, `emoji` for a mosaic
+    /// of the emoji nearest to each cell's color, `quadrant` for 2x2 block elements, or
+    /// `stencil` for the two ends of the character set.
+    #[schema(example = "emoji")]
+    mode: Option<String>,
+    /// Brightness (0-255) above which `stencil` mode inks a pixel (below, with the light
+    /// theme), or `otsu` (default) to pick it from the image's histogram.
+    #[schema(example = "otsu")]
+    threshold: Option<String>,
+    /// `true` error-diffuses `stencil` mode into a halftone.
+    dither: Option<bool>,
+    /// Even out uneven lighting before mapping by comparing each pixel with the block
+    /// around it: `mean` or `sauvola`. Off by default.
+    #[schema(example = "sauvola")]
+    adaptive: Option<String>,
+    /// Side of the `adaptive` block in pixels of the resized image (3-255, default 31).
+    adaptive_block: Option<u32>,
+    /// Brightness added after the local threshold is taken out; default 0.
+    adaptive_offset: Option<i16>,
+    /// `true` turns the image into line art with an extended difference-of-Gaussians
+    /// filter before mapping; combine with `mode=edges` for sketches.
+    line_art: Option<bool>,
+    /// Blur radius of the line art filter in pixels of the resized image (0.3-10,
+    /// default 1); larger values draw bolder, simpler lines.
+    line_sigma: Option<f32>,
+    /// Brightness levels (0-255, default 2) an edge must be darker than its surroundings
+    /// to be drawn as a line; higher values keep only strong edges.
+    line_threshold: Option<u8>,
+    /// `true` selects the detailed character set.
+    detailed: Option<bool>,
+    /// How colors become brightness: `luma` (default, ITU-R BT.709), `average`,
+    /// `lightness`, or a single channel, `red`, `green` or `blue`.
+    #[schema(example = "red")]
+    grayscale: Option<String>,
+    /// Name of a built-in character set from `GET /api/charsets`, such as `blocks` or `braille`.
+    #[schema(example = "blocks")]
+    preset: Option<String>,
+    /// Custom characters to draw with, replacing the built-in sets; sorted from least to
+    /// most ink unless `sort_charset` is `false`.
+    #[schema(example = " .oO@")]
+    charset: Option<String>,
+    /// `false` keeps the custom characters in the order given.
+    sort_charset: Option<bool>,
+    /// A monospace TTF/OTF font to calibrate the aspect ratio and custom character order
+    /// for, and to render PNG and SVG output with.
+    #[schema(value_type = Option<String>, format = Binary)]
+    font: Option<Vec<u8>>,
+    /// Family name of an installed monospace font to use instead of uploading one.
+    #[schema(example = "DejaVu Sans Mono")]
+    font_family: Option<String>,
+    /// `true` skips resizing to the default width.
+    full_resolution: Option<bool>,
+    /// `true` ignores the EXIF orientation tag instead of turning photos upright.
+    ignore_exif: Option<bool>,
+    /// `background` (default) composites translucent pixels over the background color;
+    /// `blank` renders pixels below `alpha_threshold` as spaces.
+    #[schema(example = "background")]
+    transparency: Option<String>,
+    /// Alpha (0-255) below which a pixel counts as transparent; default 128.
+    alpha_threshold: Option<u8>,
+    /// `true` converts only the first frame of an animated GIF, APNG or WebP.
+    first_frame_only: Option<bool>,
+    /// Clockwise rotation: `90`, `180` or `270`.
+    #[schema(example = "90")]
+    rotate: Option<String>,
+    /// Mirror the image: `h` (horizontally), `v` (vertically) or `hv` (both).
+    #[schema(example = "h")]
+    flip: Option<String>,
+    /// Background color as `#rrggbb`, replacing the theme's.
+    #[schema(example = "#202040")]
+    background_color: Option<String>,
+    /// Text color as `#rrggbb`, replacing the theme's.
+    #[schema(example = "#ffcc00")]
+    text_color: Option<String>,
+    /// Page of a multi-page TIFF, counting from 1; `all` converts every page (only on `/upload`).
+    #[schema(example = "1")]
+    page: Option<String>,
+    /// Left edge of the region to convert, in pixels of the upright image; default 0.
+    crop_x: Option<u32>,
+    /// Top edge of the region to convert; default 0.
+    crop_y: Option<u32>,
+    /// Width of the region to convert; cropping needs both `crop_w` and `crop_h`.
+    crop_w: Option<u32>,
+    /// Height of the region to convert.
+    crop_h: Option<u32>,
+    /// Title recorded in the SAUCE record of `.ans` exports (up to 35 characters).
+    sauce_title: Option<String>,
+    /// Author for the SAUCE record (up to 20 characters).
+    sauce_author: Option<String>,
+    /// Group for the SAUCE record (up to 20 characters).
+    sauce_group: Option<String>,
+    /// Line of text written above the art (up to 60 characters), widening it if needed.
+    #[schema(example = "Sunset by Ada")]
+    caption: Option<String>,
+    /// `plain` (default) writes the caption as typed; `banner` draws it in large letters.
+    #[schema(example = "banner")]
+    caption_style: Option<String>,
+    /// `top` (default) or `bottom`.
+    caption_position: Option<String>,
+    /// Text written over a corner of the art (up to 40 characters).
+    #[schema(example = "@ada")]
+    watermark: Option<String>,
+    /// `bottom-right` (default), `bottom-left`, `top-right` or `top-left`.
+    watermark_corner: Option<String>,
+    /// Columns of a collage; by default as many as make it roughly square.
+    collage_columns: Option<u32>,
+    /// Rows of a collage; with `collage_columns` as well, there must be room for every image.
+    collage_rows: Option<u32>,
+    /// `false` leaves out the label above each image of a collage.
+    collage_labels: Option<bool>,
+    /// Label of a collage cell, replacing the filename; repeat the field once per image, in order.
+    #[schema(example = "before")]
+    collage_label: Option<String>,
+    /// Name of a saved recipe whose fields apply before the ones sent with it.
+    #[schema(example = "poster-bw")]
+    recipe: Option<String>,
+    /// Client-chosen ID (letters, digits and dashes) for following progress at `/progress/{job_id}`.
+    job_id: Option<String>,
+    /// `/jobs` only: an `http` or `https` URL that gets a signed JSON POST when the job
+    /// finishes or fails.
+    #[schema(example = "https://example.com/hooks/ascii")]
+    callback_url: Option<String>,
+    /// `/upload` only: ID of a conversion in the browser session's history whose images
+    /// are converted again, with these options, when no `image` is sent.
+    rerun: Option<String>,
+}
+
+impl UploadForm {
+    fn new() -> Self {
+        UploadForm {
+            image: None,
+            filename: "image".to_string(),
+            more_images: Vec::new(),
+            collage: CollageLayout::default(),
+            options: ConversionOptions::default(),
+            job_id: None,
+            callback_url: None,
+            all_pages: false,
+            sauce: None,
+            rerun: None,
+        }
+    }
+
+    /// Reads the submission. The fields of a `recipe` are applied first, so the fields
+    /// sent along with it override the recipe's.
+    pub async fn read(payload: &mut Multipart, config: &ServerConfig, recipes: &RecipeStore) -> Result<Self, Error> {
+        let mut form = UploadForm::new();
+        form.options.max_cells = Some(config.max_full_resolution_cells).filter(|&cells| cells > 0);
+        let mut fields = Vec::new();
+        let mut recipe = None;
+        let mut font = None;
+
+        while let Some(item) = payload.next().await {
+            let mut field = item?;
+            let content_disposition = field.content_disposition();
+            let field_name = content_disposition.get_name().unwrap_or_default().to_string();
+
+            match field_name.as_str() {
+                "image" => {
+                    let filename = sanitize(content_disposition.get_filename().unwrap_or("image.png"));
+                    let Some(body) = UploadBody::read_field(&mut field, config.spool_threshold, config.max_upload_bytes).await? else {
+                        continue;
+                    };
+                    if form.image.is_none() {
+                        (form.filename, form.image) = (filename, Some(body));
+                    } else if form.more_images.len() + 1 < collage::MAX_IMAGES {
+                        form.more_images.push((filename, body));
+                    } else {
+                        return Err(ErrorBadRequest(format!("A collage takes at most {} images.", collage::MAX_IMAGES)));
+                    }
+                }
+                "font" => {
+                    let data = read_bytes(&mut field, font::MAX_FONT_BYTES).await?;
+                    if !data.is_empty() {
+                        font = Some(parse_font(data, 0)?);
+                    }
+                }
+                "recipe" => recipe = Some(read_text(&mut field).await?),
+                _ => fields.push((field_name, read_text(&mut field).await?)),
+            }
+        }
+
+        // x, y, width and height; a crop needs at least the width and height.
+        let mut crop = [None; 4];
+        if let Some(name) = recipe.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+            let Some(saved) = recipes.get(name) else {
+                return Err(ErrorBadRequest(format!("Unknown recipe {:?}.", name)));
+            };
+            for (name, value) in saved {
+                form.apply(&mut crop, &name, value)?;
+            }
+        }
+        for (name, value) in fields {
+            form.apply(&mut crop, &name, value)?;
+        }
+        if font.is_some() {
+            form.options.font = font;
+        }
+        if let [x, y, Some(width), Some(height)] = crop {
+            form.options.crop = Some(Crop { x: x.unwrap_or(0), y: y.unwrap_or(0), width, height });
+        }
+        Ok(form)
+    }
+
+    /// `first`, the form's image, and the further images of a collage, with their filenames.
+    pub fn take_images(&mut self, first: UploadBody) -> Vec<(String, UploadBody)> {
+        std::iter::once((self.filename.clone(), first)).chain(self.more_images.drain(..)).collect()
+    }
+
+    /// Checks that every field of a recipe is one `read` applies, with a valid value.
+    pub fn check_recipe(fields: &Fields) -> Result<(), String> {
+        let mut form = UploadForm::new();
+        let mut crop = [None; 4];
+        for (name, value) in fields {
+            // Per-request fields make no sense to save.
+            let known = !matches!(name.as_str(), "job_id" | "callback_url" | "collage_label" | "rerun") && form.apply(&mut crop, name, value.clone()).map_err(|err| err.to_string())?;
+            if !known {
+                return Err(format!("{:?} cannot be part of a recipe.", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies one text field; returns `false` for names it does not know.
+    fn apply(&mut self, crop: &mut [Option<u32>; 4], name: &str, value: String) -> Result<bool, Error> {
+        let options = &mut self.options;
+        match name {
+            "theme" => options.theme = ColorTheme::from_name(&value),
+            "mode" => options.mode = Mode::from_name(&value),
+            "detailed" => options.detailed = value == "true",
+            "grayscale" => options.grayscale = Grayscale::from_name(&value),
+            "threshold" => {
+                let Some(threshold) = Threshold::parse(&value) else {
+                    return Err(ErrorBadRequest(format!("Invalid threshold {:?}; expected 0-255 or otsu.", value.trim())));
+                };
+                options.threshold = threshold;
+            }
+            "dither" => options.dither = value == "true",
+            "adaptive" => options.local_threshold = LocalThreshold::from_name(&value),
+            "adaptive_block" => options.adaptive_block = value.trim().parse().ok(),
+            "adaptive_offset" => options.adaptive_offset = value.trim().parse().ok(),
+            "line_art" => options.line_art = value == "true",
+            "line_sigma" => options.line_sigma = value.trim().parse().ok().filter(|sigma: &f32| sigma.is_finite()),
+            "line_threshold" => options.line_threshold = value.trim().parse().ok(),
+            "preset" => {
+                if !value.trim().is_empty() {
+                    let Some(preset) = charset::preset(&value) else {
+                        return Err(ErrorBadRequest(format!("Unknown character set preset {:?}.", value.trim())));
+                    };
+                    options.preset = Some(preset);
+                }
+            }
+            "charset" => options.charset = charset::parse(&value),
+            "sort_charset" => options.keep_charset_order = value == "false",
+            "font_family" => {
+                if !value.trim().is_empty() {
+                    let Some((data, index)) = svg::system_font(value.trim()) else {
+                        return Err(ErrorBadRequest(format!("No installed font named {:?}.", value.trim())));
+                    };
+                    options.font = Some(parse_font(data, index)?);
+                }
+            }
+            "full_resolution" => options.full_resolution = value == "true",
+            "ignore_exif" => options.ignore_exif = value == "true",
+            "transparency" => options.transparency = Transparency::from_name(&value),
+            "alpha_threshold" => options.alpha_threshold = value.trim().parse().ok(),
+            "page" => match value.trim() {
+                "all" => self.all_pages = true,
+                page => options.page = page.parse::<usize>().map_or(0, |page| page.saturating_sub(1)),
+            },
+            "first_frame_only" => options.first_frame_only = value == "true",
+            "rotate" => options.rotate = preprocess::parse_rotation(&value),
+            "flip" => options.flip = Flip::from_name(&value),
+            "background_color" => options.background_color = parse_color(&value, "background")?,
+            "text_color" => options.text_color = parse_color(&value, "text")?,
+            "crop_x" => crop[0] = value.trim().parse().ok(),
+            "crop_y" => crop[1] = value.trim().parse().ok(),
+            "crop_w" => crop[2] = value.trim().parse().ok(),
+            "crop_h" => crop[3] = value.trim().parse().ok(),
+            "sauce_title" => self.sauce.get_or_insert_with(Sauce::default).title = value,
+            "sauce_author" => self.sauce.get_or_insert_with(Sauce::default).author = value,
+            "sauce_group" => self.sauce.get_or_insert_with(Sauce::default).group = value,
+            "caption" => options.overlay.caption = parse_overlay_text(&value, "caption", overlay::MAX_CAPTION_CHARS)?,
+            "caption_style" => options.overlay.caption_style = CaptionStyle::from_name(&value),
+            "caption_position" => options.overlay.caption_below = value.trim() == "bottom",
+            "watermark" => options.overlay.watermark = parse_overlay_text(&value, "watermark", overlay::MAX_WATERMARK_CHARS)?,
+            "watermark_corner" => options.overlay.watermark_corner = Corner::from_name(&value),
+            "collage_columns" => self.collage.columns = parse_collage_size(&value),
+            "collage_rows" => self.collage.rows = parse_collage_size(&value),
+            "collage_labels" => self.collage.labels = value != "false",
+            "collage_label" => self.collage.names.push(value.trim().to_string()),
+            "job_id" => {
+                if ProgressRegistry::is_valid_id(&value) {
+                    self.job_id = Some(value);
+                }
+            }
+            "rerun" => self.rerun = Some(value.trim().to_string()).filter(|id| !id.is_empty()),
+            "callback_url" => {
+                let url = value.trim();
+                if !url.is_empty() {
+                    if !webhook::is_valid_url(url) {
+                        return Err(ErrorBadRequest(format!("Invalid callback URL {:?}; expected http:// or https://.", url)));
+                    }
+                    self.callback_url = Some(url.to_string());
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// One line of caption or watermark text; empty values leave it out.
+fn parse_overlay_text(value: &str, name: &str, max_chars: usize) -> Result<Option<String>, Error> {
+    let text = value.trim();
+    if text.chars().any(char::is_control) {
+        return Err(ErrorBadRequest(format!("The {} must be a single line of text.", name)));
+    }
+    if text.chars().count() > max_chars {
+        return Err(ErrorBadRequest(format!("The {} is longer than {} characters.", name, max_chars)));
+    }
+    Ok((!text.is_empty()).then(|| text.to_string()))
+}
+
+/// Columns or rows of a collage, 1 up to the number of images it can hold.
+fn parse_collage_size(value: &str) -> Option<u32> {
+    value.trim().parse().ok().map(|cells: u32| cells.clamp(1, collage::MAX_IMAGES as u32))
+}
+
+/// An empty value keeps the theme color; anything but `#rrggbb` is rejected.
+fn parse_color(value: &str, name: &str) -> Result<Option<Rgb<u8>>, Error> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    render::parse_hex_color(value.trim())
+        .map(Some)
+        .map_err(|_| ErrorBadRequest(format!("Invalid {} color {:?}; expected #rrggbb.", name, value.trim())))
+}
+
+fn parse_font(data: Vec<u8>, index: u32) -> Result<UserFont, Error> {
+    UserFont::from_bytes(data, index).map_err(|err| ErrorBadRequest(format!("Unusable font: {:#}.", err)))
+}
+
+async fn read_bytes(field: &mut Field, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > limit {
+            return Err(ErrorPayloadTooLarge(format!("The font is larger than {} MiB.", limit / 1024 / 1024)));
+        }
+    }
+    Ok(data)
+}
+
+async fn read_text(field: &mut Field) -> Result<String, Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > MAX_TEXT_FIELD_BYTES {
+            return Err(ErrorPayloadTooLarge(format!("A text field is larger than {} KiB.", MAX_TEXT_FIELD_BYTES / 1024)));
+        }
+    }
+    Ok(String::from_utf8(data).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::PayloadError;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::http::StatusCode;
+    use actix_web::web::Bytes;
+
+    fn multipart(name: &str, value: &[u8]) -> Multipart {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("multipart/form-data; boundary=xyz"));
+        let mut body = format!("--xyz\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"a.png\"\r\n\r\n")
+            .into_bytes();
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n--xyz--\r\n");
+        Multipart::new(&headers, futures_util::stream::iter([Ok::<_, PayloadError>(Bytes::from(body))]))
+    }
+
+    async fn read(name: &str, value: &[u8], max_upload_bytes: u64) -> Result<UploadForm, Error> {
+        let mut config = ServerConfig::from_env();
+        config.max_upload_bytes = max_upload_bytes;
+        UploadForm::read(&mut multipart(name, value), &config, &RecipeStore::open(None).unwrap()).await
+    }
+
+    #[actix_web::test]
+    async fn refuses_oversized_images() {
+        let image = vec![7; 4096];
+        let err = read("image", &image, 4095).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(read("image", &image, 4096).await.unwrap().image.unwrap().len(), 4096);
+        assert!(read("image", &image, 0).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn refuses_oversized_text_fields() {
+        let err = read("caption", &[b'a'; MAX_TEXT_FIELD_BYTES + 1], 0).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(read("charset", &[b'a'; 64], 0).await.is_ok());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::jobs::JobQueue;
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::BTreeMap;
+
+// --- HEALTH CHECKS ---
+
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    /// `ok` or `unavailable`.
+    #[schema(value_type = String)]
+    status: &'static str,
+    /// `ok` or the failure reason, per check.
+    #[schema(value_type = BTreeMap<String, String>)]
+    checks: BTreeMap<&'static str, String>,
+}
+
+/// Liveness: the process is up and serving requests.
+#[utoipa::path(tag = "health", responses((status = 200, description = "The process is up", body = String)))]
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body("ok")
+}
+
+/// Readiness: the spool directory is writable and the job queue can accept work.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to accept work", body = Readiness),
+        (status = 503, description = "A check failed", body = Readiness),
+    ),
+)]
+#[get("/readyz")]
+async fn readyz(queue: web::Data<JobQueue>) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+
+    let storage = web::block(|| tempfile::tempfile().map(drop)).await;
+    let storage = match storage {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(format!("temp directory not writable: {}", err)),
+        Err(err) => Err(err.to_string()),
+    };
+    let job_queue = queue.health().map_err(str::to_string);
+
+    let mut ready = true;
+    for (name, result) in [("storage", storage), ("job_queue", job_queue)] {
+        ready &= result.is_ok();
+        checks.insert(name, result.err().unwrap_or_else(|| "ok".to_string()));
+    }
+
+    let body = Readiness { status: if ready { "ok" } else { "unavailable" }, checks };
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
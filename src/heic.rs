@@ -0,0 +1,36 @@
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+// --- HEIC DECODING ---
+
+/// Major brands of HEVC-coded HEIF files, as written by iPhones and most cameras.
+const HEIC_BRANDS: [&[u8; 4]; 6] = [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis"];
+
+/// Recognizes the `ftyp` box of a HEIC file.
+pub fn is_heic(head: &[u8]) -> bool {
+    head.len() >= 12 && &head[4..8] == b"ftyp" && HEIC_BRANDS.iter().any(|brand| &head[8..12] == *brand)
+}
+
+fn decoding_error(err: libheif_rs::HeifError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("HEIC".to_string()), err))
+}
+
+/// Decodes the primary image. libheif applies the container's rotation and mirroring
+/// itself, so the EXIF orientation must not be applied again.
+pub fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    let context = HeifContext::read_from_bytes(data).map_err(decoding_error)?;
+    let handle = context.primary_image_handle().map_err(decoding_error)?;
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(decoding_error)?;
+
+    let plane = image.planes().interleaved.expect("RGBA images are interleaved");
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    let img = RgbaImage::from_raw(plane.width, plane.height, pixels).expect("plane size matches");
+    Ok(DynamicImage::ImageRgba8(img))
+}
@@ -0,0 +1,316 @@
+use crate::cache::ConversionOutput;
+use crate::concurrency::ConversionLimiter;
+use crate::jobs::{self, ResultQuery};
+use crate::output;
+use crate::spool::UploadBody;
+use crate::tile::TileQuery;
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::dev::Payload;
+use actix_web::{delete, get, http::header, web, FromRequest, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::{ready, Ready};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// --- CONVERSION HISTORY ---
+
+/// Cookie holding the ID of the browser session a history belongs to.
+const SESSION_COOKIE: &str = "ascii_session";
+/// Conversions kept across all sessions; the oldest is dropped to make room for another.
+const MAX_ENTRIES: usize = 64;
+
+/// The images of one upload, shared by the history entries of its pages.
+type Uploads = Arc<Vec<(String, UploadBody)>>;
+
+/// The browser session a request belongs to: the ID in its cookie, or a new one.
+pub struct Session {
+    id: String,
+    /// The request came over HTTPS, so the cookie may be limited to it.
+    secure: bool,
+}
+
+impl FromRequest for Session {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req
+            .cookie(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+            .filter(|id| uuid::Uuid::parse_str(id).is_ok());
+        let secure = req.connection_info().scheme() == "https";
+        ready(Ok(Session { id: id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()), secure }))
+    }
+}
+
+struct Entry {
+    id: String,
+    name: String,
+    created: SystemTime,
+    stored: Instant,
+    output: Arc<ConversionOutput>,
+    /// What was uploaded, for converting it again with other options.
+    uploads: Option<Uploads>,
+}
+
+/// One conversion as `GET /history` lists it.
+#[derive(Serialize)]
+struct HistoryEntry {
+    id: String,
+    name: String,
+    /// Seconds since the Unix epoch.
+    created: u64,
+    columns: u32,
+    rows: u32,
+    /// The options that shaped the art.
+    options: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notice: Option<String>,
+    /// Formats the result can be downloaded in from `/history/{id}?format=`.
+    formats: Vec<&'static str>,
+    /// Whether the upload is still kept for `rerun`.
+    rerun: bool,
+}
+
+#[derive(Serialize)]
+struct HistoryList {
+    enabled: bool,
+    entries: Vec<HistoryEntry>,
+}
+
+/// The recent `/upload` results of each browser session, newest first, together with
+/// their uploads in temporary files.
+pub struct History {
+    sessions: Mutex<HashMap<String, VecDeque<Entry>>>,
+    /// Conversions kept per session; `0` turns the history off.
+    length: usize,
+    retention: Duration,
+}
+
+impl History {
+    pub fn new(length: usize, retention: Duration) -> Self {
+        Self { sessions: Mutex::new(HashMap::new()), length, retention }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.length > 0 && !self.retention.is_zero()
+    }
+
+    /// Moves the images of an upload to temporary files, so they can be kept without
+    /// holding them in memory. Blocks on the file writes.
+    pub fn keep_uploads(images: Vec<(String, UploadBody)>) -> Option<Uploads> {
+        let kept = images
+            .into_iter()
+            .map(|(filename, body)| Ok((filename, body.into_spooled()?)))
+            .collect::<std::io::Result<Vec<_>>>();
+        match kept {
+            Ok(kept) => Some(Arc::new(kept)),
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to keep upload for the history");
+                None
+            }
+        }
+    }
+
+    /// Adds a result to the front of the session's history.
+    pub fn record(&self, session: &Session, name: String, output: Arc<ConversionOutput>, uploads: Option<Uploads>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        for entries in sessions.values_mut() {
+            entries.retain(|entry| entry.stored.elapsed() < self.retention);
+        }
+        sessions.retain(|_, entries| !entries.is_empty());
+        let entries = sessions.entry(session.id.clone()).or_default();
+        entries.truncate(self.length - 1);
+        entries.push_front(Entry {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            created: SystemTime::now(),
+            stored: Instant::now(),
+            output,
+            uploads,
+        });
+
+        while sessions.values().map(VecDeque::len).sum::<usize>() > MAX_ENTRIES {
+            let oldest = sessions
+                .iter()
+                .filter_map(|(id, entries)| Some((id, entries.back()?.stored)))
+                .min_by_key(|(_, stored)| *stored)
+                .map(|(id, _)| id.clone());
+            let Some(oldest) = oldest else { break };
+            if let Some(entries) = sessions.get_mut(&oldest) {
+                entries.pop_back();
+                if entries.is_empty() {
+                    sessions.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// The uploads of one of the session's conversions, to convert them again.
+    pub fn uploads(&self, session: &Session, id: &str) -> Option<Vec<(String, UploadBody)>> {
+        let sessions = self.sessions.lock().unwrap();
+        let uploads = self.entry(&sessions, session, id)?.uploads.as_ref()?;
+        Some(uploads.to_vec())
+    }
+
+    fn entry<'a>(&self, sessions: &'a HashMap<String, VecDeque<Entry>>, session: &Session, id: &str) -> Option<&'a Entry> {
+        sessions.get(&session.id)?.iter().find(|entry| entry.id == id && entry.stored.elapsed() < self.retention)
+    }
+
+    fn list(&self, session: &Session) -> Vec<HistoryEntry> {
+        let sessions = self.sessions.lock().unwrap();
+        let Some(entries) = sessions.get(&session.id) else { return Vec::new() };
+        entries
+            .iter()
+            .filter(|entry| entry.stored.elapsed() < self.retention)
+            .map(|entry| HistoryEntry {
+                id: entry.id.clone(),
+                name: entry.name.clone(),
+                created: entry.created.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+                columns: entry.output.dimensions.0,
+                rows: entry.output.dimensions.1,
+                options: entry.output.options.clone(),
+                notice: entry.output.notice.clone(),
+                formats: output::renderers()
+                    .filter(|renderer| renderer.supports(entry.output.mode))
+                    .map(|renderer| renderer.name())
+                    .collect(),
+                rerun: entry.uploads.is_some(),
+            })
+            .collect()
+    }
+
+    /// Sets the session cookie, or renews it, for as long as the history is kept.
+    pub fn cookie(&self, session: &Session) -> Cookie<'static> {
+        Cookie::build(SESSION_COOKIE, session.id.clone())
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(session.secure)
+            .max_age(time::Duration::seconds(self.retention.as_secs().try_into().unwrap_or(i64::MAX)))
+            .finish()
+    }
+}
+
+/// The session's recent conversions, newest first.
+#[get("/history")]
+async fn list_history(session: Session, history: web::Data<History>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        .json(HistoryList { enabled: history.is_enabled(), entries: history.list(&session) })
+}
+
+/// One of the session's conversions in the requested format, like `/jobs/{id}/result`.
+#[get("/history/{id}")]
+async fn history_result(
+    req: HttpRequest,
+    session: Session,
+    id: web::Path<String>,
+    query: web::Query<ResultQuery>,
+    tiles: web::Query<TileQuery>,
+    history: web::Data<History>,
+    limiter: web::Data<ConversionLimiter>,
+) -> HttpResponse {
+    let found = {
+        let sessions = history.sessions.lock().unwrap();
+        history.entry(&sessions, &session, &id).map(|entry| (entry.output.clone(), entry.name.clone()))
+    };
+    let Some((output, name)) = found else {
+        return HttpResponse::NotFound().body("Unknown or expired conversion.");
+    };
+    jobs::render_result(&req, output, &name, query.format.as_deref(), &tiles, &id, history.retention, &limiter).await
+}
+
+/// Forgets the session's conversions and their uploads.
+#[delete("/history")]
+async fn clear_history(session: Session, history: web::Data<History>) -> HttpResponse {
+    history.sessions.lock().unwrap().remove(&session.id);
+    HttpResponse::NoContent().finish()
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_history).service(history_result).service(clear_history);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str) -> Session {
+        Session { id: id.to_string(), secure: false }
+    }
+
+    fn record(history: &History, session: &Session, name: &str) {
+        history.record(session, name.to_string(), Arc::new(ConversionOutput::from_art("x\n")), None);
+    }
+
+    fn names(history: &History, session: &Session) -> Vec<String> {
+        history.list(session).into_iter().map(|entry| entry.name).collect()
+    }
+
+    /// Makes the session's entries, or just the one with `id`, look `age` older.
+    fn backdate(history: &History, session: &Session, id: Option<&str>, age: Duration) {
+        let mut sessions = history.sessions.lock().unwrap();
+        for entry in sessions.get_mut(&session.id).unwrap() {
+            if id.is_none_or(|id| entry.id == id) {
+                entry.stored -= age;
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_entries_per_session() {
+        let history = History::new(2, Duration::from_secs(60));
+        let (alice, bob) = (session("a"), session("b"));
+        for name in ["one", "two", "three"] {
+            record(&history, &alice, name);
+        }
+        record(&history, &bob, "other");
+        assert_eq!(names(&history, &alice), ["three", "two"]);
+        assert_eq!(names(&history, &bob), ["other"]);
+    }
+
+    #[test]
+    fn forgets_entries_past_the_retention() {
+        let history = History::new(5, Duration::from_secs(10));
+        let (alice, bob) = (session("a"), session("b"));
+        record(&history, &alice, "old");
+        record(&history, &alice, "new");
+        let old = history.list(&alice)[1].id.clone();
+        backdate(&history, &alice, Some(&old), Duration::from_secs(11));
+        assert_eq!(names(&history, &alice), ["new"]);
+        assert!(history.uploads(&alice, &old).is_none());
+
+        // Recording for any session drops what has expired everywhere.
+        backdate(&history, &alice, None, Duration::from_secs(11));
+        record(&history, &bob, "other");
+        assert!(!history.sessions.lock().unwrap().contains_key("a"));
+    }
+
+    #[test]
+    fn caps_entries_across_sessions() {
+        let history = History::new(10, Duration::from_secs(60));
+        let sessions: Vec<Session> = (0..MAX_ENTRIES + 1).map(|index| session(&index.to_string())).collect();
+        // Each session's entry is a millisecond older than the next one's.
+        for (index, session) in sessions.iter().enumerate() {
+            record(&history, session, "art");
+            backdate(&history, session, None, Duration::from_millis((MAX_ENTRIES + 1 - index) as u64));
+        }
+        assert!(names(&history, &sessions[0]).is_empty());
+        assert_eq!(names(&history, &sessions[MAX_ENTRIES]), ["art"]);
+        assert_eq!(history.sessions.lock().unwrap().values().map(VecDeque::len).sum::<usize>(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn records_nothing_when_disabled() {
+        for history in [History::new(0, Duration::from_secs(60)), History::new(5, Duration::ZERO)] {
+            record(&history, &session("a"), "art");
+            assert!(!history.is_enabled());
+            assert!(history.sessions.lock().unwrap().is_empty());
+        }
+    }
+}
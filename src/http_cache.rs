@@ -0,0 +1,94 @@
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag, IfNoneMatch};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::time::Duration;
+
+// --- HTTP CACHING ---
+
+/// An entity tag plus the `Cache-Control` policy sent with it.
+///
+/// Tags are weak because the compression middleware may re-encode the body; the
+/// representation is the same, the bytes on the wire are not.
+pub struct Validator {
+    etag: EntityTag,
+    cache_control: CacheControl,
+}
+
+impl Validator {
+    /// Pages compiled into the binary: clients revalidate every time, which costs a `304`.
+    pub fn for_static(body: &str) -> Self {
+        Self::for_digest(&Sha256::digest(body.as_bytes()).into())
+    }
+
+    /// Like [`Validator::for_static`], for content whose SHA-256 is already known.
+    pub fn for_digest(digest: &[u8; 32]) -> Self {
+        let tag = digest[..12].iter().fold(String::new(), |mut tag, byte| {
+            let _ = write!(tag, "{:02x}", byte);
+            tag
+        });
+        Self { etag: EntityTag::new_weak(tag), cache_control: CacheControl(vec![CacheDirective::NoCache]) }
+    }
+
+    /// Results never change once stored, so they may be reused until they expire.
+    pub fn for_result(tag: String, lifetime: Duration) -> Self {
+        Self {
+            etag: EntityTag::new_weak(tag),
+            cache_control: CacheControl(vec![
+                CacheDirective::Private,
+                CacheDirective::MaxAge(lifetime.as_secs().try_into().unwrap_or(u32::MAX)),
+            ]),
+        }
+    }
+
+    /// `304 Not Modified` when the request's `If-None-Match` already names this tag.
+    pub fn not_modified(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        let matched = match req.get_header::<IfNoneMatch>()? {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+        };
+        matched.then(|| self.apply(&mut HttpResponse::NotModified()).finish())
+    }
+
+    /// A `200 OK` builder carrying the validator headers.
+    pub fn ok(&self) -> HttpResponseBuilder {
+        let mut builder = HttpResponse::Ok();
+        self.apply(&mut builder);
+        builder
+    }
+
+    fn apply<'a>(&self, builder: &'a mut HttpResponseBuilder) -> &'a mut HttpResponseBuilder {
+        builder
+            .insert_header(header::ETag(self.etag.clone()))
+            .insert_header(self.cache_control.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn matches_weak_tags() {
+        let validator = Validator::for_static("<html></html>");
+        let etag = validator.ok().finish().headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\"") && etag.len() == 24 + 4);
+
+        let fresh = TestRequest::default().insert_header((header::IF_NONE_MATCH, etag.clone())).to_http_request();
+        let response = validator.not_modified(&fresh).unwrap();
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-cache");
+
+        let stale = TestRequest::default().insert_header((header::IF_NONE_MATCH, "W/\"other\"")).to_http_request();
+        assert!(validator.not_modified(&stale).is_none());
+        assert!(validator.not_modified(&TestRequest::default().to_http_request()).is_none());
+    }
+
+    #[test]
+    fn results_are_private_until_they_expire() {
+        let response = Validator::for_result("job-1".to_string(), Duration::from_secs(600)).ok().finish();
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "W/\"job-1\"");
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=600");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::quantize;
+use image::RgbImage;
+use std::fmt::Write;
+
+// --- IRC OUTPUT ---
+
+/// Longest line sent to IRC, in bytes. Messages are capped at 512 bytes including the
+/// command, target and the server's prefix, so leave generous room for those.
+pub const MAX_LINE_BYTES: usize = 400;
+
+const COLOR: char = '\x03';
+const RESET: char = '\x0f';
+/// Bold toggled on and off again, which ends a color code without changing the text.
+const SEPARATOR: &str = "\x02\x02";
+
+/// Formats the art for pasting into IRC: with `colors`, each character gets the nearest
+/// mIRC color code; without, the text is left plain. Rows longer than
+/// [`MAX_LINE_BYTES`] are split, so a wide image becomes more, shorter lines rather than
+/// being cut off by the server.
+pub fn format(ascii_art: &str, colors: Option<&RgbImage>) -> String {
+    let mut out = String::with_capacity(ascii_art.len() * 4);
+    for (y, row) in ascii_art.lines().enumerate() {
+        let mut line = String::new();
+        let mut current = None;
+        for (x, ch) in row.chars().enumerate() {
+            let color = colors
+                .filter(|_| ch != ' ')
+                .and_then(|colors| colors.get_pixel_checked(x as u32, y as u32))
+                .map(|pixel| quantize::nearest_mirc(pixel.0))
+                .filter(|&code| current != Some(code));
+            let code = color.map(|code| color_code(code, ch)).unwrap_or_default();
+            if line.len() + code.len() + ch.len_utf8() + RESET.len_utf8() > MAX_LINE_BYTES && !line.is_empty() {
+                finish_line(&mut out, &mut line, current.is_some());
+                // The next line starts uncolored, so repeat the color there.
+                if let (Some(code), None) = (current, color) {
+                    line.push_str(&color_code(code, ch));
+                }
+            }
+            line.push_str(&code);
+            line.push(ch);
+            current = color.or(current);
+        }
+        finish_line(&mut out, &mut line, current.is_some());
+    }
+    out
+}
+
+/// The code setting `code` as the color of `next`. It is always two digits, so art that
+/// starts with a digit is not misread, and a comma after it would be read as the start of
+/// a background color, so one is kept apart.
+fn color_code(code: u8, next: char) -> String {
+    let mut out = String::with_capacity(5);
+    let _ = write!(out, "{}{:02}", COLOR, code);
+    if next == ',' {
+        out.push_str(SEPARATOR);
+    }
+    out
+}
+
+fn finish_line(out: &mut String, line: &mut String, colored: bool) {
+    out.push_str(line);
+    if colored {
+        out.push(RESET);
+    }
+    out.push('\n');
+    line.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// The text an IRC client shows, with colors, bold and resets taken out the way mIRC
+    /// reads them: up to two digits of foreground, then a comma and up to two of background.
+    fn visible(formatted: &str) -> String {
+        let mut out = String::new();
+        let mut chars = formatted.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                COLOR => {
+                    let mut digits = 0;
+                    while digits < 2 && chars.next_if(char::is_ascii_digit).is_some() {
+                        digits += 1;
+                    }
+                    if digits > 0 && chars.next_if_eq(&',').is_some() {
+                        let mut background = 0;
+                        while background < 2 && chars.next_if(char::is_ascii_digit).is_some() {
+                            background += 1;
+                        }
+                    }
+                }
+                '\x02' | RESET => {}
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn plain_without_colors() {
+        assert_eq!(format("ab\n c\n", None), "ab\n c\n");
+    }
+
+    #[test]
+    fn colors_each_change_once() {
+        let colors = RgbImage::from_fn(3, 1, |x, _| if x < 2 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) });
+        let red = quantize::nearest_mirc([255, 0, 0]);
+        let blue = quantize::nearest_mirc([0, 0, 255]);
+        assert_eq!(format("abc", Some(&colors)), format!("\x03{:02}ab\x03{:02}c\x0f\n", red, blue));
+    }
+
+    #[test]
+    fn commas_after_a_code_stay_text() {
+        let colors = RgbImage::from_fn(4, 1, |x, _| if x % 2 == 0 { Rgb([255, 0, 0]) } else { Rgb([0, 255, 0]) });
+        let formatted = format(",1,5", Some(&colors));
+        assert!(formatted.contains(SEPARATOR));
+        assert_eq!(visible(&formatted), ",1,5\n");
+        assert_eq!(visible(&format("12", Some(&colors))), "12\n");
+    }
+
+    #[test]
+    fn splits_long_rows_and_repeats_the_color() {
+        let row = "#".repeat(MAX_LINE_BYTES * 2);
+        let colors = RgbImage::from_pixel(row.len() as u32, 1, Rgb([255, 0, 0]));
+        let formatted = format(&row, Some(&colors));
+        let lines: Vec<&str> = formatted.lines().collect();
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_BYTES && line.starts_with(COLOR)));
+        assert_eq!(visible(&formatted).replace('\n', ""), row);
+    }
+}
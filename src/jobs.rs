@@ -0,0 +1,484 @@
+use crate::cache::{ConversionOutput, ResultCache};
+use crate::collage::{self, CollageLayout};
+use crate::concurrency::{self, ConversionLimiter, Priority};
+use crate::config::ServerConfig;
+use crate::form::{UploadFields, UploadForm};
+use crate::output::{self, AsciiGrid};
+use crate::recipe::RecipeStore;
+use crate::http_cache::Validator;
+use crate::progress::{Progress, ProgressRegistry};
+use crate::spool::UploadBody;
+use crate::tile::TileQuery;
+use crate::webhook::{self, Callback, Payload, Webhooks};
+use crate::{convert_cached, filename_stem, ConversionOptions};
+use actix_multipart::Multipart;
+use actix_web::{get, http::header, post, web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+// --- BACKGROUND JOB QUEUE ---
+
+struct Job {
+    id: String,
+    /// The uploads with their filenames; more than one makes a collage.
+    images: Vec<(String, UploadBody)>,
+    options: ConversionOptions,
+    collage: CollageLayout,
+    progress: Arc<Progress>,
+    callback: Option<Callback>,
+}
+
+enum JobState {
+    Queued,
+    Running,
+    Done(Arc<ConversionOutput>),
+    Failed(String),
+}
+
+struct JobRecord {
+    state: JobState,
+    filename: String,
+    progress: Arc<Progress>,
+    updated: Instant,
+}
+
+/// Conversions submitted through `/jobs`, processed by a fixed pool of background workers.
+pub struct JobQueue {
+    sender: mpsc::Sender<Job>,
+    jobs: Mutex<HashMap<String, JobRecord>>,
+    retention: Duration,
+    live_workers: AtomicUsize,
+    closing: AtomicBool,
+    webhooks: Arc<Webhooks>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobStatus {
+    id: String,
+    /// `queued`, `running`, `done` or `failed`.
+    #[schema(value_type = String, example = "running")]
+    status: &'static str,
+    /// Percentage complete, 0-100.
+    progress: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result_url: Option<String>,
+    /// Why the result is smaller than asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notice: Option<String>,
+}
+
+impl JobQueue {
+    /// Creates the queue and spawns its workers on the current runtime.
+    pub fn start(config: &ServerConfig, cache: web::Data<ResultCache>, limiter: web::Data<ConversionLimiter>) -> web::Data<Self> {
+        let (sender, receiver) = mpsc::channel(config.job_queue_capacity.max(1));
+        let queue = web::Data::new(JobQueue {
+            sender,
+            jobs: Mutex::new(HashMap::new()),
+            retention: config.job_retention,
+            live_workers: AtomicUsize::new(0),
+            closing: AtomicBool::new(false),
+            webhooks: Arc::new(Webhooks::new(config.webhook_secret.as_deref())),
+        });
+
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for _ in 0..config.job_workers.max(1) {
+            let queue = queue.clone();
+            let receiver = receiver.clone();
+            let cache = cache.clone();
+            let limiter = limiter.clone();
+            queue.live_workers.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                loop {
+                    let job = receiver.lock().await.recv().await;
+                    match job {
+                        Some(job) => queue.run(job, &cache, &limiter).await,
+                        None => break,
+                    }
+                }
+                queue.live_workers.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        queue
+    }
+
+    async fn run(&self, job: Job, cache: &ResultCache, limiter: &ConversionLimiter) {
+        let span = tracing::info_span!("job", job.id = %job.id, conversion.options = ?job.options);
+        async {
+            self.set_state(&job.id, JobState::Running);
+            let started = Instant::now();
+            let (mut images, progress) = (job.images, Some(job.progress));
+            let converted = if images.len() == 1 {
+                let (_, image) = images.remove(0);
+                convert_cached(image, &job.options, cache, limiter, Priority::Background, progress).await
+            } else {
+                collage::convert(images, &job.options, &job.collage, cache, limiter, Priority::Background, progress).await
+            };
+            let state = match converted {
+                Ok(output) => {
+                    tracing::info!(
+                        output.bytes = output.ascii_art.len(),
+                        duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+                        "job finished"
+                    );
+                    JobState::Done(output)
+                }
+                Err(err) => {
+                    let message = format!("{:#}", err);
+                    tracing::warn!(error = %message, "job failed");
+                    JobState::Failed(message)
+                }
+            };
+            let callback = job.callback.map(|callback| {
+                let payload = match &state {
+                    JobState::Done(output) => {
+                        let mut payload = Payload::new(&job.id, &callback, None);
+                        payload.notice = output.notice.clone();
+                        let result_url = format!("{}/jobs/{}/result", callback.base_url, job.id);
+                        for renderer in output::renderers().filter(|renderer| renderer.supports(output.mode)) {
+                            payload.results.insert(renderer.name(), format!("{}?format={}", result_url, renderer.name()));
+                        }
+                        payload
+                    }
+                    JobState::Failed(message) => Payload::new(&job.id, &callback, Some(message.clone())),
+                    JobState::Queued | JobState::Running => unreachable!("a finished job is done or failed"),
+                };
+                (callback.url, payload)
+            });
+            self.set_state(&job.id, state);
+            // Delivered once the result can be fetched, off the async workers since
+            // retries wait.
+            if let Some((url, payload)) = callback {
+                let webhooks = self.webhooks.clone();
+                tokio::task::spawn_blocking(move || webhooks.deliver(&url, &payload));
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Queues a conversion and returns its job ID, or `None` when the queue is full or
+    /// shutting down.
+    fn submit(
+        &self,
+        images: Vec<(String, UploadBody)>,
+        options: ConversionOptions,
+        collage: CollageLayout,
+        callback: Option<Callback>,
+        progress_registry: &ProgressRegistry,
+    ) -> Option<String> {
+        if self.closing.load(Ordering::SeqCst) {
+            return None;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let progress = progress_registry.tracker(&id);
+        let record = JobRecord {
+            state: JobState::Queued,
+            filename: if images.len() > 1 { "collage".to_string() } else { images[0].0.clone() },
+            progress: progress.clone(),
+            updated: Instant::now(),
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, record| {
+            !matches!(record.state, JobState::Done(_) | JobState::Failed(_))
+                || record.updated.elapsed() < self.retention
+        });
+        if self.sender.try_send(Job { id: id.clone(), images, options, collage, progress, callback }).is_err() {
+            progress_registry.remove(&id);
+            return None;
+        }
+        jobs.insert(id.clone(), record);
+        Some(id)
+    }
+
+    /// Stops accepting jobs and waits up to `grace` for queued and running ones to finish.
+    pub async fn drain(&self, grace: Duration) {
+        self.closing.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + grace;
+        loop {
+            let pending = self.pending();
+            if pending == 0 {
+                tracing::info!("all background jobs finished");
+                return;
+            }
+            if Instant::now() >= deadline {
+                tracing::warn!(pending, "shutdown grace period elapsed with unfinished jobs");
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    fn pending(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .filter(|record| matches!(record.state, JobState::Queued | JobState::Running))
+            .count()
+    }
+
+    /// Describes why the queue cannot take jobs, if it cannot.
+    pub fn health(&self) -> Result<(), &'static str> {
+        if self.closing.load(Ordering::SeqCst) {
+            return Err("shutting down");
+        }
+        if self.sender.is_closed() || self.live_workers.load(Ordering::SeqCst) == 0 {
+            return Err("no job workers running");
+        }
+        if self.sender.capacity() == 0 {
+            return Err("job queue is full");
+        }
+        Ok(())
+    }
+
+    fn set_state(&self, id: &str, state: JobState) {
+        if let Some(record) = self.jobs.lock().unwrap().get_mut(id) {
+            record.state = state;
+            record.updated = Instant::now();
+        }
+    }
+
+    fn status(&self, id: &str) -> Option<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        let record = jobs.get(id)?;
+        let (status, error, notice) = match &record.state {
+            JobState::Queued => ("queued", None, None),
+            JobState::Running => ("running", None, None),
+            JobState::Done(output) => ("done", None, output.notice.clone()),
+            JobState::Failed(err) => ("failed", Some(err.clone()), None),
+        };
+        Some(JobStatus {
+            id: id.to_string(),
+            status,
+            progress: record.progress.percent(),
+            error,
+            result_url: matches!(record.state, JobState::Done(_)).then(|| format!("/jobs/{}/result", id)),
+            notice,
+        })
+    }
+}
+
+/// Queues a conversion and returns its status immediately.
+#[utoipa::path(
+    path = "/jobs",
+    tag = "jobs",
+    request_body(content = UploadFields, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "Job queued", body = JobStatus,
+            headers(("Location" = String, description = "URL of the job status"))),
+        (status = 400, description = "No image uploaded, or a callback URL without webhooks configured or to a non-public address"),
+        (status = 503, description = "Queue full or shutting down",
+            headers(("Retry-After" = u64, description = "Seconds to wait"))),
+    ),
+)]
+#[post("")]
+async fn submit_job(
+    req: HttpRequest,
+    mut payload: Multipart,
+    server_config: web::Data<ServerConfig>,
+    recipes: web::Data<RecipeStore>,
+    queue: web::Data<JobQueue>,
+    progress_registry: web::Data<ProgressRegistry>,
+) -> Result<HttpResponse, Error> {
+    let mut form = UploadForm::read(&mut payload, &server_config, &recipes).await?;
+    let image_data = match form.image.take() {
+        Some(data) => data,
+        None => return Ok(HttpResponse::BadRequest().body("No image uploaded.")),
+    };
+    let images = form.take_images(image_data);
+    // The result can be fetched later in any format, colored ones included.
+    form.options.colors = true;
+    if form.callback_url.is_some() && !queue.webhooks.is_enabled() {
+        return Ok(HttpResponse::BadRequest().body("Callbacks need ASCII_WEBHOOK_SECRET to be set on the server."));
+    }
+    if let Some(url) = form.callback_url.clone()
+        && let Err(message) = web::block(move || webhook::check_destination(&url)).await?
+    {
+        return Ok(HttpResponse::BadRequest().body(message));
+    }
+    let connection = req.connection_info();
+    let callback =
+        form.callback_url.map(|url| Callback { url, base_url: format!("{}://{}", connection.scheme(), connection.host()) });
+
+    let Some(id) = queue.submit(images, form.options, form.collage, callback, &progress_registry) else {
+        return Ok(HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, "30"))
+            .body("The job queue is full, try again later."));
+    };
+    let status = queue.status(&id);
+    Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/jobs/{}", id)))
+        .json(status))
+}
+
+#[utoipa::path(
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID returned on submission")),
+    responses(
+        (status = 200, description = "Current job status", body = JobStatus),
+        (status = 404, description = "Unknown job ID"),
+    ),
+)]
+#[get("/{id}")]
+async fn job_status(id: web::Path<String>, queue: web::Data<JobQueue>) -> HttpResponse {
+    match queue.status(&id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().body("Unknown job ID."),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResultQuery {
+    /// An output format: `html` (default), `text` (or `txt`), `ansi`, `irc`, `svg`, `png`,
+    /// the animations of `gif` and `apng`, the panels of `zip` and `pdf`, or the cells of `grid`.
+    pub format: Option<String>,
+}
+
+/// Returns a finished job's result in the requested format, the HTML viewer by default.
+#[utoipa::path(
+    path = "/jobs/{id}/result",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID returned on submission"), ResultQuery, TileQuery),
+    responses(
+        (status = 200, description = "The finished result", content(
+            (String = "text/html"),
+            (String = "text/plain"),
+            (String = "image/svg+xml"),
+            (Vec<u8> = "image/png"),
+            (Vec<u8> = "application/zip"),
+            (Vec<u8> = "application/pdf"),
+            (String = "application/vnd.ascii-grid+json"),
+        )),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Unknown format"),
+        (status = 404, description = "Unknown job ID"),
+        (status = 409, description = "The job is pending or failed"),
+        (status = 422, description = "The result cannot be drawn in this format"),
+        (status = 503, description = "Too many conversions and downloads are being drawn",
+            headers(("Retry-After" = u64, description = "Seconds to wait"))),
+    ),
+)]
+#[get("/{id}/result")]
+async fn job_result(
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<ResultQuery>,
+    tiles: web::Query<TileQuery>,
+    queue: web::Data<JobQueue>,
+    limiter: web::Data<ConversionLimiter>,
+) -> HttpResponse {
+    let (output, filename) = {
+        let jobs = queue.jobs.lock().unwrap();
+        match jobs.get(id.as_str()) {
+            Some(JobRecord { state: JobState::Done(output), filename, .. }) => (output.clone(), filename_stem(filename)),
+            Some(_) => return HttpResponse::Conflict().body("The job has not finished successfully."),
+            None => return HttpResponse::NotFound().body("Unknown job ID."),
+        }
+    };
+
+    render_result(&req, output, &filename, query.format.as_deref(), &tiles, &id, queue.retention, &limiter).await
+}
+
+/// Draws a stored result in `format`, the HTML viewer by default, as a download named after
+/// `filename`. `tag` identifies the result, which never changes while it is kept for `lifetime`.
+/// Drawing takes a conversion slot, since large art makes for large images.
+#[allow(clippy::too_many_arguments)]
+pub async fn render_result(
+    req: &HttpRequest,
+    output: Arc<ConversionOutput>,
+    filename: &str,
+    format: Option<&str>,
+    tiles: &TileQuery,
+    tag: &str,
+    lifetime: Duration,
+    limiter: &ConversionLimiter,
+) -> HttpResponse {
+    let name = match format {
+        None => "html",
+        Some("txt") => "text",
+        Some(name) => name,
+    };
+    let Some(renderer) = output::renderer(name) else {
+        return HttpResponse::BadRequest().body("Unknown format.");
+    };
+    if !renderer.supports(output.mode) {
+        return HttpResponse::UnprocessableEntity().body("Emoji mosaics cannot be drawn in this format.");
+    }
+    // The result's tag, format and panel size identify the body.
+    let (tile_columns, tile_rows) = tiles.size();
+    let validator =
+        Validator::for_result(format!("{}-{}-{}x{}", tag, renderer.name(), tile_columns, tile_rows), lifetime);
+    if let Some(not_modified) = validator.not_modified(req) {
+        return not_modified;
+    }
+    let Ok(_slot) = limiter.acquire(Priority::Interactive).await else {
+        return concurrency::overloaded();
+    };
+    let rendered = match web::block(move || renderer.render(&AsciiGrid::new(&output).with_tile_size((tile_columns, tile_rows)))).await {
+        Ok(Ok(rendered)) => rendered,
+        Ok(Err(err)) => return HttpResponse::UnprocessableEntity().body(format!("{:#}", err)),
+        Err(_) => return HttpResponse::InternalServerError().body("Rendering was interrupted."),
+    };
+    validator
+        .ok()
+        .content_type(rendered.content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"{}.{}\"", filename, renderer.extension()),
+        ))
+        .body(rendered.body)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(submit_job).service(job_status).service(job_result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A queue without workers; jobs stay in the channel until the receiver is dropped.
+    fn idle_queue(capacity: usize) -> (JobQueue, mpsc::Receiver<Job>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let queue = JobQueue {
+            sender,
+            jobs: Mutex::new(HashMap::new()),
+            retention: Duration::from_secs(60),
+            live_workers: AtomicUsize::new(1),
+            closing: AtomicBool::new(false),
+            webhooks: Arc::new(Webhooks::new(None)),
+        };
+        (queue, receiver)
+    }
+
+    fn submit(queue: &JobQueue, progress_registry: &ProgressRegistry) -> Option<String> {
+        let images = vec![("a.png".to_string(), UploadBody::from_bytes(b"image"))];
+        queue.submit(images, ConversionOptions::default(), CollageLayout::default(), None, progress_registry)
+    }
+
+    #[test]
+    fn refused_jobs_leave_no_tracker() {
+        let progress_registry = ProgressRegistry::default();
+        let (queue, _receiver) = idle_queue(1);
+        let id = submit(&queue, &progress_registry).unwrap();
+        // The queue is full now.
+        assert!(submit(&queue, &progress_registry).is_none());
+        progress_registry.remove(&id);
+        assert!(progress_registry.is_empty());
+
+        let (queue, _receiver) = idle_queue(1);
+        queue.closing.store(true, Ordering::SeqCst);
+        assert!(submit(&queue, &progress_registry).is_none());
+        assert!(progress_registry.is_empty());
+        assert!(queue.jobs.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+//! The conversion core: character mappers, character sets, preprocessing and the
+//! PNG/SVG renderer. It has no web server dependencies, so it also builds for
+//! WebAssembly, where the `wasm` module exposes it to JavaScript.
+
+pub mod braille;
+pub mod charset;
+pub mod emoji;
+pub mod font;
+pub mod mapper;
+pub mod preprocess;
+pub mod quadrant;
+pub mod quantize;
+pub mod render;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+use braille::Braille;
+use emoji::Emoji;
+use mapper::{CharMapper, Dithered, Edges, GlyphMatch, Ramp, Stencil, Threshold};
+use quadrant::Quadrant;
+
+// --- CONVERSION SETTINGS ---
+
+pub const SIMPLE_CHARS: &str = " .:-=+*#%@";
+pub const DETAILED_CHARS: &str = " .'`^\",:;Il!i><~+_-?][}{1)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$";
+/// Cell height relative to its width in common monospace fonts, as rows per column of pixels.
+pub const DEFAULT_ASPECT_RATIO: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ColorTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ColorTheme {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "light" => ColorTheme::Light,
+            _ => ColorTheme::Dark,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorTheme::Dark => "dark",
+            ColorTheme::Light => "light",
+        }
+    }
+
+    /// Background color, text color, and whether bright pixels get the light end of the ramp.
+    pub fn colors(self) -> (&'static str, &'static str, bool) {
+        match self {
+            ColorTheme::Dark => ("#1a1a1a", "#e0e0e0", false),
+            ColorTheme::Light => ("#f0f0f0", "#111111", true),
+        }
+    }
+}
+
+/// What each cell of the output is drawn with; every mode is a [`CharMapper`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    /// Characters from a brightness ramp.
+    #[default]
+    Ascii,
+    /// The ramp with error diffusion.
+    Dithered,
+    /// Line characters along edges, the ramp elsewhere.
+    Edges,
+    /// Ramp characters whose bitmap best matches each 8x8 block.
+    Glyph,
+    /// Braille patterns, two by four pixels per character.
+    Braille,
+    /// The emoji nearest to each cell's average color.
+    Emoji,
+    /// Quadrant block elements, two by two pixels per character.
+    Quadrant,
+    /// The two ends of the ramp, split at a threshold or dithered.
+    Stencil,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "dithered" => Mode::Dithered,
+            "edges" => Mode::Edges,
+            "glyph" => Mode::Glyph,
+            "braille" => Mode::Braille,
+            "emoji" => Mode::Emoji,
+            "quadrant" => Mode::Quadrant,
+            "stencil" => Mode::Stencil,
+            _ => Mode::Ascii,
+        }
+    }
+
+    /// The name [`Mode::from_name`] takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Ascii => "ascii",
+            Mode::Dithered => "dithered",
+            Mode::Edges => "edges",
+            Mode::Glyph => "glyph",
+            Mode::Braille => "braille",
+            Mode::Emoji => "emoji",
+            Mode::Quadrant => "quadrant",
+            Mode::Stencil => "stencil",
+        }
+    }
+
+    /// The mapper drawing this mode with the ramp `chars`. `threshold` and `dither` only
+    /// apply to [`Mode::Stencil`].
+    pub fn mapper(self, chars: &[char], invert: bool, threshold: Threshold, dither: bool) -> Box<dyn CharMapper> {
+        let ramp = || Ramp::new(chars.to_vec(), invert);
+        match self {
+            Mode::Ascii => Box::new(ramp()),
+            Mode::Dithered => Box::new(Dithered(ramp())),
+            Mode::Edges => Box::new(Edges(ramp())),
+            Mode::Glyph => Box::new(GlyphMatch::new(chars, invert)),
+            Mode::Braille => Box::new(Braille { invert }),
+            Mode::Emoji => Box::new(Emoji),
+            Mode::Quadrant => Box::new(Quadrant { invert }),
+            Mode::Stencil => Box::new(Stencil::new(chars, threshold, dither, invert)),
+        }
+    }
+}
+
+/// Columns and rows for drawing a `width` x `height` image `columns` characters wide.
+pub fn output_size(width: u32, height: u32, columns: u32, aspect_ratio: f32) -> (u32, u32) {
+    let rows = (height as f32 * columns as f32 / width as f32 * aspect_ratio).max(1.0) as u32;
+    (columns, rows)
+}
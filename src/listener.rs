@@ -0,0 +1,107 @@
+use crate::config::ServerConfig;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::{BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+
+// --- LISTENERS ---
+
+/// First descriptor systemd passes to an activated service.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Where the plain HTTP server accepts connections.
+pub enum Listener {
+    /// A TCP address to bind, from `ASCII_BIND`.
+    Address(String),
+    /// A TCP socket inherited through systemd socket activation.
+    Tcp(TcpListener),
+    /// A Unix domain socket, inherited or bound at `path`.
+    Unix { listener: UnixListener, path: Option<PathBuf> },
+}
+
+impl Listener {
+    /// A socket passed by systemd comes first, then `ASCII_UNIX_SOCKET`, then `ASCII_BIND`.
+    pub fn from_config(config: &ServerConfig) -> io::Result<Self> {
+        if let Some(listener) = inherited()? {
+            return Ok(listener);
+        }
+        match &config.unix_socket {
+            Some(path) => bind_unix(path, config.unix_socket_mode),
+            None => Ok(Listener::Address(config.bind_address.clone())),
+        }
+    }
+
+    /// The socket file this server created, to be removed once it stops.
+    pub fn socket_path(&self) -> Option<&Path> {
+        match self {
+            Listener::Unix { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Listener::Address(address) => write!(f, "http://{}", address),
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => write!(f, "http://{} (socket activated)", address),
+                Err(_) => write!(f, "an inherited TCP socket"),
+            },
+            Listener::Unix { path: Some(path), .. } => write!(f, "unix:{}", path.display()),
+            Listener::Unix { listener, path: None } => match listener.local_addr().ok().and_then(|address| address.as_pathname().map(Path::to_path_buf)) {
+                Some(path) => write!(f, "unix:{} (socket activated)", path.display()),
+                None => write!(f, "an inherited Unix socket"),
+            },
+        }
+    }
+}
+
+/// The listening socket systemd passed, when `LISTEN_PID` names this process. Only the
+/// first of several sockets is used.
+fn inherited() -> io::Result<Option<Listener>> {
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.trim().parse::<u32>().ok()) == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|count| count.trim().parse::<u32>().ok()).unwrap_or(0);
+    if !for_us || count == 0 {
+        return Ok(None);
+    }
+    if count > 1 {
+        tracing::warn!(count, "systemd passed several sockets, only the first is used");
+    }
+    // SAFETY: the descriptor is only borrowed to check that it is open.
+    unsafe { BorrowedFd::borrow_raw(SD_LISTEN_FDS_START) }
+        .try_clone_to_owned()
+        .map_err(|err| io::Error::new(err.kind(), format!("The socket passed by systemd is not open: {}", err)))?;
+    // SAFETY: systemd hands the descriptors starting at 3 over to this process, and
+    // nothing else in it takes ownership of them.
+    let unix = UnixListener::from(unsafe { OwnedFd::from_raw_fd(SD_LISTEN_FDS_START) });
+    if unix.local_addr().is_ok() {
+        unix.set_nonblocking(true)?;
+        return Ok(Some(Listener::Unix { listener: unix, path: None }));
+    }
+    let tcp = TcpListener::from(OwnedFd::from(unix));
+    tcp.local_addr()
+        .map_err(|err| io::Error::other(format!("The socket passed by systemd is neither TCP nor Unix: {}", err)))?;
+    tcp.set_nonblocking(true)?;
+    Ok(Some(Listener::Tcp(tcp)))
+}
+
+/// Binds a Unix socket at `path` with permissions `mode`, replacing a socket left behind
+/// by an earlier run but never any other kind of file.
+fn bind_unix(path: &Path, mode: u32) -> io::Result<Listener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::other(format!("{} exists and is not a socket", path.display())));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("Failed to bind {}: {}", path.display(), err)))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    listener.set_nonblocking(true)?;
+    Ok(Listener::Unix { listener, path: Some(path.to_path_buf()) })
+}
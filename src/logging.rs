@@ -0,0 +1,57 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpMessage};
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_actix_web::{root_span, DefaultRootSpanBuilder, RootSpanBuilder};
+use tracing_subscriber::EnvFilter;
+
+// --- STRUCTURED LOGGING ---
+
+/// Installs the global subscriber. `RUST_LOG` sets the filter (default `info`) and
+/// `ASCII_LOG_FORMAT=json` switches to one JSON object per line.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = std::env::var("ASCII_LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if json {
+        builder.json().flatten_event(true).with_current_span(true).with_span_list(false).init();
+    } else {
+        builder.init();
+    }
+}
+
+struct RequestStart(Instant);
+
+/// Root span for every request: the default HTTP fields plus the conversion options,
+/// output size and duration, which are logged together when the request completes.
+pub struct RequestSpan;
+
+impl RootSpanBuilder for RequestSpan {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        request.extensions_mut().insert(RequestStart(Instant::now()));
+        root_span!(request, conversion.options = Empty, output.bytes = Empty, duration_ms = Empty)
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span.clone(), outcome);
+        let status = match outcome {
+            Ok(response) => {
+                if let Some(RequestStart(start)) = response.request().extensions().get::<RequestStart>() {
+                    span.record("duration_ms", start.elapsed().as_secs_f64() * 1000.0);
+                }
+                response.status().as_u16()
+            }
+            Err(err) => err.as_response_error().status_code().as_u16(),
+        };
+        span.in_scope(|| tracing::info!(status, "request completed"));
+    }
+}
+
+/// Attaches the conversion details to the current request span.
+pub fn record_conversion(options: &impl std::fmt::Debug, output_bytes: usize) {
+    let span = Span::current();
+    span.record("conversion.options", tracing::field::debug(options));
+    span.record("output.bytes", output_bytes);
+}
@@ -1,276 +1,934 @@
+mod animation;
+mod ansi;
+mod api;
+mod api_auth;
+mod assets;
+mod cache;
+mod collage;
+mod concurrency;
+mod config;
+mod cors;
+mod encoder;
+mod form;
+mod health;
+mod history;
+#[cfg(feature = "heic")]
+mod heic;
+mod http_cache;
+mod irc;
+mod jobs;
+mod listener;
+mod logging;
+mod metrics;
+mod multipage;
+mod openapi;
+mod original;
+mod output;
+mod overlay;
+mod plain;
+mod progress;
+mod rate_limit;
+mod recipe;
+mod sauce;
+mod spool;
+mod stream;
+mod svg;
+mod tile;
+mod tls;
+mod webhook;
+
 use actix_multipart::Multipart;
-use actix_web::{get, post, App, Error, HttpResponse, HttpServer, Responder};
-use anyhow::{Context, Result};
-use futures_util::stream::StreamExt;
-use image::{DynamicImage, ImageError};
-use sanitize_filename::sanitize;
-use std::io::Write;
+use actix_web::{get, http::header, middleware, post, web, App, Error, HttpResponse, HttpServer, Responder};
+use animation::{AsciiFrame, FrameSequence};
+use anyhow::{bail, Context, Result};
+use askama::Template;
+use base64::Engine;
+use api_auth::{ApiKeyAuth, ApiKeyStore};
+use cache::{CacheKey, ConversionOutput, ResultCache};
+use concurrency::{ConversionLimiter, Overloaded, Priority};
+use config::ServerConfig;
+use font::UserFont;
+use form::UploadForm;
+use history::{History, Session};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+use jobs::JobQueue;
+use listener::Listener;
+use logging::RequestSpan;
+use mapper::{CharMapper, Pixels, RowProgress, Threshold};
+use metrics::METRICS;
+use original::OriginalStore;
+use output::{AsciiGrid, OutputRenderer};
+use overlay::Overlay;
+use png_to_ascii::{
+    charset, font, mapper, preprocess, quantize, render, ColorTheme, Mode, DEFAULT_ASPECT_RATIO, DETAILED_CHARS, SIMPLE_CHARS,
+};
+use progress::{Progress, ProgressRegistry, Stage};
+use rate_limit::{RateLimit, RateLimiter};
+use recipe::RecipeStore;
+use preprocess::{Crop, Flip, Grayscale, LocalThreshold};
+use spool::UploadBody;
 use std::path::PathBuf;
-use url_escape;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing_actix_web::TracingLogger;
 
 // --- ASCII CONVERSION LOGIC ---
 
-const SIMPLE_CHARS: &str = " .:-=+*#%@";
-const DETAILED_CHARS: &str = " .'`^\",:;Il!i><~+_-?][}{1)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$";
+/// Output width in characters when not using full resolution.
+const DEFAULT_WIDTH: u32 = 150;
+/// Emoji are square and twice as wide as characters, so mosaics get half the columns.
+const EMOJI_WIDTH: u32 = DEFAULT_WIDTH / 2;
+
+/// What to do with pixels of images that have an alpha channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Transparency {
+    /// Composite over the theme's background color.
+    #[default]
+    Background,
+    /// Render pixels below the alpha threshold as spaces.
+    Blank,
+}
 
-#[derive(Clone, Copy)]
-enum ColorTheme {
-    Dark,
-    Light,
+impl Transparency {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "blank" => Transparency::Blank,
+            _ => Transparency::Background,
+        }
+    }
 }
 
+/// Alpha below which pixels count as transparent in [`Transparency::Blank`] mode.
+const DEFAULT_ALPHA_THRESHOLD: u8 = 128;
+
 struct AsciiConfig {
     width: u32,
     use_full_resolution: bool,
+    /// Characters full-resolution art is downscaled to fit in.
+    max_cells: Option<u64>,
     character_set: Vec<char>,
     invert_mapping: bool,
     aspect_ratio_correction: f32,
     background_color: String,
     text_color: String,
+    /// Also return the color of every cell, for colored renderers.
+    keep_colors: bool,
+    apply_exif_orientation: bool,
+    transparency: Transparency,
+    alpha_threshold: u8,
+    crop: Option<Crop>,
+    rotate: u16,
+    flip: Flip,
+    page: usize,
+    animate: bool,
+    /// Font the output will be shown in, which sets the cell aspect ratio and renders PNGs.
+    font: Option<UserFont>,
+    mode: Mode,
+    grayscale: Grayscale,
+    threshold: Threshold,
+    dither: bool,
+    local_threshold: Option<LocalThreshold>,
+    adaptive_block: u32,
+    adaptive_offset: i16,
+    line_art: bool,
+    line_sigma: f32,
+    line_threshold: u8,
+}
+
+/// User-selectable conversion settings, as submitted through the form or job API.
+#[derive(Clone, Debug, Default)]
+struct ConversionOptions {
+    theme: ColorTheme,
+    detailed: bool,
+    full_resolution: bool,
+    /// Characters full-resolution art may have, from the server configuration.
+    max_cells: Option<u64>,
+    /// Keep per-cell colors; set by routes that emit colored output.
+    colors: bool,
+    /// Use the pixels as stored instead of turning the image upright per its EXIF tag.
+    ignore_exif: bool,
+    transparency: Transparency,
+    alpha_threshold: Option<u8>,
+    /// Region to convert, applied after the EXIF orientation.
+    crop: Option<Crop>,
+    /// Clockwise rotation in degrees, applied after cropping.
+    rotate: u16,
+    flip: Flip,
+    /// Zero-based page of a multi-page TIFF.
+    page: usize,
+    /// Convert only the first frame of animated inputs.
+    first_frame_only: bool,
+    /// Replace the theme's background and text colors.
+    background_color: Option<Rgb<u8>>,
+    text_color: Option<Rgb<u8>>,
+    /// Named ramp replacing the simple or detailed set.
+    preset: Option<&'static charset::Preset>,
+    /// Custom ramp replacing the built-in character sets, including presets.
+    charset: Option<Vec<char>>,
+    /// Use the custom ramp in the given order instead of sorting it by ink density.
+    keep_charset_order: bool,
+    /// Uploaded or installed monospace font to calibrate for and render with.
+    font: Option<UserFont>,
+    mode: Mode,
+    /// How colors become brightness.
+    grayscale: Grayscale,
+    /// Where the stencil mode splits ink from background.
+    threshold: Threshold,
+    /// Error-diffuse the stencil mode into a halftone.
+    dither: bool,
+    /// Even out uneven lighting by thresholding against the surrounding block.
+    local_threshold: Option<LocalThreshold>,
+    /// Side of the block, in pixels of the sampled image.
+    adaptive_block: Option<u32>,
+    /// Added to every pixel after the local threshold is taken out.
+    adaptive_offset: Option<i16>,
+    /// Turn the image into XDoG line art before mapping.
+    line_art: bool,
+    line_sigma: Option<f32>,
+    line_threshold: Option<u8>,
+    /// Caption and watermark written into the art.
+    overlay: Overlay,
+}
+
+impl ConversionOptions {
+    /// Whether the art covers the whole upload, upright as browsers show it.
+    fn shows_whole_upload(&self) -> bool {
+        self.crop.is_none()
+            && self.rotate == 0
+            && !self.flip.horizontal
+            && !self.flip.vertical
+            && !self.ignore_exif
+            && self.page == 0
+    }
+
+    fn ascii_config(&self) -> AsciiConfig {
+        let mut config = AsciiConfig::new(self.theme, self.detailed);
+        if let Some(preset) = self.preset {
+            config.character_set = preset.chars.chars().collect();
+        }
+        if let Some(chars) = &self.charset {
+            config.character_set = chars.clone();
+            if !self.keep_charset_order {
+                charset::sort_by_density(&mut config.character_set, self.font.as_ref());
+            }
+        }
+        if self.background_color.is_some() || self.text_color.is_some() {
+            let theme_color = |color: &str| render::parse_hex_color(color).expect("theme colors are valid");
+            let background = self.background_color.unwrap_or_else(|| theme_color(&config.background_color));
+            let text = self.text_color.unwrap_or_else(|| theme_color(&config.text_color));
+            // Dense characters stand for the text color, so dark text on a light background inverts.
+            config.invert_mapping = render::luminance(background) > render::luminance(text);
+            config.background_color = render::hex_color(background);
+            config.text_color = render::hex_color(text);
+        }
+        let mut config = AsciiConfig {
+            use_full_resolution: self.full_resolution,
+            max_cells: self.max_cells,
+            keep_colors: self.colors,
+            apply_exif_orientation: !self.ignore_exif,
+            transparency: self.transparency,
+            alpha_threshold: self.alpha_threshold.unwrap_or(DEFAULT_ALPHA_THRESHOLD),
+            crop: self.crop,
+            rotate: self.rotate,
+            flip: self.flip,
+            page: self.page,
+            animate: !self.first_frame_only,
+            aspect_ratio_correction: self.font.as_ref().map_or(config.aspect_ratio_correction, UserFont::aspect_ratio),
+            font: self.font.clone(),
+            mode: self.mode,
+            grayscale: self.grayscale,
+            threshold: self.threshold,
+            dither: self.dither,
+            local_threshold: self.local_threshold,
+            adaptive_block: self.adaptive_block.unwrap_or(preprocess::DEFAULT_ADAPTIVE_BLOCK),
+            adaptive_offset: self.adaptive_offset.unwrap_or(0),
+            line_art: self.line_art,
+            line_sigma: self.line_sigma.unwrap_or(preprocess::DEFAULT_LINE_SIGMA),
+            line_threshold: self.line_threshold.unwrap_or(preprocess::DEFAULT_LINE_THRESHOLD),
+            ..config
+        };
+        if self.mode == Mode::Emoji {
+            config.width = EMOJI_WIDTH;
+            config.aspect_ratio_correction = 1.0;
+        }
+        config
+    }
+
+    /// Label values for the `mode`, `charset` and `resolution` metric labels.
+    fn metric_labels(&self) -> [&'static str; 3] {
+        [
+            self.mode.name(),
+            if self.detailed { "detailed" } else { "simple" },
+            if self.full_resolution { "full" } else { "resized" },
+        ]
+    }
+
+    /// The options that shaped the art, as reported by the grid JSON format.
+    fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "theme": self.theme.name(),
+            "mode": self.mode.name(),
+            "detailed": self.detailed,
+            "preset": self.preset.map(|preset| preset.name),
+            "custom_charset": self.charset.is_some(),
+            "full_resolution": self.full_resolution,
+            "grayscale": self.grayscale.name(),
+            "threshold": match self.threshold {
+                Threshold::Otsu => serde_json::json!("otsu"),
+                Threshold::Fixed(level) => serde_json::json!(level),
+            },
+            "dither": self.dither,
+            "adaptive": self.local_threshold.map(LocalThreshold::name),
+            "line_art": self.line_art,
+            "rotate": self.rotate,
+        })
+    }
+
+    /// Every option takes part in the key, so new fields are picked up automatically.
+    fn cache_key(&self, content_digest: &[u8; 32]) -> CacheKey {
+        ResultCache::key(content_digest, &format!("{:?}", self))
+    }
+}
+
+impl AsciiConfig {
+    fn new(theme: ColorTheme, detailed: bool) -> Self {
+        let (bg_color, txt_color, invert_mapping) = theme.colors();
+
+        let char_string = if detailed { DETAILED_CHARS } else { SIMPLE_CHARS };
+
+        Self {
+            width: DEFAULT_WIDTH,
+            use_full_resolution: false,
+            max_cells: None,
+            character_set: char_string.chars().collect(),
+            invert_mapping,
+            aspect_ratio_correction: DEFAULT_ASPECT_RATIO,
+            background_color: bg_color.to_string(),
+            text_color: txt_color.to_string(),
+            keep_colors: false,
+            apply_exif_orientation: true,
+            transparency: Transparency::Background,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+            crop: None,
+            rotate: 0,
+            flip: Flip::default(),
+            page: 0,
+            animate: true,
+            font: None,
+            mode: Mode::Ascii,
+            grayscale: Grayscale::Luma,
+            threshold: Threshold::Otsu,
+            dither: false,
+            local_threshold: None,
+            adaptive_block: preprocess::DEFAULT_ADAPTIVE_BLOCK,
+            adaptive_offset: 0,
+            line_art: false,
+            line_sigma: preprocess::DEFAULT_LINE_SIGMA,
+            line_threshold: preprocess::DEFAULT_LINE_THRESHOLD,
+        }
+    }
+}
+
+/// One image as mapped by [`AsciiConverter::convert_to_ascii`].
+struct ConvertedImage {
+    art: String,
+    dimensions: (u32, u32),
+    /// With `keep_colors`, the color of each cell.
+    colors: Option<RgbImage>,
+    /// The brightness each cell was mapped from.
+    brightness: GrayImage,
+    /// Why the art came out smaller than asked for.
+    notice: Option<String>,
 }
 
 struct AsciiConverter {
     config: AsciiConfig,
+    progress: Option<Arc<Progress>>,
 }
 
 impl AsciiConverter {
     fn new(config: AsciiConfig) -> Self {
-        Self { config }
+        Self { config, progress: None }
     }
 
-    fn load_image_from_memory(&self, buffer: &[u8]) -> Result<DynamicImage, ImageError> {
-        image::load_from_memory(buffer)
+    fn with_progress(mut self, progress: Option<Arc<Progress>>) -> Self {
+        self.progress = progress;
+        self
     }
 
-    fn resize_image(&self, img: &DynamicImage) -> DynamicImage {
-        let original_width = img.width();
-        let original_height = img.height();
-        let new_height = ((original_height as f32 * self.config.width as f32)
-            / original_width as f32 * self.config.aspect_ratio_correction)
-            .max(1.0) as u32;
-        img.resize_exact(self.config.width, new_height, image::imageops::FilterType::Lanczos3)
+    fn report(&self, stage: Stage) {
+        if let Some(progress) = &self.progress {
+            progress.set_stage(stage);
+        }
+    }
+
+    /// Decodes the upload into its frames (one for still images) and applies the
+    /// orientation, crop, rotation and flip to each.
+    fn load_frames(&self, body: UploadBody) -> Result<FrameSequence> {
+        let orientation = if self.config.apply_exif_orientation { body.exif_orientation() } else { None };
+        let animation = if self.config.animate && self.config.page == 0 {
+            body.decode_animation().context("Failed to decode animation")?
+        } else {
+            None
+        };
+        let sequence = match animation {
+            Some(sequence) => sequence,
+            None => {
+                if self.config.page > 0 {
+                    let pages = body.page_count().context("Failed to read the page count")?;
+                    if self.config.page >= pages {
+                        bail!("Page {} was requested, but the image has {} page(s)", self.config.page + 1, pages);
+                    }
+                }
+                FrameSequence::still(body.decode(self.config.page).context("Failed to decode image")?)
+            }
+        };
+
+        let sequence = sequence.map_images(|mut img| {
+            if let Some(orientation) = orientation {
+                img = preprocess::apply_exif_orientation(img, orientation);
+            }
+            if let Some(crop) = self.config.crop {
+                img = preprocess::crop(&img, crop)?;
+            }
+            Ok(preprocess::rotate_and_flip(img, self.config.rotate, self.config.flip))
+        })?;
+        self.report(Stage::Decoded);
+        Ok(sequence)
+    }
+
+    fn target_dimensions(&self, original_width: u32, original_height: u32) -> (u32, u32) {
+        png_to_ascii::output_size(original_width, original_height, self.config.width, self.config.aspect_ratio_correction)
+    }
+
+    /// Resizes to the output size, sampling `cell_size` pixels per character.
+    fn resize_image(&self, img: &DynamicImage, (cell_width, cell_height): (u32, u32)) -> DynamicImage {
+        let (columns, rows) = self.target_dimensions(img.width(), img.height());
+        img.resize_exact(columns * cell_width, rows * cell_height, image::imageops::FilterType::Lanczos3)
+    }
+
+    /// The mapper for the configured mode.
+    fn mapper(&self) -> Box<dyn CharMapper> {
+        let config = &self.config;
+        config.mode.mapper(&config.character_set, config.invert_mapping, config.threshold, config.dither)
+    }
+
+    fn row_progress(&self) -> Option<&dyn RowProgress> {
+        self.progress.as_deref().map(|progress| progress as &dyn RowProgress)
     }
 
-    fn pixel_to_ascii(&self, brightness: u8) -> char {
-        let char_count = self.config.character_set.len();
-        let mut char_index = (brightness as f32 / 255.0 * (char_count - 1) as f32).round() as usize;
-        if self.config.invert_mapping {
-            char_index = char_count - 1 - char_index;
+    /// The grid full-resolution art of `img` is scaled down to, keeping its shape, and a
+    /// notice saying so, when it would have more characters than `max_cells`.
+    fn full_resolution_limit(&self, img: &DynamicImage, (cell_width, cell_height): (u32, u32)) -> Option<((u32, u32), String)> {
+        let max_cells = self.config.max_cells?;
+        let (columns, rows) = (img.width() / cell_width, img.height() / cell_height);
+        let cells = u64::from(columns) * u64::from(rows);
+        if cells <= max_cells {
+            return None;
         }
-        self.config.character_set[char_index]
+        let scale = (max_cells as f64 / cells as f64).sqrt();
+        let scaled = |cells: u32| ((f64::from(cells) * scale) as u32).max(1);
+        let (scaled_columns, scaled_rows) = (scaled(columns), scaled(rows));
+        tracing::info!(columns, rows, scaled_columns, scaled_rows, "downscaling full-resolution art");
+        let notice = format!(
+            "At full resolution the art would be {}x{} characters; it was scaled down to {}x{} to stay within {} characters.",
+            columns, rows, scaled_columns, scaled_rows, max_cells
+        );
+        Some(((scaled_columns, scaled_rows), notice))
     }
 
-    fn convert_to_ascii(&self, img: &DynamicImage) -> (String, (u32, u32)) {
-        let source_img = if self.config.use_full_resolution {
-            println!("Using full resolution ({}x{})", img.width(), img.height());
-            img.clone()
+    fn convert_to_ascii(&self, img: &DynamicImage) -> ConvertedImage {
+        let flattened;
+        let img = if self.config.transparency == Transparency::Background && img.color().has_alpha() {
+            let background = render::parse_hex_color(&self.config.background_color).unwrap_or(Rgb([0, 0, 0]));
+            flattened = preprocess::flatten_alpha(img, background);
+            &flattened
         } else {
-            println!("Resizing image to width: {}", self.config.width);
-            self.resize_image(img)
+            img
         };
 
-        let gray_img = source_img.to_luma8();
-        let (width, height) = gray_img.dimensions();
-        let capacity = (width * height + height) as usize;
-        let mut ascii_art = String::with_capacity(capacity);
-
-        for y in 0..height {
-            for x in 0..width {
-                let brightness = gray_img.get_pixel(x, y)[0];
-                ascii_art.push(self.pixel_to_ascii(brightness));
+        // Mappers that draw several pixels per character get the image sampled that much finer.
+        let mapper = self.mapper();
+        let cell_size = mapper.cell_size();
+        let mut notice = None;
+        let source_img = if self.config.use_full_resolution {
+            tracing::debug!(width = img.width(), height = img.height(), "using full resolution");
+            match self.full_resolution_limit(img, cell_size) {
+                Some(((columns, rows), limit)) => {
+                    notice = Some(limit);
+                    let resized = img.resize_exact(
+                        columns * cell_size.0,
+                        rows * cell_size.1,
+                        image::imageops::FilterType::Lanczos3,
+                    );
+                    self.report(Stage::Resized);
+                    resized
+                }
+                None => img.clone(),
             }
-            ascii_art.push('\n');
+        } else {
+            tracing::debug!(width = self.config.width, "resizing image");
+            let resized = self.resize_image(img, cell_size);
+            self.report(Stage::Resized);
+            resized
+        };
+
+        let mut gray_img = preprocess::grayscale(&source_img, self.config.grayscale);
+        if let Some(method) = self.config.local_threshold {
+            gray_img = preprocess::adaptive_threshold(&gray_img, method, self.config.adaptive_block, self.config.adaptive_offset);
         }
-        (ascii_art, (width, height))
+        if self.config.line_art {
+            let bright_lines = !self.config.invert_mapping;
+            gray_img = preprocess::line_art(&gray_img, self.config.line_sigma, self.config.line_threshold, bright_lines);
+        }
+        let rgb_img = (self.config.keep_colors || mapper.needs_colors()).then(|| source_img.to_rgb8());
+        let mask = (self.config.transparency == Transparency::Blank && source_img.color().has_alpha())
+            .then(|| preprocess::opacity_mask(&source_img, self.config.alpha_threshold));
+        let pixels = Pixels::new(&gray_img, rgb_img.as_ref(), mask.as_ref());
+        let art = mapper.map(&pixels, self.row_progress());
+        let dimensions = pixels.grid_size(cell_size);
+        let colors = rgb_img.filter(|_| self.config.keep_colors).map(|rgb_img| {
+            if cell_size == (1, 1) {
+                rgb_img
+            } else {
+                image::imageops::resize(&rgb_img, dimensions.0, dimensions.1, image::imageops::FilterType::Triangle)
+            }
+        });
+        let brightness = if cell_size == (1, 1) {
+            gray_img
+        } else {
+            image::imageops::resize(&gray_img, dimensions.0, dimensions.1, image::imageops::FilterType::Triangle)
+        };
+        ConvertedImage { art, dimensions, colors, brightness, notice }
     }
-}
-
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#39;")
-}
 
-fn generate_html_viewer(
-    ascii_art: &str,
-    dimensions: (u32, u32),
-    bg_color: &str,
-    txt_color: &str,
-) -> String {
-    let escaped_art = html_escape(ascii_art);
-    let (art_width, art_height) = dimensions;
-    format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>ASCII Art Viewer</title>
-    <style>
-        html, body {{ margin: 0; padding: 0; width: 100%; height: 100%; display: flex; justify-content: center; align-items: center; background-color: {bg_color}; overflow: hidden; }}
-        pre {{ color: {txt_color}; font-family: 'Courier New', Courier, monospace; white-space: pre; font-size: 10px; line-height: 0.8em; }}
-    </style>
-</head>
-<body>
-<pre id="ascii-art">{escaped_art}</pre>
-<script>
-    (function() {{
-        const artElement = document.getElementById('ascii-art');
-        const artCols = {art_width}; const artRows = {art_height};
-        const FONT_ASPECT_RATIO = 0.6;
-        function resizeArt() {{
-            const fontSizeForWidth = (window.innerWidth / artCols) * FONT_ASPECT_RATIO;
-            const fontSizeForHeight = window.innerHeight / artRows;
-            artElement.style.fontSize = Math.min(fontSizeForWidth, fontSizeForHeight) + 'px';
-        }}
-        window.addEventListener('resize', resizeArt);
-        document.addEventListener('DOMContentLoaded', resizeArt);
-    }})();
-</script>
-</body>
-</html>"#,
-        bg_color = bg_color,
-        txt_color = txt_color,
-        escaped_art = escaped_art,
-        art_width = art_width,
-        art_height = art_height
-    )
+    /// Maps an image already sized to the output with the configured mode; pixels that
+    /// are 0 in `mask` become blanks. Emoji mode picks from the colors in `rgb_img`.
+    fn render_rows(&self, gray_img: &GrayImage, mask: Option<&GrayImage>, rgb_img: Option<&RgbImage>) -> String {
+        self.mapper().map(&Pixels::new(gray_img, rgb_img, mask), self.row_progress())
+    }
 }
 
 // --- WEB SERVER LOGIC ---
 
-#[get("/")]
-async fn index() -> impl Responder {
-    HttpResponse::Ok()
-        .content_type("text/html; charset=utf-8")
-        .body(include_str!("index.html"))
-}
+/// Runs a conversion on the blocking pool once `limiter` has a slot for it, serving and
+/// populating the result cache. Fails with [`Overloaded`] when turned away.
+async fn convert_cached(
+    image: UploadBody,
+    options: &ConversionOptions,
+    cache: &ResultCache,
+    limiter: &ConversionLimiter,
+    priority: Priority,
+    progress: Option<Arc<Progress>>,
+) -> Result<Arc<ConversionOutput>> {
+    let cache_key = options.cache_key(image.digest());
+    if let Some(output) = cache.get(&cache_key) {
+        tracing::debug!(hits = cache.hits(), misses = cache.misses(), "serving cached result");
+        if let Some(progress) = &progress {
+            progress.set_stage(Stage::Done);
+        }
+        return Ok(output);
+    }
 
-#[post("/upload")]
-async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
-    let mut image_data: Option<Vec<u8>> = None;
-    let mut theme = ColorTheme::Dark;
-    let mut detailed = false;
-    let mut full_resolution = false;
-    let mut original_filename = "image".to_string();
-
-    while let Some(item) = payload.next().await {
-        let mut field = item?;
-        let content_disposition = field.content_disposition();
-        let field_name = content_disposition.get_name().unwrap_or_default();
-
-        match field_name {
-            "image" => {
-                original_filename = sanitize(content_disposition.get_filename().unwrap_or("image.png"));
-                let mut data = Vec::new();
-                while let Some(chunk) = field.next().await { data.extend_from_slice(&chunk?); }
-                if !data.is_empty() { image_data = Some(data); }
-            }
-            "theme" => {
-                let mut data = Vec::new();
-                while let Some(chunk) = field.next().await { data.extend_from_slice(&chunk?); }
-                if String::from_utf8(data).unwrap_or_default() == "light" { theme = ColorTheme::Light; }
-            }
-            "detailed" => {
-                let mut data = Vec::new();
-                while let Some(chunk) = field.next().await { data.extend_from_slice(&chunk?); }
-                if String::from_utf8(data).unwrap_or_default() == "true" { detailed = true; }
-            }
-            "full_resolution" => {
-                let mut data = Vec::new();
-                while let Some(chunk) = field.next().await { data.extend_from_slice(&chunk?); }
-                if String::from_utf8(data).unwrap_or_default() == "true" { full_resolution = true; }
+    let slot = match limiter.acquire(priority).await {
+        Ok(slot) => slot,
+        Err(overloaded) => {
+            METRICS.errors.with_label_values(&["overloaded"]).inc();
+            return Err(overloaded.into());
+        }
+    };
+    METRICS.upload_bytes.observe(image.len() as f64);
+    let labels = options.metric_labels();
+    let started = Instant::now();
+
+    // Decoding, resizing and the per-pixel loop are CPU-bound, so run them on the
+    // blocking thread pool instead of stalling the actix worker.
+    let config = options.ascii_config();
+    let summary = options.summary();
+    let overlay = options.overlay.clone();
+    let block_progress = progress.clone();
+    let conversion = web::block(move || -> Result<ConversionOutput> {
+        // Held until the work is done, even if the request is cancelled meanwhile.
+        let _slot = slot;
+        let mut converter = AsciiConverter::new(config).with_progress(block_progress);
+        let sequence = converter.load_frames(image)?;
+        let img = &sequence.frames[0].image;
+        METRICS.image_dimensions.with_label_values(&["width"]).observe(img.width() as f64);
+        METRICS.image_dimensions.with_label_values(&["height"]).observe(img.height() as f64);
+        let ConvertedImage { art: ascii_art, dimensions, colors, brightness, notice } = converter.convert_to_ascii(img);
+
+        let mut frames = Vec::new();
+        if sequence.is_animated() {
+            // The progress bar follows the first frame only.
+            converter.progress = None;
+            frames.push(AsciiFrame { art: ascii_art.clone(), delay: sequence.frames[0].delay });
+            for frame in &sequence.frames[1..] {
+                let art = converter.convert_to_ascii(&frame.image).art;
+                frames.push(AsciiFrame { art, delay: frame.delay });
             }
-            _ => (),
         }
+        let config = converter.config;
+        let mut output = ConversionOutput {
+            ascii_art,
+            dimensions,
+            colors,
+            brightness,
+            notice,
+            charset: config.character_set.iter().collect(),
+            options: summary,
+            frames,
+            mode: config.mode,
+            background_color: config.background_color,
+            text_color: config.text_color,
+            font: config.font,
+        };
+        overlay.apply(&mut output)?;
+        Ok(output)
+    })
+    .await;
+
+    let conversion = match conversion {
+        Ok(Ok(output)) => {
+            METRICS.conversions.with_label_values(&labels).inc();
+            METRICS.conversion_duration.with_label_values(&labels).observe(started.elapsed().as_secs_f64());
+            Ok(output)
+        }
+        Ok(Err(err)) => {
+            METRICS.errors.with_label_values(&["decode"]).inc();
+            Err(err)
+        }
+        Err(err) => {
+            METRICS.errors.with_label_values(&["interrupted"]).inc();
+            Err(anyhow::Error::new(err).context("Conversion was interrupted"))
+        }
+    };
+
+    if let Some(progress) = &progress {
+        progress.set_stage(if conversion.is_ok() { Stage::Done } else { Stage::Failed });
     }
+    let output = Arc::new(conversion?);
+    cache.insert(cache_key, output.clone());
+    Ok(output)
+}
+
+/// Strips the extension from an uploaded filename, falling back to "image".
+fn filename_stem(filename: &str) -> String {
+    PathBuf::from(filename)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("image")
+        .to_string()
+}
 
-    let image_data = match image_data {
+#[post("/upload")]
+#[allow(clippy::too_many_arguments)]
+async fn upload(
+    session: Session,
+    mut payload: Multipart,
+    server_config: web::Data<ServerConfig>,
+    recipes: web::Data<RecipeStore>,
+    cache: web::Data<ResultCache>,
+    progress_registry: web::Data<ProgressRegistry>,
+    originals: web::Data<OriginalStore>,
+    limiter: web::Data<ConversionLimiter>,
+    history: web::Data<History>,
+) -> Result<HttpResponse, Error> {
+    let mut form = UploadForm::read(&mut payload, &server_config, &recipes).await?;
+    if let Some(id) = form.rerun.take().filter(|_| form.image.is_none()) {
+        let Some(mut uploads) = history.uploads(&session, &id) else {
+            return Ok(HttpResponse::NotFound().body("That conversion is no longer in your history."));
+        };
+        let (filename, body) = uploads.remove(0);
+        (form.filename, form.image, form.more_images) = (filename, Some(body), uploads);
+    }
+    let mut image_data = match form.image.take() {
         Some(data) => data,
         None => return Ok(HttpResponse::BadRequest().body("No image uploaded.")),
     };
+    let collage = !form.more_images.is_empty();
+    // A collage is a single result of every image's first page.
+    let all_pages = form.all_pages && !collage;
+    // The slider lays the upload over its art, which only lines up when the art shows
+    // all of it the way a browser would.
+    let original_id = if !all_pages && !collage && form.options.shows_whole_upload() {
+        let body = image_data.clone();
+        web::block(move || originals.store(body)).await?
+    } else {
+        None
+    };
 
-    let (bg_color, txt_color, invert_mapping) = match theme {
-        ColorTheme::Dark => ("#1a1a1a", "#e0e0e0", false),
-        ColorTheme::Light => ("#f0f0f0", "#111111", true),
+    let page_count = if all_pages {
+        let (pages, body) = web::block(move || (image_data.page_count(), image_data)).await?;
+        image_data = body;
+        match pages {
+            Ok(pages) => pages.min(multipage::MAX_PAGES),
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("Failed to read the page count: {}", err))),
+        }
+    } else {
+        1
     };
 
-    let char_string = if detailed { DETAILED_CHARS } else { SIMPLE_CHARS };
-    let character_set = char_string.chars().collect();
-
-    let config = AsciiConfig {
-        width: 150, // Default width if not full resolution
-        use_full_resolution: full_resolution,
-        character_set,
-        invert_mapping,
-        aspect_ratio_correction: 0.5,
-        background_color: bg_color.to_string(),
-        text_color: txt_color.to_string(),
+    // Kept for the history, so the upload can be converted again with other options.
+    let uploads = if history.is_enabled() {
+        let mut images = vec![(form.filename.clone(), image_data.clone())];
+        for (filename, body) in &form.more_images {
+            images.push((filename.clone(), body.clone()));
+        }
+        web::block(move || History::keep_uploads(images)).await?
+    } else {
+        None
     };
 
-    let converter = AsciiConverter::new(config);
-    let img = converter.load_image_from_memory(&image_data).context("Failed to decode image").unwrap();
-    let (ascii_art, dimensions) = converter.convert_to_ascii(&img);
-    let html_viewer = generate_html_viewer(&ascii_art, dimensions, bg_color, txt_color);
-
-    let filename_base = PathBuf::from(&original_filename).file_stem().unwrap().to_str().unwrap().to_string();
-    let txt_filename = format!("{}.txt", filename_base);
-    let html_filename = format!("{}.html", filename_base);
-
-    let result_html = format!(
-        r#"
-        <!DOCTYPE html>
-        <html lang="en">
-        <head>
-            <meta charset="UTF-8">
-            <title>ASCII Art Result</title>
-            <style>
-                body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif; background-color: #f0f2f5; margin: 0; padding: 20px; text-align: center; }}
-                h1 {{ color: #333; }}
-                .container {{ max-width: 1200px; margin: 0 auto; background: #fff; border-radius: 8px; box-shadow: 0 4px 8px rgba(0,0,0,0.1); padding: 20px; }}
-                .preview-container {{ width: 100%; height: 70vh; border: 1px solid #ddd; margin-top: 20px; border-radius: 8px; overflow: hidden; }}
-                .download-links {{ margin-top: 20px; }}
-                .download-links a {{ display: inline-block; padding: 12px 24px; background-color: #007bff; color: white; text-decoration: none; border-radius: 5px; margin: 0 10px; font-weight: bold; transition: background-color 0.2s; }}
-                .download-links a:hover {{ background-color: #0056b3; }}
-                a.home-link {{ display: inline-block; margin-top: 20px; color: #007bff; }}
-            </style>
-        </head>
-        <body>
-            <div class="container">
-                <h1>Your ASCII Art is Ready!</h1>
-                <div class="preview-container">
-                    <iframe srcdoc="{}" style="width:100%; height:100%; border:0;"></iframe>
-                </div>
-                <div class="download-links">
-                    <a href="data:text/plain;charset=utf-8,{}" download="{}">Download .txt File</a>
-                    <a href="data:text/html;charset=utf-8,{}" download="{}">Download .html Viewer</a>
-                </div>
-                <a href="/" class="home-link">Convert another image</a>
-            </div>
-        </body>
-        </html>
-        "#,
-        html_escape(&html_viewer),
-        url_escape::encode_component(&ascii_art),
-        txt_filename,
-        url_escape::encode_component(&html_viewer),
-        html_filename
-    );
+    let filename_base = if collage { "collage".to_string() } else { filename_stem(&form.filename) };
+    let mut progress = form.job_id.take().map(|id| progress_registry.tracker(&id));
+    let mut sections = Vec::with_capacity(page_count);
+    let mut output_bytes = 0;
+    for page in 0..page_count {
+        let mut options = form.options.clone();
+        let mut stem = filename_base.clone();
+        if all_pages {
+            options.page = page;
+            stem = format!("{}-page{}", filename_base, page + 1);
+        }
+        // The progress bar follows the last page; earlier ones would end its stream early.
+        let page_progress = if page + 1 == page_count { progress.take() } else { None };
+        let converted = if collage {
+            let images = form.take_images(image_data.clone());
+            collage::convert(images, &options, &form.collage, &cache, &limiter, Priority::Interactive, page_progress).await
+        } else {
+            convert_cached(image_data.clone(), &options, &cache, &limiter, Priority::Interactive, page_progress).await
+        };
+        let output = match converted {
+            Ok(output) => output,
+            Err(err) if err.is::<Overloaded>() => return Ok(concurrency::overloaded()),
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{:#}", err))),
+        };
+        output_bytes += output.ascii_art.len();
+        history.record(&session, stem.clone(), output.clone(), uploads.clone());
+        let heading = all_pages.then(|| format!("Page {} of {}", page + 1, page_count));
+        let section = match ResultSection::new(heading, &output, stem, original_id.as_deref()) {
+            Ok(section) => section,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{:#}", err))),
+        };
+        sections.push(section);
+    }
+    logging::record_conversion(&form.options, output_bytes);
 
-    Ok(HttpResponse::Ok().content_type("text/html").body(result_html))
+    let result_html = ResultPage { sections }.render().map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut response = HttpResponse::Ok();
+    if history.is_enabled() {
+        response.cookie(history.cookie(&session));
+    }
+    Ok(response.content_type("text/html").body(result_html))
+}
+
+/// Context of `templates/result.html`, the page `/upload` answers with.
+#[derive(Template)]
+#[template(path = "result.html")]
+struct ResultPage {
+    sections: Vec<ResultSection>,
+}
+
+/// The preview and download links for one result on the upload page.
+struct ResultSection {
+    heading: Option<String>,
+    text: String,
+    viewer: String,
+    filename_stem: String,
+    notice: Option<String>,
+    comparison: Option<Comparison>,
+}
+
+/// The upload and its art drawn at the same size, for the comparison slider.
+struct Comparison {
+    original_url: String,
+    /// The art as an SVG data URI.
+    art_url: String,
+}
+
+impl ResultSection {
+    fn new(heading: Option<String>, output: &ConversionOutput, filename_stem: String, original_id: Option<&str>) -> Result<Self> {
+        let grid = AsciiGrid::new(output);
+        let viewer = output::Html.render(&grid)?.into_text();
+        let comparison = original_id.filter(|_| output::Svg.supports(output.mode)).map(|id| {
+            let svg = output::Svg.render(&grid)?;
+            Ok::<_, anyhow::Error>(Comparison {
+                original_url: format!("/originals/{}", id),
+                art_url: format!("data:image/svg+xml;base64,{}", base64::engine::general_purpose::STANDARD.encode(svg.body)),
+            })
+        });
+        Ok(Self {
+            heading,
+            text: output.ascii_art.clone(),
+            viewer,
+            filename_stem,
+            notice: output.notice.clone(),
+            comparison: comparison.transpose()?,
+        })
+    }
+}
+
+#[get("/progress/{job_id}")]
+async fn progress_events(
+    job_id: web::Path<String>,
+    progress_registry: web::Data<ProgressRegistry>,
+) -> impl Responder {
+    let job_id = job_id.into_inner();
+    if !ProgressRegistry::is_valid_id(&job_id) {
+        return HttpResponse::BadRequest().body("Invalid job ID.");
+    }
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        // A compressor would hold events back until its buffer fills.
+        .insert_header(header::ContentEncoding::Identity)
+        .streaming(progress_registry.into_inner().events(job_id))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("Starting server at http://127.0.0.1:8080");
-    HttpServer::new(|| {
+    logging::init();
+    let server_config = ServerConfig::from_env();
+    let cache = web::Data::new(ResultCache::new(server_config.cache_capacity, server_config.cache_ttl));
+    let progress_registry = web::Data::new(ProgressRegistry::default());
+    let limiter = web::Data::new(ConversionLimiter::new(server_config.max_conversions, server_config.conversion_backlog));
+    let job_queue = JobQueue::start(&server_config, cache.clone(), limiter.clone());
+    let rate_limiter = Arc::new(
+        RateLimiter::new(
+            server_config.rate_limit_per_minute,
+            server_config.max_concurrent_per_ip,
+            server_config.trust_proxy_headers,
+        )
+        .with_trusted_proxies(server_config.trusted_proxies.as_deref().unwrap_or(rate_limit::DEFAULT_TRUSTED_PROXIES)),
+    );
+    let api_keys = ApiKeyStore::from_config(&server_config)
+        .map_err(|err| std::io::Error::other(format!("{:#}", err)))?;
+    let guarded_routes: &'static [&'static str] =
+        if server_config.api_keys_everywhere { &api_auth::CONVERSION_ROUTES } else { &[] };
+    if api_keys.is_enabled() {
+        tracing::info!(also = ?guarded_routes, "API key authentication enabled for /api routes");
+    }
+    let api_keys = Arc::new(api_keys);
+    let originals = web::Data::new(OriginalStore::new(server_config.original_retention));
+    let history = web::Data::new(History::new(server_config.history_length, server_config.history_retention));
+    let recipes = web::Data::new(
+        RecipeStore::open(server_config.recipes_file.clone())
+            .map_err(|err| std::io::Error::other(format!("{:#}", err)))?,
+    );
+
+    let tls_config = match (&server_config.tls_cert, &server_config.tls_key) {
+        (Some(cert), Some(key)) => Some(
+            tls::load_server_config(cert, key).map_err(|err| std::io::Error::other(format!("{:#}", err)))?,
+        ),
+        (None, None) => None,
+        _ => return Err(std::io::Error::other("ASCII_TLS_CERT and ASCII_TLS_KEY must be set together")),
+    };
+    let listener = Listener::from_config(&server_config)?;
+    if matches!(listener, Listener::Unix { .. }) && rate_limiter.is_enabled() && !server_config.trust_proxy_headers {
+        return Err(std::io::Error::other(
+            "Clients on a Unix socket have no IP address; set ASCII_TRUST_PROXY_HEADERS=true for the per-IP limits",
+        ));
+    }
+    let socket_path = listener.socket_path().map(PathBuf::from);
+    let tls_bind_address = server_config.tls_bind_address.clone();
+    let shutdown_grace = server_config.shutdown_grace;
+    let shutdown_queue = job_queue.clone();
+    let server_config = web::Data::new(server_config);
+
+    let server = HttpServer::new(move || {
         App::new()
-            .service(index)
+            .wrap(middleware::Compress::default())
+            .wrap(ApiKeyAuth::for_paths(api_keys.clone(), guarded_routes))
+            .wrap(RateLimit::new(rate_limiter.clone()))
+            .wrap(TracingLogger::<RequestSpan>::new())
+            .app_data(server_config.clone())
+            .app_data(cache.clone())
+            .app_data(progress_registry.clone())
+            .app_data(job_queue.clone())
+            .app_data(recipes.clone())
+            .app_data(originals.clone())
+            .app_data(history.clone())
+            .app_data(limiter.clone())
+            .configure(|cfg| assets::configure(cfg, &server_config.static_dir))
             .service(upload)
+            .service(original::original_upload)
+            .configure(history::configure)
+            .service(plain::plain)
+            .service(progress_events)
+            .service(metrics::metrics)
+            .service(health::healthz)
+            .service(health::readyz)
+            .service(
+                web::scope("/jobs")
+                    .wrap(cors::api_cors(&server_config))
+                    .configure(jobs::configure),
+            )
+            // Registered ahead of the `/api` scope so they bypass its API key check.
+            .service(openapi::spec)
+            .service(openapi::docs)
+            .service(
+                web::scope("/api")
+                    .wrap(ApiKeyAuth::new(api_keys.clone()))
+                    .wrap(cors::api_cors(&server_config))
+                    .configure(api::configure)
+                    .configure(recipe::configure),
+            )
+            .service(stream::ws_stream)
     })
-        .bind(("127.0.0.1", 8080))?
-        .run()
-        .await
+    .shutdown_timeout(shutdown_grace.as_secs());
+
+    match tls_config {
+        None => {
+            tracing::info!("Starting server at {}", listener);
+            let server = match listener {
+                Listener::Address(address) => server.bind(&address)?,
+                Listener::Tcp(tcp) => server.listen(tcp)?,
+                Listener::Unix { listener, .. } => server.listen_uds(listener)?,
+            };
+            server.run().await?;
+        }
+        Some(tls_config) => {
+            // With TLS enabled the plain HTTP listener only redirects to HTTPS.
+            let https_server = server.bind_rustls_0_23(&tls_bind_address, tls_config)?;
+            let https_port = web::Data::new(tls::HttpsPort(
+                https_server.addrs().first().map_or(443, |addr| addr.port()),
+            ));
+            let redirect_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(https_port.clone())
+                    .default_service(web::to(tls::redirect_to_https))
+            })
+            .shutdown_timeout(shutdown_grace.as_secs());
+
+            tracing::info!("Starting server at https://{} (redirecting {})", tls_bind_address, listener);
+            let redirect_server = match listener {
+                Listener::Address(address) => redirect_server.bind(&address)?,
+                Listener::Tcp(tcp) => redirect_server.listen(tcp)?,
+                Listener::Unix { listener, .. } => redirect_server.listen_uds(listener)?,
+            };
+            futures_util::future::try_join(https_server.run(), redirect_server.run()).await?;
+        }
+    }
+
+    // On SIGTERM/SIGINT actix stops accepting connections and lets in-flight requests
+    // finish within the grace period; background jobs get the same allowance afterwards.
+    tracing::info!("Server stopped, draining background jobs");
+    shutdown_queue.drain(shutdown_grace).await;
+    if let Some(path) = socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metric_labels_follow_the_mode() {
+        assert_eq!(ConversionOptions::default().metric_labels(), ["ascii", "simple", "resized"]);
+        let options = ConversionOptions { mode: Mode::Braille, detailed: true, full_resolution: true, ..Default::default() };
+        assert_eq!(options.metric_labels(), ["braille", "detailed", "full"]);
+    }
 }
@@ -0,0 +1,455 @@
+use crate::render;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+
+// --- PIXEL-TO-CHARACTER MAPPERS ---
+
+/// Sobel gradient magnitude above which a pixel counts as an edge in [`Edges`].
+const EDGE_THRESHOLD: f32 = 160.0;
+
+/// The sampled image a mapper turns into text, [`CharMapper::cell_size`] pixels per cell.
+pub struct Pixels<'a> {
+    pub gray: &'a GrayImage,
+    /// The colors, for mappers that ask for them.
+    pub rgb: Option<&'a RgbImage>,
+    /// 0 where the pixel is transparent.
+    mask: Option<&'a GrayImage>,
+    /// Mean brightness, which the two-tone mappers split the pixels at.
+    pub mean: u8,
+    /// Otsu's threshold: the brightness that best separates the dark and bright pixels.
+    pub otsu: u8,
+}
+
+impl<'a> Pixels<'a> {
+    pub fn new(gray: &'a GrayImage, rgb: Option<&'a RgbImage>, mask: Option<&'a GrayImage>) -> Self {
+        let mut histogram = [0u64; 256];
+        for pixel in gray.pixels() {
+            histogram[pixel[0] as usize] += 1;
+        }
+        let count = u64::from(gray.width() * gray.height());
+        let total: u64 = histogram.iter().enumerate().map(|(level, &pixels)| level as u64 * pixels).sum();
+        let mean = (total / count.max(1)) as u8;
+        Self { gray, rgb, mask, mean, otsu: otsu(&histogram, count) }
+    }
+
+    /// Columns and rows of cells of `cell_size` pixels; partial cells at the edges count.
+    pub fn grid_size(&self, (cell_width, cell_height): (u32, u32)) -> (u32, u32) {
+        (self.gray.width().div_ceil(cell_width), self.gray.height().div_ceil(cell_height))
+    }
+
+    /// Whether (`x`, `y`) lies inside the image and is not transparent.
+    pub fn is_opaque(&self, x: u32, y: u32) -> bool {
+        x < self.gray.width() && y < self.gray.height() && self.mask.is_none_or(|mask| mask.get_pixel(x, y)[0] != 0)
+    }
+
+    /// Brightness at (`x`, `y`), repeating the edge pixels outside the image.
+    pub fn brightness(&self, x: i64, y: i64) -> u8 {
+        let x = x.clamp(0, i64::from(self.gray.width()) - 1) as u32;
+        let y = y.clamp(0, i64::from(self.gray.height()) - 1) as u32;
+        self.gray.get_pixel(x, y)[0]
+    }
+
+    /// Two-tone ink: opaque pixels brighter than the mean (darker with `invert`).
+    pub fn inked(&self, x: u32, y: u32, invert: bool) -> bool {
+        if !self.is_opaque(x, y) {
+            return false;
+        }
+        let brightness = self.gray.get_pixel(x, y)[0];
+        if invert { brightness < self.mean } else { brightness > self.mean }
+    }
+
+    fn is_blank(&self, x: u32, y: u32, (cell_width, cell_height): (u32, u32)) -> bool {
+        self.mask.is_some() && (y..y + cell_height).all(|y| (x..x + cell_width).all(|x| !self.is_opaque(x, y)))
+    }
+}
+
+/// Receives row counts while a mapper runs, for progress reporting.
+pub trait RowProgress: Sync {
+    fn start_rows(&self, total: u32);
+    fn row_done(&self);
+}
+
+/// The level with the greatest between-class variance when the histogram is split into
+/// the pixels at or below it and those above it.
+fn otsu(histogram: &[u64; 256], count: u64) -> u8 {
+    let total: f64 = histogram.iter().enumerate().map(|(level, &pixels)| level as f64 * pixels as f64).sum();
+    let (mut dark_count, mut dark_total) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (level, &pixels) in histogram.iter().enumerate() {
+        dark_count += pixels as f64;
+        dark_total += level as f64 * pixels as f64;
+        let bright_count = count as f64 - dark_count;
+        if dark_count == 0.0 || bright_count == 0.0 {
+            continue;
+        }
+        let mean_gap = dark_total / dark_count - (total - dark_total) / bright_count;
+        let variance = dark_count * bright_count * mean_gap * mean_gap;
+        if variance > best_variance {
+            (best, best_variance) = (level as u8, variance);
+        }
+    }
+    best
+}
+
+/// A strategy for drawing the image with characters, selected by the `mode` option.
+pub trait CharMapper: Sync {
+    /// Pixels sampled per character, horizontally and vertically.
+    fn cell_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    /// Whether [`Pixels::rgb`] must be present.
+    fn needs_colors(&self) -> bool {
+        false
+    }
+
+    /// Character for a cell whose pixels are all transparent.
+    fn blank(&self) -> char {
+        ' '
+    }
+
+    /// The character for the cell whose top-left pixel is (`x`, `y`).
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char;
+
+    /// Maps every cell, one row per rayon task. Mappers whose cells depend on each other
+    /// override this.
+    fn map(&self, pixels: &Pixels, progress: Option<&dyn RowProgress>) -> String {
+        map_cells(self, pixels, progress)
+    }
+}
+
+/// The default [`CharMapper::map`], for overrides that only sometimes replace it.
+fn map_cells<M: CharMapper + ?Sized>(mapper: &M, pixels: &Pixels, progress: Option<&dyn RowProgress>) -> String {
+    let cell_size @ (cell_width, cell_height) = mapper.cell_size();
+    let (columns, rows) = pixels.grid_size(cell_size);
+    if let Some(progress) = progress {
+        progress.start_rows(rows);
+    }
+    let lines: Vec<String> = (0..rows)
+        .into_par_iter()
+        .map(|row| {
+            let mut line = String::with_capacity(columns as usize * 3 + 1);
+            for column in 0..columns {
+                let (x, y) = (column * cell_width, row * cell_height);
+                line.push(if pixels.is_blank(x, y, cell_size) { mapper.blank() } else { mapper.map_cell(pixels, x, y) });
+            }
+            line.push('\n');
+            if let Some(progress) = progress {
+                progress.row_done();
+            }
+            line
+        })
+        .collect();
+    lines.concat()
+}
+
+/// One character per pixel from a ramp ordered from the least to the most ink.
+pub struct Ramp {
+    chars: Vec<char>,
+    /// Bright pixels get the light end of the ramp, for dark text on a light background.
+    invert: bool,
+}
+
+impl Ramp {
+    pub fn new(chars: Vec<char>, invert: bool) -> Self {
+        Self { chars, invert }
+    }
+
+    fn steps(&self) -> usize {
+        self.chars.len() - 1
+    }
+
+    /// The character `level` steps up the ramp, from the background side.
+    fn at(&self, level: usize) -> char {
+        self.chars[if self.invert { self.steps() - level } else { level }]
+    }
+
+    fn level(&self, brightness: u8) -> char {
+        self.at((brightness as f32 / 255.0 * self.steps() as f32).round() as usize)
+    }
+}
+
+impl CharMapper for Ramp {
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        self.level(pixels.gray.get_pixel(x, y)[0])
+    }
+}
+
+/// The ramp with Floyd-Steinberg error diffusion, so smooth gradients keep their tone
+/// instead of breaking into bands.
+pub struct Dithered(pub Ramp);
+
+impl CharMapper for Dithered {
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        self.0.map_cell(pixels, x, y)
+    }
+
+    fn map(&self, pixels: &Pixels, progress: Option<&dyn RowProgress>) -> String {
+        let ramp = &self.0;
+        let steps = ramp.steps() as f32;
+        diffuse(pixels, progress, self.blank(), |wanted| {
+            let level = (wanted / 255.0 * steps).round().clamp(0.0, steps);
+            (ramp.at(level as usize), level * 255.0 / steps)
+        })
+    }
+}
+
+/// Where [`Stencil`] splits the pixels into ink and background.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Threshold {
+    /// Otsu's method, from the image's own histogram.
+    #[default]
+    Otsu,
+    /// Pixels brighter than this are bright.
+    Fixed(u8),
+}
+
+impl Threshold {
+    /// Parses `otsu` (or nothing) or a brightness from 0 to 255.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" | "otsu" | "auto" => Some(Threshold::Otsu),
+            level => level.parse().ok().map(Threshold::Fixed),
+        }
+    }
+}
+
+/// Two tones: the ends of the ramp, split at a brightness threshold, for stencils and
+/// logos. With `dither` the error is diffused instead, for a halftone.
+pub struct Stencil {
+    background: char,
+    ink: char,
+    threshold: Threshold,
+    dither: bool,
+    invert: bool,
+}
+
+impl Stencil {
+    pub fn new(chars: &[char], threshold: Threshold, dither: bool, invert: bool) -> Self {
+        let (background, ink) = (chars.first().copied().unwrap_or(' '), chars.last().copied().unwrap_or('#'));
+        Self { background, ink, threshold, dither, invert }
+    }
+
+    fn level(&self, pixels: &Pixels) -> f32 {
+        f32::from(match self.threshold {
+            Threshold::Otsu => pixels.otsu,
+            Threshold::Fixed(level) => level,
+        })
+    }
+
+    fn tone(&self, bright: bool) -> char {
+        if bright != self.invert { self.ink } else { self.background }
+    }
+}
+
+impl CharMapper for Stencil {
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        self.tone(f32::from(pixels.gray.get_pixel(x, y)[0]) > self.level(pixels))
+    }
+
+    fn map(&self, pixels: &Pixels, progress: Option<&dyn RowProgress>) -> String {
+        if !self.dither {
+            return map_cells(self, pixels, progress);
+        }
+        let level = self.level(pixels);
+        diffuse(pixels, progress, self.blank(), |wanted| {
+            let bright = wanted > level;
+            (self.tone(bright), if bright { 255.0 } else { 0.0 })
+        })
+    }
+}
+
+/// Floyd-Steinberg error diffusion over every pixel: `quantize` picks the character for
+/// the wanted brightness and returns the brightness it stands for, and the difference is
+/// carried to the pixels not yet drawn. Each pixel depends on the error left by the ones
+/// before it, so rows run in order.
+fn diffuse(
+    pixels: &Pixels,
+    progress: Option<&dyn RowProgress>,
+    blank: char,
+    quantize: impl Fn(f32) -> (char, f32),
+) -> String {
+    let (width, height) = pixels.gray.dimensions();
+    if let Some(progress) = progress {
+        progress.start_rows(height);
+    }
+    let mut out = String::with_capacity((width as usize + 1) * height as usize);
+    // Error carried into the current and the next row, with a spare slot at each end.
+    let mut current = vec![0.0f32; width as usize + 2];
+    let mut next = vec![0.0f32; width as usize + 2];
+    for y in 0..height {
+        for x in 0..width {
+            if !pixels.is_opaque(x, y) {
+                out.push(blank);
+                continue;
+            }
+            let slot = x as usize + 1;
+            let wanted = f32::from(pixels.gray.get_pixel(x, y)[0]) + current[slot];
+            let (ch, drawn) = quantize(wanted);
+            let error = wanted - drawn;
+            current[slot + 1] += error * 7.0 / 16.0;
+            next[slot - 1] += error * 3.0 / 16.0;
+            next[slot] += error * 5.0 / 16.0;
+            next[slot + 1] += error / 16.0;
+            out.push(ch);
+        }
+        out.push('\n');
+        std::mem::swap(&mut current, &mut next);
+        next.fill(0.0);
+        if let Some(progress) = progress {
+            progress.row_done();
+        }
+    }
+    out
+}
+
+/// Lines drawn along strong edges (`-`, `/`, `|`, `\` by the direction of the edge), with
+/// the ramp everywhere else.
+pub struct Edges(pub Ramp);
+
+impl CharMapper for Edges {
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let at = |dx: i64, dy: i64| f32::from(pixels.brightness(x + dx, y + dy));
+        let gx = at(1, -1) + 2.0 * at(1, 0) + at(1, 1) - at(-1, -1) - 2.0 * at(-1, 0) - at(-1, 1);
+        let gy = at(-1, 1) + 2.0 * at(0, 1) + at(1, 1) - at(-1, -1) - 2.0 * at(0, -1) - at(1, -1);
+        if gx.hypot(gy) < EDGE_THRESHOLD {
+            return self.0.map_cell(pixels, x as u32, y as u32);
+        }
+        // The edge runs across the gradient; image rows grow downwards.
+        let angle = gx.atan2(gy).to_degrees().rem_euclid(180.0);
+        match angle {
+            a if !(22.5..157.5).contains(&a) => '-',
+            a if a < 67.5 => '/',
+            a if a < 112.5 => '|',
+            _ => '\\',
+        }
+    }
+}
+
+/// For each 8x8 block of pixels, the character of the ramp whose 8x8 bitmap glyph looks
+/// most like it. Characters without a bitmap glyph are left out.
+pub struct GlyphMatch {
+    glyphs: Vec<(char, [u8; 64])>,
+    invert: bool,
+}
+
+impl GlyphMatch {
+    pub fn new(chars: &[char], invert: bool) -> Self {
+        let mut glyphs: Vec<(char, [u8; 64])> = chars
+            .iter()
+            .filter_map(|&ch| {
+                let rows = render::glyph(ch)?;
+                let mut ink = [0; 64];
+                for (index, value) in ink.iter_mut().enumerate() {
+                    if rows[index / 8] & (1 << (index % 8)) != 0 {
+                        *value = 255;
+                    }
+                }
+                Some((ch, ink))
+            })
+            .collect();
+        if !glyphs.iter().any(|&(ch, _)| ch == ' ') {
+            glyphs.push((' ', [0; 64]));
+        }
+        Self { glyphs, invert }
+    }
+}
+
+impl CharMapper for GlyphMatch {
+    fn cell_size(&self) -> (u32, u32) {
+        (8, 8)
+    }
+
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        let mut ink = [0u8; 64];
+        for (index, value) in ink.iter_mut().enumerate() {
+            let (px, py) = (x + index as u32 % 8, y + index as u32 / 8);
+            if pixels.is_opaque(px, py) {
+                let brightness = pixels.gray.get_pixel(px, py)[0];
+                *value = if self.invert { 255 - brightness } else { brightness };
+            }
+        }
+        let distance = |glyph: &[u8; 64]| -> u32 {
+            ink.iter().zip(glyph).map(|(&a, &b)| u32::from(a.abs_diff(b)).pow(2)).sum()
+        };
+        self.glyphs.iter().min_by_key(|(_, glyph)| distance(glyph)).map_or(' ', |&(ch, _)| ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn ramp(invert: bool) -> Ramp {
+        Ramp::new(" .:-=+*#%@".chars().collect(), invert)
+    }
+
+    fn map(mapper: &dyn CharMapper, gray: &GrayImage) -> String {
+        mapper.map(&Pixels::new(gray, None, None), None)
+    }
+
+    #[test]
+    fn ramp_endpoints() {
+        let gray = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 0 { 0 } else { 255 }]));
+        assert_eq!(map(&ramp(true), &gray), "@ \n");
+        assert_eq!(map(&ramp(false), &gray), " @\n");
+    }
+
+    #[test]
+    fn sobel_finds_step_edges() {
+        let vertical = GrayImage::from_fn(6, 3, |x, _| Luma([if x < 3 { 0 } else { 255 }]));
+        assert_eq!(map(&Edges(ramp(false)), &vertical), "  ||@@\n  ||@@\n  ||@@\n");
+        let horizontal = GrayImage::from_fn(3, 4, |_, y| Luma([if y < 2 { 255 } else { 0 }]));
+        assert_eq!(map(&Edges(ramp(false)), &horizontal), "@@@\n---\n---\n   \n");
+    }
+
+    #[test]
+    fn glyph_match_picks_the_drawn_character() {
+        let chars: Vec<char> = " .+#@|-".chars().collect();
+        for ch in ['#', '|', '-'] {
+            let rows = render::glyph(ch).unwrap();
+            let gray = GrayImage::from_fn(8, 8, |x, y| Luma([if rows[y as usize] & (1 << x) != 0 { 255 } else { 0 }]));
+            assert_eq!(map(&GlyphMatch::new(&chars, false), &gray), format!("{ch}\n"));
+            let negative = GrayImage::from_fn(8, 8, |x, y| Luma([255 - gray.get_pixel(x, y)[0]]));
+            assert_eq!(map(&GlyphMatch::new(&chars, true), &negative), format!("{ch}\n"));
+        }
+        assert_eq!(map(&GlyphMatch::new(&chars, false), &GrayImage::new(8, 8)), " \n");
+    }
+
+    #[test]
+    fn otsu_splits_bimodal_histograms() {
+        let mut histogram = [0u64; 256];
+        for level in 40..=60 {
+            histogram[level] = 10;
+            histogram[level + 150] = 30;
+        }
+        assert_eq!(otsu(&histogram, 21 * 40), 60);
+        let gray = GrayImage::from_fn(4, 1, |x, _| Luma([[30, 35, 180, 220][x as usize]]));
+        let pixels = Pixels::new(&gray, None, None);
+        assert_eq!(pixels.otsu, 35);
+        assert_eq!(Stencil::new(&[' ', '#'], Threshold::Otsu, false, false).map(&pixels, None), "  ##\n");
+    }
+
+    #[test]
+    fn stencil_draws_flat_images_in_one_tone() {
+        for value in [0, 128, 255] {
+            let gray = GrayImage::from_pixel(3, 2, Luma([value]));
+            assert_eq!(Pixels::new(&gray, None, None).otsu, 0);
+            let art = map(&Stencil::new(&[' ', '#'], Threshold::Otsu, false, false), &gray);
+            let tone = if value == 0 { ' ' } else { '#' };
+            assert_eq!(art, format!("{tone}{tone}{tone}\n").repeat(2));
+        }
+    }
+
+    #[test]
+    fn dithered_stencils_stay_two_toned() {
+        let gray = GrayImage::from_fn(32, 8, |x, _| Luma([(x * 8) as u8]));
+        let art = map(&Stencil::new(&[' ', '.', '#'], Threshold::Fixed(127), true, false), &gray);
+        let mut tones: Vec<char> = art.chars().filter(|&ch| ch != '\n').collect();
+        tones.sort();
+        tones.dedup();
+        assert_eq!(tones, [' ', '#']);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cache::ResultCache;
+use actix_web::{get, web, HttpResponse};
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+
+// --- PROMETHEUS METRICS ---
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Finished conversions, labelled by mapper mode, charset detail and resolution.
+    pub conversions: IntCounterVec,
+    pub conversion_duration: HistogramVec,
+    /// Source image dimensions in pixels, labelled by axis.
+    pub image_dimensions: HistogramVec,
+    pub upload_bytes: prometheus::Histogram,
+    pub cache_hits: IntCounter,
+    pub cache_misses: IntCounter,
+    cache_entries: IntGauge,
+    pub conversions_running: IntGauge,
+    /// Conversions waiting for one of the running ones to finish.
+    pub conversions_waiting: IntGauge,
+    /// Failed conversions, labelled by kind.
+    pub errors: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("ascii".to_string()), None).unwrap();
+
+        let conversions = IntCounterVec::new(
+            Opts::new("conversions_total", "Completed conversions"),
+            &["mode", "charset", "resolution"],
+        )
+        .unwrap();
+        let conversion_duration = HistogramVec::new(
+            HistogramOpts::new("conversion_duration_seconds", "Time spent decoding and converting images")
+                .buckets(exponential_buckets(0.005, 2.0, 14).unwrap()),
+            &["mode", "charset", "resolution"],
+        )
+        .unwrap();
+        let image_dimensions = HistogramVec::new(
+            HistogramOpts::new("image_dimension_pixels", "Width and height of decoded source images")
+                .buckets(exponential_buckets(16.0, 2.0, 11).unwrap()),
+            &["axis"],
+        )
+        .unwrap();
+        let upload_bytes = prometheus::Histogram::with_opts(
+            HistogramOpts::new("upload_size_bytes", "Size of uploaded image bodies")
+                .buckets(exponential_buckets(1024.0, 4.0, 10).unwrap()),
+        )
+        .unwrap();
+        let cache_hits = IntCounter::new("cache_hits_total", "Conversions served from the result cache").unwrap();
+        let cache_misses = IntCounter::new("cache_misses_total", "Result cache lookups that missed").unwrap();
+        let cache_entries = IntGauge::new("cache_entries", "Results currently held in the cache").unwrap();
+        let conversions_running = IntGauge::new("conversions_running", "Conversions currently running").unwrap();
+        let conversions_waiting =
+            IntGauge::new("conversions_waiting", "Conversions waiting for a free conversion slot").unwrap();
+        let errors = IntCounterVec::new(Opts::new("errors_total", "Failed conversions"), &["kind"]).unwrap();
+
+        registry.register(Box::new(conversions.clone())).unwrap();
+        registry.register(Box::new(conversion_duration.clone())).unwrap();
+        registry.register(Box::new(image_dimensions.clone())).unwrap();
+        registry.register(Box::new(upload_bytes.clone())).unwrap();
+        registry.register(Box::new(cache_hits.clone())).unwrap();
+        registry.register(Box::new(cache_misses.clone())).unwrap();
+        registry.register(Box::new(cache_entries.clone())).unwrap();
+        registry.register(Box::new(conversions_running.clone())).unwrap();
+        registry.register(Box::new(conversions_waiting.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+
+        Self {
+            registry,
+            conversions,
+            conversion_duration,
+            image_dimensions,
+            upload_bytes,
+            cache_hits,
+            cache_misses,
+            cache_entries,
+            conversions_running,
+            conversions_waiting,
+            errors,
+        }
+    }
+}
+
+/// Prometheus text exposition of all metrics.
+#[get("/metrics")]
+async fn metrics(cache: web::Data<ResultCache>) -> HttpResponse {
+    METRICS.cache_entries.set(cache.len() as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&METRICS.registry.gather(), &mut buffer) {
+        return HttpResponse::InternalServerError().body(format!("Failed to encode metrics: {}", err));
+    }
+    HttpResponse::Ok().content_type(encoder.format_type()).body(buffer)
+}
@@ -0,0 +1,58 @@
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageResult};
+use std::io::{Read, Seek};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::ColorType;
+
+// --- MULTI-PAGE TIFF ---
+
+/// Upper bound on the pages converted for `page=all`.
+pub const MAX_PAGES: usize = 50;
+
+pub fn is_tiff(head: &[u8]) -> bool {
+    head.starts_with(b"II*\0") || head.starts_with(b"MM\0*")
+}
+
+fn tiff_error(err: tiff::TiffError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Tiff), err))
+}
+
+/// Number of images (IFDs) in the file.
+pub fn page_count<R: Read + Seek>(reader: R) -> ImageResult<usize> {
+    let mut decoder = Decoder::new(reader).map_err(tiff_error)?;
+    let mut pages = 1;
+    while decoder.more_images() {
+        decoder.next_image().map_err(tiff_error)?;
+        pages += 1;
+    }
+    Ok(pages)
+}
+
+/// Decodes the page at zero-based `index`. Used for pages after the first, which goes
+/// through the image crate and its wider color support; this handles gray and RGB, with
+/// or without alpha, at 8 or 16 bits.
+pub fn decode_page<R: Read + Seek>(reader: R, index: usize) -> ImageResult<DynamicImage> {
+    let mut decoder = Decoder::new(reader).map_err(tiff_error)?;
+    decoder.seek_to_image(index).map_err(tiff_error)?;
+
+    let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+    let color_type = decoder.colortype().map_err(tiff_error)?;
+    let pixels = decoder.read_image().map_err(tiff_error)?;
+    let img = match (color_type, pixels) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8),
+        (ColorType::RGB(8), DecodingResult::U8(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+        (ColorType::Gray(16), DecodingResult::U16(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16),
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16),
+        (ColorType::RGB(16), DecodingResult::U16(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16),
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16),
+        (color_type, _) => {
+            return Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Exact(ImageFormat::Tiff),
+                UnsupportedErrorKind::GenericFeature(format!("{:?} pages after the first", color_type)),
+            )));
+        }
+    };
+    img.ok_or_else(|| tiff_error(tiff::TiffError::LimitsExceeded))
+}
@@ -0,0 +1,74 @@
+use crate::http_cache::Validator;
+use crate::{api, health, jobs, plain, recipe};
+use actix_web::{get, HttpRequest, Responder};
+use std::sync::LazyLock;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// --- OPENAPI DOCUMENT ---
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ASCII Art Generator", description = "Convert images to ASCII art."),
+    paths(
+        api::convert,
+        api::charsets,
+        recipe::list_recipes,
+        recipe::get_recipe,
+        recipe::put_recipe,
+        recipe::delete_recipe,
+        plain::plain,
+        jobs::submit_job,
+        jobs::job_status,
+        jobs::job_result,
+        health::healthz,
+        health::readyz,
+    ),
+    modifiers(&ApiKeySchemes),
+    tags(
+        (name = "api", description = "JSON conversion API, subject to API keys when configured"),
+        (name = "recipes", description = "Named sets of form fields, applied with `recipe`"),
+        (name = "plain", description = "Bare text output for command-line clients"),
+        (name = "jobs", description = "Background conversions for large images"),
+        (name = "health", description = "Liveness and readiness probes"),
+    ),
+)]
+struct ApiDoc;
+
+/// The two ways [`crate::api_auth`] accepts a key.
+struct ApiKeySchemes;
+
+impl Modify for ApiKeySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))));
+    }
+}
+
+static SPEC: LazyLock<String> = LazyLock::new(|| ApiDoc::openapi().to_pretty_json().unwrap());
+static SPEC_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| Validator::for_static(&SPEC));
+
+const DOCS_HTML: &str = include_str!("docs.html");
+static DOCS_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| Validator::for_static(DOCS_HTML));
+
+/// The OpenAPI document. Registered outside the `/api` scope so it needs no API key.
+#[get("/api/openapi.json")]
+async fn spec(req: HttpRequest) -> impl Responder {
+    if let Some(not_modified) = SPEC_VALIDATOR.not_modified(&req) {
+        return not_modified;
+    }
+    SPEC_VALIDATOR.ok().content_type("application/json").body(SPEC.as_str())
+}
+
+/// Swagger UI for the document above, loaded from a CDN.
+#[get("/api/docs")]
+async fn docs(req: HttpRequest) -> impl Responder {
+    if let Some(not_modified) = DOCS_VALIDATOR.not_modified(&req) {
+        return not_modified;
+    }
+    DOCS_VALIDATOR.ok().content_type("text/html; charset=utf-8").body(DOCS_HTML)
+}
@@ -0,0 +1,72 @@
+use crate::spool::UploadBody;
+use actix_web::{get, http::header, web, HttpResponse};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// --- ORIGINAL UPLOADS ---
+
+/// Uploads kept at once; the oldest is dropped to make room for another.
+const MAX_ORIGINALS: usize = 64;
+
+struct Original {
+    body: UploadBody,
+    content_type: &'static str,
+    stored: Instant,
+}
+
+/// Uploaded images kept for a while after conversion, so the result page can show them
+/// next to their art. Large uploads stay in their spool file rather than in memory.
+pub struct OriginalStore {
+    originals: Mutex<HashMap<String, Original>>,
+    retention: Duration,
+}
+
+impl OriginalStore {
+    pub fn new(retention: Duration) -> Self {
+        Self { originals: Mutex::new(HashMap::new()), retention }
+    }
+
+    /// Keeps `body` and returns the ID it is served under, or `None` when the store is
+    /// disabled or browsers cannot display the format.
+    pub fn store(&self, body: UploadBody) -> Option<String> {
+        if self.retention.is_zero() {
+            return None;
+        }
+        let content_type = body.browser_content_type()?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut originals = self.originals.lock().unwrap();
+        originals.retain(|_, original| original.stored.elapsed() < self.retention);
+        let oldest = originals.iter().min_by_key(|(_, original)| original.stored).map(|(id, _)| id.clone());
+        if let Some(oldest) = oldest.filter(|_| originals.len() >= MAX_ORIGINALS) {
+            originals.remove(&oldest);
+        }
+        originals.insert(id.clone(), Original { body, content_type, stored: Instant::now() });
+        Some(id)
+    }
+
+    fn get(&self, id: &str) -> Option<(UploadBody, &'static str)> {
+        let originals = self.originals.lock().unwrap();
+        let original = originals.get(id).filter(|original| original.stored.elapsed() < self.retention)?;
+        Some((original.body.clone(), original.content_type))
+    }
+}
+
+/// An uploaded image as it was received, while it is kept.
+#[get("/originals/{id}")]
+async fn original_upload(id: web::Path<String>, store: web::Data<OriginalStore>) -> HttpResponse {
+    let Some((body, content_type)) = store.get(&id) else {
+        return HttpResponse::NotFound().body("Unknown or expired upload.");
+    };
+    let bytes = match web::block(move || body.into_bytes()).await {
+        Ok(Ok(bytes)) => bytes,
+        _ => return HttpResponse::InternalServerError().body("The upload could not be read."),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        // Uploaded SVGs must not run scripts when opened directly.
+        .insert_header((header::CONTENT_SECURITY_POLICY, "default-src 'none'; style-src 'unsafe-inline'"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(bytes)
+}
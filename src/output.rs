@@ -0,0 +1,419 @@
+use crate::animation::AsciiFrame;
+use crate::ansi::{self, Palette};
+use crate::cache::ConversionOutput;
+use crate::font::UserFont;
+use crate::{encoder, irc, render, tile, Mode};
+use anyhow::Result;
+use askama::Template;
+use image::{GrayImage, RgbImage};
+use serde::Serialize;
+
+// --- OUTPUT RENDERERS ---
+
+/// A finished conversion as the renderers see it: the characters and how to draw them.
+pub struct AsciiGrid<'a> {
+    pub art: &'a str,
+    /// Width and height in characters.
+    pub dimensions: (u32, u32),
+    /// One pixel per character, when the conversion kept colors.
+    pub colors: Option<&'a RgbImage>,
+    /// The brightness each character was chosen from, one pixel per character.
+    pub brightness: &'a GrayImage,
+    pub charset: &'a str,
+    /// The options that shaped the art.
+    pub options: &'a serde_json::Value,
+    /// Every frame of an animation, the first of which is `art`; empty for stills.
+    pub frames: &'a [AsciiFrame],
+    pub mode: Mode,
+    pub background_color: &'a str,
+    pub text_color: &'a str,
+    pub font: Option<&'a UserFont>,
+    /// Colors the ANSI renderer may use.
+    pub palette: Palette,
+    /// Most columns and rows per panel of the tiled formats.
+    pub tile_size: (u32, u32),
+}
+
+impl<'a> AsciiGrid<'a> {
+    pub fn new(output: &'a ConversionOutput) -> Self {
+        Self {
+            art: &output.ascii_art,
+            dimensions: output.dimensions,
+            colors: output.colors.as_ref(),
+            brightness: &output.brightness,
+            charset: &output.charset,
+            options: &output.options,
+            frames: &output.frames,
+            mode: output.mode,
+            background_color: &output.background_color,
+            text_color: &output.text_color,
+            font: output.font.as_ref(),
+            palette: Palette::default(),
+            tile_size: tile::DEFAULT_TILE_SIZE,
+        }
+    }
+
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn with_tile_size(mut self, tile_size: (u32, u32)) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+}
+
+/// A rendered document.
+pub struct Output {
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Output {
+    fn text(content_type: &'static str, body: String) -> Self {
+        Self { content_type, body: body.into_bytes() }
+    }
+
+    /// The body of a text format as a string.
+    pub fn into_text(self) -> String {
+        String::from_utf8(self.body).unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    }
+}
+
+/// One output format. Register new formats in [`RENDERERS`] to make them available by name.
+pub trait OutputRenderer: Sync {
+    /// Name the format is requested by, e.g. `?format=svg` on `/jobs/{id}/result`.
+    fn name(&self) -> &'static str;
+    /// File extension for downloads.
+    fn extension(&self) -> &'static str;
+    /// Whether art converted in `mode` can be drawn in this format.
+    fn supports(&self, _mode: Mode) -> bool {
+        true
+    }
+    fn render(&self, grid: &AsciiGrid) -> Result<Output>;
+}
+
+/// Every output format, looked up by [`renderer`].
+static RENDERERS: [&dyn OutputRenderer; 11] = [&Text, &Html, &Ansi, &Irc, &Svg, &Png, &Gif, &Apng, &Zip, &Pdf, &Grid];
+
+pub fn renderer(name: &str) -> Option<&'static dyn OutputRenderer> {
+    renderers().find(|renderer| renderer.name() == name)
+}
+
+pub fn renderers() -> impl Iterator<Item = &'static dyn OutputRenderer> {
+    RENDERERS.iter().copied()
+}
+
+/// The art as plain text.
+pub struct Text;
+
+impl OutputRenderer for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output::text("text/plain; charset=utf-8", grid.art.to_string()))
+    }
+}
+
+/// The self-contained HTML viewer that scales the art to the window.
+pub struct Html;
+
+impl OutputRenderer for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output::text("text/html; charset=utf-8", viewer(grid)?))
+    }
+}
+
+/// Text with ANSI color escapes in the grid's palette; plain text without colors.
+pub struct Ansi;
+
+impl OutputRenderer for Ansi {
+    fn name(&self) -> &'static str {
+        "ansi"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ans"
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        let text = match grid.colors {
+            Some(colors) => ansi::colorize(grid.art, colors, grid.palette),
+            None => grid.art.to_string(),
+        };
+        Ok(Output::text("text/plain; charset=utf-8", text))
+    }
+}
+
+/// Text with mIRC color codes, split into lines IRC servers accept.
+pub struct Irc;
+
+impl OutputRenderer for Irc {
+    fn name(&self) -> &'static str {
+        "irc"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output::text("text/plain; charset=utf-8", irc::format(grid.art, grid.colors)))
+    }
+}
+
+/// An SVG document drawn in the grid's colors and font.
+pub struct Svg;
+
+impl OutputRenderer for Svg {
+    fn name(&self) -> &'static str {
+        "svg"
+    }
+
+    fn extension(&self) -> &'static str {
+        "svg"
+    }
+
+    // Emoji have no outlines in the fonts these renderers draw with.
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        let svg = render::render_svg(grid.art, grid.background_color, grid.text_color, grid.font);
+        Ok(Output::text("image/svg+xml", svg))
+    }
+}
+
+/// A PNG bitmap drawn in the grid's colors and font.
+pub struct Png;
+
+impl OutputRenderer for Png {
+    fn name(&self) -> &'static str {
+        "png"
+    }
+
+    fn extension(&self) -> &'static str {
+        "png"
+    }
+
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        let png = render::render_png(grid.art, grid.background_color, grid.text_color, grid.font)?;
+        Ok(Output { content_type: "image/png", body: png })
+    }
+}
+
+/// Every frame drawn like [`Png`], as a looping GIF with the original timing.
+pub struct Gif;
+
+impl OutputRenderer for Gif {
+    fn name(&self) -> &'static str {
+        "gif"
+    }
+
+    fn extension(&self) -> &'static str {
+        "gif"
+    }
+
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output { content_type: "image/gif", body: encoder::gif(grid)? })
+    }
+}
+
+/// The frames of [`Gif`] as an animated PNG.
+pub struct Apng;
+
+impl OutputRenderer for Apng {
+    fn name(&self) -> &'static str {
+        "apng"
+    }
+
+    fn extension(&self) -> &'static str {
+        "png"
+    }
+
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output { content_type: "image/apng", body: encoder::apng(grid)? })
+    }
+}
+
+/// A ZIP archive of numbered panels, as PNGs and text, for art too large to print whole.
+pub struct Zip;
+
+impl OutputRenderer for Zip {
+    fn name(&self) -> &'static str {
+        "zip"
+    }
+
+    fn extension(&self) -> &'static str {
+        "zip"
+    }
+
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output { content_type: "application/zip", body: tile::zip(grid)? })
+    }
+}
+
+/// The same panels as [`Zip`], one per page of a PDF.
+pub struct Pdf;
+
+impl OutputRenderer for Pdf {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn extension(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn supports(&self, mode: Mode) -> bool {
+        mode != Mode::Emoji
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        Ok(Output { content_type: "application/pdf", body: tile::pdf(grid)? })
+    }
+}
+
+/// Media type of the [`Grid`] format.
+pub const GRID_CONTENT_TYPE: &str = "application/vnd.ascii-grid+json";
+/// Response header carrying [`ConversionOutput::notice`] where the body has no room for it.
+pub const NOTICE_HEADER: &str = "X-Conversion-Notice";
+
+/// The art as JSON rows of cells, each with its character, brightness and color, for
+/// tools that drive LED matrices or game tiles rather than display text.
+pub struct Grid;
+
+#[derive(Serialize)]
+struct GridDocument<'a> {
+    width: u32,
+    height: u32,
+    mode: &'static str,
+    charset: &'a str,
+    background_color: &'a str,
+    text_color: &'a str,
+    options: &'a serde_json::Value,
+    cells: Vec<Vec<GridCell>>,
+}
+
+#[derive(Serialize)]
+struct GridCell {
+    #[serde(rename = "char")]
+    character: char,
+    brightness: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rgb: Option<[u8; 3]>,
+}
+
+impl OutputRenderer for Grid {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn render(&self, grid: &AsciiGrid) -> Result<Output> {
+        let cells = grid
+            .art
+            .lines()
+            .enumerate()
+            .map(|(y, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(x, character)| {
+                        let (x, y) = (x as u32, y as u32);
+                        let brightness = grid.brightness.get_pixel_checked(x, y).map_or(0, |pixel| pixel.0[0]);
+                        let rgb = grid.colors.and_then(|colors| colors.get_pixel_checked(x, y)).map(|pixel| pixel.0);
+                        GridCell { character, brightness, rgb }
+                    })
+                    .collect()
+            })
+            .collect();
+        let (width, height) = grid.dimensions;
+        let document = GridDocument {
+            width,
+            height,
+            mode: grid.mode.name(),
+            charset: grid.charset,
+            background_color: grid.background_color,
+            text_color: grid.text_color,
+            options: grid.options,
+            cells,
+        };
+        Ok(Output { content_type: GRID_CONTENT_TYPE, body: serde_json::to_vec(&document)? })
+    }
+}
+
+/// Context of `templates/viewer.html`, the standalone viewer page.
+#[derive(Template)]
+#[template(path = "viewer.html")]
+struct Viewer<'a> {
+    art: &'a str,
+    columns: u32,
+    rows: u32,
+    background_color: &'a str,
+    text_color: &'a str,
+    /// Lay the art out on a grid of square cells instead of in a `<pre>`.
+    grid_cells: bool,
+    line_height: &'static str,
+    /// Cell width over font size, for fitting the art to the window.
+    font_aspect_ratio: f32,
+    /// The art and delay in milliseconds of every frame; empty for stills.
+    frame_arts: Vec<&'a str>,
+    frame_delays: Vec<u64>,
+}
+
+fn viewer(grid: &AsciiGrid) -> Result<String> {
+    let (columns, rows) = grid.dimensions;
+    let animated = grid.frames.len() > 1;
+    // Emoji have no reliable advance width, so mosaics are laid out on a grid of square
+    // cells instead of relying on a monospace font.
+    let grid_cells = grid.mode == Mode::Emoji;
+    let page = Viewer {
+        art: grid.art,
+        columns,
+        rows,
+        background_color: grid.background_color,
+        text_color: grid.text_color,
+        grid_cells,
+        // Block elements and braille span the whole line, so rows must not overlap.
+        line_height: if matches!(grid.mode, Mode::Quadrant | Mode::Braille) { "1em" } else { "0.8em" },
+        font_aspect_ratio: if grid_cells { 1.0 } else { 0.6 },
+        frame_arts: grid.frames.iter().filter(|_| animated).map(|frame| frame.art.as_str()).collect(),
+        frame_delays: grid.frames.iter().filter(|_| animated).map(|frame| frame.delay.as_millis() as u64).collect(),
+    };
+    Ok(page.render()?)
+}
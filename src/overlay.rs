@@ -0,0 +1,226 @@
+use crate::cache::ConversionOutput;
+use crate::{render, Mode};
+use anyhow::{bail, Result};
+use image::{GrayImage, RgbImage};
+
+// --- CAPTIONS AND WATERMARKS ---
+
+/// Longest caption, in characters; a banner caption is eight columns per character.
+pub const MAX_CAPTION_CHARS: usize = 60;
+pub const MAX_WATERMARK_CHARS: usize = 40;
+/// Text rows a banner takes: two rows of the 8x8 bitmap font per half-block character.
+const BANNER_ROWS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CaptionStyle {
+    /// The caption as it was typed.
+    #[default]
+    Plain,
+    /// Large letters drawn from the built-in bitmap font with half blocks.
+    Banner,
+}
+
+impl CaptionStyle {
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "banner" => CaptionStyle::Banner,
+            _ => CaptionStyle::Plain,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    /// Parses `top-left`, `top-right`, `bottom-left` or `bottom-right`.
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "top-left" => Corner::TopLeft,
+            "top-right" => Corner::TopRight,
+            "bottom-left" => Corner::BottomLeft,
+            _ => Corner::BottomRight,
+        }
+    }
+}
+
+/// Text written into the art itself, so attribution survives copy and paste.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    pub caption: Option<String>,
+    pub caption_style: CaptionStyle,
+    /// Put the caption below the art instead of above it.
+    pub caption_below: bool,
+    pub watermark: Option<String>,
+    pub watermark_corner: Corner,
+}
+
+impl Overlay {
+    pub fn is_empty(&self) -> bool {
+        self.caption.is_none() && self.watermark.is_none()
+    }
+
+    /// The caption's rows of characters.
+    fn caption_rows(&self) -> Vec<Vec<char>> {
+        let Some(caption) = &self.caption else { return Vec::new() };
+        if self.caption_style == CaptionStyle::Plain {
+            return vec![caption.chars().collect()];
+        }
+        let glyphs: Vec<[u8; 8]> = caption.chars().map(|ch| render::glyph(ch).or_else(|| render::glyph('?')).unwrap_or([0; 8])).collect();
+        (0..BANNER_ROWS)
+            .map(|row| {
+                glyphs
+                    .iter()
+                    .flat_map(|glyph| {
+                        let (top, bottom) = (glyph[row * 2], glyph[row * 2 + 1]);
+                        (0..8).map(move |bit| match (top & (1 << bit) != 0, bottom & (1 << bit) != 0) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Writes the caption next to the art, widening it when the caption is wider, and
+    /// the watermark over the art's corner. Every frame of an animation gets the same.
+    pub fn apply(&self, output: &mut ConversionOutput) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if output.mode == Mode::Emoji {
+            bail!("Captions and watermarks cannot be added to emoji mosaics");
+        }
+        let caption = self.caption_rows();
+        let (art_width, art_height) = output.dimensions;
+        let caption_width = caption.iter().map(Vec::len).max().unwrap_or(0) as u32;
+        // A blank row keeps the caption apart from the art.
+        let caption_height = if caption.is_empty() { 0 } else { caption.len() as u32 + 1 };
+        let (width, height) = (art_width.max(caption_width), art_height + caption_height);
+        let art_left = (width - art_width) / 2;
+        let (art_top, caption_top) = if self.caption_below { (0, art_height + 1) } else { (caption_height, 0) };
+        let caption_left = ((width - caption_width) / 2) as usize;
+
+        let watermark: Vec<char> = self
+            .watermark
+            .iter()
+            .flat_map(|text| format!(" {} ", text).chars().collect::<Vec<_>>())
+            .take(art_width as usize)
+            .collect();
+        let watermark_left = match self.watermark_corner {
+            Corner::TopLeft | Corner::BottomLeft => art_left,
+            Corner::TopRight | Corner::BottomRight => art_left + art_width - watermark.len() as u32,
+        };
+        let watermark_top = match self.watermark_corner {
+            Corner::TopLeft | Corner::TopRight => art_top,
+            Corner::BottomLeft | Corner::BottomRight => art_top + art_height.saturating_sub(1),
+        };
+
+        let compose = |art: &str| {
+            let mut lines = vec![vec![' '; width as usize]; height as usize];
+            for (line, target) in art.lines().zip(&mut lines[art_top as usize..]) {
+                for (cell, ch) in target[art_left as usize..].iter_mut().zip(line.chars().take(art_width as usize)) {
+                    *cell = ch;
+                }
+            }
+            for (row, text) in caption.iter().enumerate() {
+                lines[caption_top as usize + row][caption_left..caption_left + text.len()].copy_from_slice(text);
+            }
+            if art_height > 0 {
+                let start = watermark_left as usize;
+                lines[watermark_top as usize][start..start + watermark.len()].copy_from_slice(&watermark);
+            }
+            let mut composed = String::with_capacity(lines.len() * (width as usize + 1));
+            for line in lines {
+                composed.extend(line);
+                composed.push('\n');
+            }
+            composed
+        };
+        output.ascii_art = compose(&output.ascii_art);
+        for frame in &mut output.frames {
+            frame.art = compose(&frame.art);
+        }
+
+        // The added text is drawn in the text color, and is blank as far as brightness goes.
+        if let Some(cell_colors) = &output.colors {
+            let text_color = render::parse_hex_color(&output.text_color)?;
+            let mut colors = RgbImage::from_pixel(width, height, text_color);
+            image::imageops::replace(&mut colors, cell_colors, i64::from(art_left), i64::from(art_top));
+            if art_height > 0 {
+                for x in watermark_left..watermark_left + watermark.len() as u32 {
+                    colors.put_pixel(x, watermark_top, text_color);
+                }
+            }
+            output.colors = Some(colors);
+        }
+        let mut brightness = GrayImage::new(width, height);
+        image::imageops::replace(&mut brightness, &output.brightness, i64::from(art_left), i64::from(art_top));
+        output.brightness = brightness;
+        output.dimensions = (width, height);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(overlay: Overlay, text: &str) -> ConversionOutput {
+        let mut output = ConversionOutput::from_art(text);
+        overlay.apply(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn centers_captions_above_or_below() {
+        let caption = Overlay { caption: Some("hello".to_string()), ..Overlay::default() };
+        let above = applied(caption.clone(), "ab\ncd\n");
+        assert_eq!(above.ascii_art, "hello\n     \n ab  \n cd  \n");
+        assert_eq!(above.dimensions, (5, 4));
+        assert_eq!(above.brightness.get_pixel(1, 2)[0], 200);
+        assert_eq!(above.brightness.get_pixel(1, 0)[0], 0);
+
+        let below = applied(Overlay { caption_below: true, ..caption }, "ab\ncd\n");
+        assert_eq!(below.ascii_art, " ab  \n cd  \n     \nhello\n");
+    }
+
+    #[test]
+    fn draws_banner_captions_from_the_bitmap_font() {
+        let caption = Some("I".to_string());
+        let caption = Overlay { caption, caption_style: CaptionStyle::Banner, ..Overlay::default() };
+        let banner = applied(caption, "x\n");
+        assert_eq!(banner.dimensions, (8, BANNER_ROWS as u32 + 2));
+        assert!(banner.ascii_art.lines().take(BANNER_ROWS).any(|line| line.contains('█')));
+        assert_eq!(banner.ascii_art.lines().last(), Some("   x    "));
+    }
+
+    #[test]
+    fn puts_watermarks_in_their_corner() {
+        let watermark = Some("w".to_string());
+        let mark = |watermark_corner| Overlay { watermark: watermark.clone(), watermark_corner, ..Overlay::default() };
+        let text = "......\n......\n......\n";
+        assert_eq!(applied(mark(Corner::TopLeft), text).ascii_art, " w ...\n......\n......\n");
+        assert_eq!(applied(mark(Corner::TopRight), text).ascii_art, "... w \n......\n......\n");
+        assert_eq!(applied(mark(Corner::BottomLeft), text).ascii_art, "......\n......\n w ...\n");
+        assert_eq!(applied(mark(Corner::BottomRight), text).ascii_art, "......\n......\n... w \n");
+        // The watermark is cut to the art's width.
+        let long = Overlay { watermark: Some("signed".to_string()), ..Overlay::default() };
+        assert_eq!(applied(long, "....\n").ascii_art, " sig\n");
+    }
+
+    #[test]
+    fn refuses_emoji_mosaics() {
+        let mut output = ConversionOutput { mode: Mode::Emoji, ..ConversionOutput::from_art("x\n") };
+        assert!(Overlay { watermark: Some("w".to_string()), ..Overlay::default() }.apply(&mut output).is_err());
+    }
+}
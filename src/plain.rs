@@ -0,0 +1,144 @@
+use crate::cache::ResultCache;
+use crate::collage;
+use crate::config::ServerConfig;
+use crate::form::{UploadFields, UploadForm};
+use crate::recipe::RecipeStore;
+use crate::ansi::Palette;
+use crate::output::{self, AsciiGrid, OutputRenderer};
+use crate::concurrency::{self, ConversionLimiter, Overloaded, Priority};
+use crate::{convert_cached, filename_stem, logging};
+use actix_multipart::Multipart;
+use actix_web::http::header::{self, ContentDisposition};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{post, web, Error, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+// --- COMMAND-LINE FRIENDLY ROUTE ---
+
+/// User agents of command-line clients that print straight to a terminal.
+const TERMINAL_AGENTS: [&str; 4] = ["curl/", "wget/", "httpie/", "xh/"];
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PlainQuery {
+    /// Force ANSI colors on or off; by default they are used for curl, wget and HTTPie.
+    color: Option<bool>,
+    /// `truecolor` (default), `256` or `16`, for terminals with fewer colors.
+    palette: Option<String>,
+    /// `irc` emits mIRC color codes and splits long rows for pasting into IRC; `ans`
+    /// downloads an ANSI art file, with a SAUCE record when any `sauce_*` field is set.
+    /// Colors are on by default for both.
+    format: Option<String>,
+}
+
+/// What `/plain` sends back.
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Irc,
+    Ans,
+}
+
+fn looks_like_terminal(req: &HttpRequest) -> bool {
+    let Some(agent) = req.headers().get(header::USER_AGENT).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let agent = agent.to_ascii_lowercase();
+    TERMINAL_AGENTS.iter().any(|prefix| agent.starts_with(prefix))
+}
+
+/// Nothing but the art: `curl -F image=@cat.jpg localhost:8080/plain`.
+#[utoipa::path(
+    tag = "plain",
+    params(PlainQuery),
+    request_body(content = UploadFields, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The art, ANSI-colored for terminal clients", body = String, content_type = "text/plain",
+            headers(("X-Conversion-Notice" = String, description = "Why the art is smaller than asked for"))),
+        (status = 200, description = "With `format=ans`, an ANSI art file", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 400, description = "Missing or undecodable image", body = String, content_type = "text/plain"),
+        (status = 503, description = "Too many conversions waiting", body = String, content_type = "text/plain",
+            headers(("Retry-After" = u64, description = "Seconds to wait"))),
+    ),
+)]
+#[post("/plain")]
+async fn plain(
+    req: HttpRequest,
+    query: web::Query<PlainQuery>,
+    mut payload: Multipart,
+    server_config: web::Data<ServerConfig>,
+    recipes: web::Data<RecipeStore>,
+    cache: web::Data<ResultCache>,
+    limiter: web::Data<ConversionLimiter>,
+) -> Result<HttpResponse, Error> {
+    let format = match query.format.as_deref() {
+        None | Some("text") => OutputFormat::Text,
+        Some("irc") => OutputFormat::Irc,
+        Some("ans") => OutputFormat::Ans,
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body("Unknown format; use text, irc or ans.\n"));
+        }
+    };
+    let palette = match query.palette.as_deref().map(Palette::from_name) {
+        Some(Some(palette)) => palette,
+        Some(None) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body("Unknown palette; use truecolor, 256 or 16.\n"));
+        }
+        // ANSI art viewers reliably support the 16 standard colors only.
+        None if format == OutputFormat::Ans => Palette::Ansi16,
+        None => Palette::default(),
+    };
+    let mut form = UploadForm::read(&mut payload, &server_config, &recipes).await?;
+    let Some(image_data) = form.image.take() else {
+        return Ok(HttpResponse::BadRequest().content_type("text/plain; charset=utf-8").body("No image uploaded.\n"));
+    };
+    form.options.colors = query.color.unwrap_or_else(|| format != OutputFormat::Text || looks_like_terminal(&req));
+
+    let converted = if form.more_images.is_empty() {
+        convert_cached(image_data, &form.options, &cache, &limiter, Priority::Interactive, None).await
+    } else {
+        let images = form.take_images(image_data);
+        collage::convert(images, &form.options, &form.collage, &cache, &limiter, Priority::Interactive, None).await
+    };
+    let output = match converted {
+        Ok(output) => output,
+        Err(err) if err.is::<Overloaded>() => return Ok(concurrency::overloaded()),
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("{:#}\n", err)));
+        }
+    };
+
+    let renderer: &dyn OutputRenderer = if format == OutputFormat::Irc { &output::Irc } else { &output::Ansi };
+    let text = renderer.render(&AsciiGrid::new(&output).with_palette(palette)).map_err(ErrorInternalServerError)?.into_text();
+    if format == OutputFormat::Ans {
+        // ANSI art files are DOS text.
+        let mut body = text.replace('\n', "\r\n").into_bytes();
+        if let Some(sauce) = &form.sauce {
+            let (width, height) = output.dimensions;
+            sauce.append_to(&mut body, width, height);
+        }
+        logging::record_conversion(&form.options, body.len());
+        let filename = format!("{}.ans", filename_stem(&form.filename));
+        return Ok(HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(ContentDisposition::attachment(filename))
+            .body(body));
+    }
+    logging::record_conversion(&form.options, text.len());
+    let mut response = HttpResponse::Ok();
+    if let Some(notice) = &output.notice {
+        // Terminals would show a notice in the body as part of the art.
+        response.insert_header((output::NOTICE_HEADER, notice.as_str()));
+    }
+    Ok(response
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((header::VARY, "User-Agent"))
+        .body(text))
+}
@@ -0,0 +1,442 @@
+use anyhow::{bail, Result};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+
+// --- IMAGE PREPROCESSING ---
+
+/// A region of interest in pixels of the upright image.
+#[derive(Clone, Copy, Debug)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Cuts `crop` out of `img`, clamping it to the image bounds.
+pub fn crop(img: &DynamicImage, crop: Crop) -> Result<DynamicImage> {
+    let width = crop.width.min(img.width().saturating_sub(crop.x));
+    let height = crop.height.min(img.height().saturating_sub(crop.y));
+    if width == 0 || height == 0 {
+        bail!("The crop region lies outside the {}x{} image", img.width(), img.height());
+    }
+    Ok(img.crop_imm(crop.x, crop.y, width, height))
+}
+
+/// Mirroring applied after rotation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Flip {
+    pub horizontal: bool,
+    pub vertical: bool,
+}
+
+impl Flip {
+    /// Parses `h`, `v` or `hv`.
+    pub fn from_name(name: &str) -> Self {
+        Flip { horizontal: name.contains('h'), vertical: name.contains('v') }
+    }
+}
+
+/// Parses a clockwise rotation of 90, 180 or 270 degrees; anything else is no rotation.
+pub fn parse_rotation(degrees: &str) -> u16 {
+    match degrees.trim() {
+        "90" => 90,
+        "180" => 180,
+        "270" => 270,
+        _ => 0,
+    }
+}
+
+/// Rotates `img` clockwise by `degrees`, then mirrors it.
+pub fn rotate_and_flip(img: DynamicImage, degrees: u16, flip: Flip) -> DynamicImage {
+    let mut img = match degrees {
+        90 => img.rotate90(),
+        180 => img.rotate180(),
+        270 => img.rotate270(),
+        _ => img,
+    };
+    if flip.horizontal {
+        img = img.fliph();
+    }
+    if flip.vertical {
+        img = img.flipv();
+    }
+    img
+}
+
+/// Rotates and flips `img` upright according to its EXIF orientation tag.
+pub fn apply_exif_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Composites a translucent image over a solid background color.
+pub fn flatten_alpha(img: &DynamicImage, background: Rgb<u8>) -> DynamicImage {
+    let mut flat = RgbImage::new(img.width(), img.height());
+    for (pixel, source) in flat.pixels_mut().zip(img.to_rgba8().pixels()) {
+        let alpha = source[3] as u32;
+        for channel in 0..3 {
+            let blended = source[channel] as u32 * alpha + background[channel] as u32 * (255 - alpha);
+            pixel[channel] = ((blended + 127) / 255) as u8;
+        }
+    }
+    DynamicImage::ImageRgb8(flat)
+}
+
+/// A mask that is 0 where the alpha channel is below `threshold` and 255 elsewhere.
+pub fn opacity_mask(img: &DynamicImage, threshold: u8) -> GrayImage {
+    let rgba = img.to_rgba8();
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([if rgba.get_pixel(x, y)[3] < threshold { 0 } else { 255 }])
+    })
+}
+
+/// How colors are reduced to the brightness the mappers work from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Grayscale {
+    /// ITU-R BT.709 luma, weighting green most and blue least, as the eye does.
+    #[default]
+    Luma,
+    /// The mean of the three channels.
+    Average,
+    /// Halfway between the brightest and the darkest channel, as in HSL.
+    Lightness,
+    Red,
+    Green,
+    Blue,
+}
+
+impl Grayscale {
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "average" => Grayscale::Average,
+            "lightness" => Grayscale::Lightness,
+            "red" | "r" => Grayscale::Red,
+            "green" | "g" => Grayscale::Green,
+            "blue" | "b" => Grayscale::Blue,
+            _ => Grayscale::Luma,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Grayscale::Luma => "luma",
+            Grayscale::Average => "average",
+            Grayscale::Lightness => "lightness",
+            Grayscale::Red => "red",
+            Grayscale::Green => "green",
+            Grayscale::Blue => "blue",
+        }
+    }
+}
+
+/// The brightness of every pixel of `img` under `weighting`.
+pub fn grayscale(img: &DynamicImage, weighting: Grayscale) -> GrayImage {
+    if weighting == Grayscale::Luma {
+        return img.to_luma8();
+    }
+    let rgb = img.to_rgb8();
+    let mut gray = GrayImage::new(img.width(), img.height());
+    for (pixel, source) in gray.pixels_mut().zip(rgb.pixels()) {
+        let [r, g, b] = source.0;
+        pixel[0] = match weighting {
+            Grayscale::Average => ((u16::from(r) + u16::from(g) + u16::from(b) + 1) / 3) as u8,
+            Grayscale::Lightness => (u16::from(r.max(g).max(b)) + u16::from(r.min(g).min(b))).div_ceil(2) as u8,
+            Grayscale::Red => r,
+            Grayscale::Green => g,
+            Grayscale::Blue => b,
+            Grayscale::Luma => unreachable!(),
+        };
+    }
+    gray
+}
+
+/// How [`adaptive_threshold`] finds the local threshold around each pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LocalThreshold {
+    /// The mean brightness of the block.
+    Mean,
+    /// Sauvola's method: the block mean, lowered where the block has little contrast, so
+    /// flat dark areas stay dark instead of being lifted to mid-gray.
+    Sauvola,
+}
+
+impl LocalThreshold {
+    /// `mean` or `sauvola`; anything else turns the pass off.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "mean" => Some(LocalThreshold::Mean),
+            "sauvola" => Some(LocalThreshold::Sauvola),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LocalThreshold::Mean => "mean",
+            LocalThreshold::Sauvola => "sauvola",
+        }
+    }
+}
+
+/// Block size in pixels of the sampled image when none is given.
+pub const DEFAULT_ADAPTIVE_BLOCK: u32 = 31;
+/// Largest block accepted; wider than this the lighting is even anyway.
+const MAX_ADAPTIVE_BLOCK: u32 = 255;
+
+/// Sensitivity of Sauvola's threshold to the block's standard deviation.
+const SAUVOLA_K: f64 = 0.2;
+/// Standard deviation of a block with full contrast, for Sauvola's threshold.
+const SAUVOLA_RANGE: f64 = 128.0;
+
+/// Evens out uneven lighting: every pixel is shifted so the threshold of the `block` x
+/// `block` pixels around it lands on mid-gray. A positive `offset` lowers the thresholds,
+/// brightening the result.
+pub fn adaptive_threshold(gray: &GrayImage, method: LocalThreshold, block: u32, offset: i16) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    // Summed-area tables of the brightness and its square, with a zero row and column.
+    let stride = width as usize + 1;
+    let mut sums = vec![0u64; stride * (height as usize + 1)];
+    let mut squares = vec![0u64; sums.len()];
+    for y in 0..height as usize {
+        let (mut row_sum, mut row_squares) = (0u64, 0u64);
+        for x in 0..width as usize {
+            let value = u64::from(gray.get_pixel(x as u32, y as u32)[0]);
+            row_sum += value;
+            row_squares += value * value;
+            let index = (y + 1) * stride + x + 1;
+            sums[index] = sums[index - stride] + row_sum;
+            squares[index] = squares[index - stride] + row_squares;
+        }
+    }
+    let area = |table: &[u64], (x0, y0, x1, y1): (usize, usize, usize, usize)| {
+        table[y1 * stride + x1] + table[y0 * stride + x0] - table[y0 * stride + x1] - table[y1 * stride + x0]
+    };
+
+    let radius = block.clamp(3, MAX_ADAPTIVE_BLOCK) / 2;
+    GrayImage::from_fn(width, height, |x, y| {
+        let window = (
+            x.saturating_sub(radius) as usize,
+            y.saturating_sub(radius) as usize,
+            (x + radius + 1).min(width) as usize,
+            (y + radius + 1).min(height) as usize,
+        );
+        let count = ((window.2 - window.0) * (window.3 - window.1)) as f64;
+        let mean = area(&sums, window) as f64 / count;
+        let threshold = match method {
+            LocalThreshold::Mean => mean,
+            LocalThreshold::Sauvola => {
+                let deviation = (area(&squares, window) as f64 / count - mean * mean).max(0.0).sqrt();
+                mean * (1.0 + SAUVOLA_K * (deviation / SAUVOLA_RANGE - 1.0))
+            }
+        };
+        let value = f64::from(gray.get_pixel(x, y)[0]) - threshold + f64::from(offset) + 128.0;
+        Luma([value.round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+/// Blur radius of the narrower Gaussian of [`line_art`] when none is given.
+pub const DEFAULT_LINE_SIGMA: f32 = 1.0;
+/// How far, in brightness levels, an edge must dip below its surroundings to be drawn
+/// when no threshold is given.
+pub const DEFAULT_LINE_THRESHOLD: u8 = 2;
+/// Ratio of the wider Gaussian's sigma to the narrower one's.
+const XDOG_K: f32 = 1.6;
+/// Weight of the wider Gaussian. Just below 1, so flat areas stay white at any brightness.
+const XDOG_TAU: f32 = 0.98;
+/// Steepness of the falloff past the threshold, per brightness level; higher values
+/// give harder lines.
+const XDOG_SHARPNESS: f32 = 0.5;
+
+/// Extended difference-of-Gaussians (XDoG) stylization: where the image is darker than
+/// its blurred surroundings by more than `threshold` levels, a line falls off steeply
+/// from white to black, and everything else is white. The lines are dark on white, or
+/// bright on black with `bright_lines`, so that they get the dense end of the ramp on
+/// either theme.
+pub fn line_art(gray: &GrayImage, sigma: f32, threshold: u8, bright_lines: bool) -> GrayImage {
+    let sigma = sigma.clamp(0.3, 10.0);
+    // Blurring works on levels from 0 to 1 for floating-point images.
+    let levels: ImageBuffer<Luma<f32>, Vec<f32>> =
+        ImageBuffer::from_fn(gray.width(), gray.height(), |x, y| Luma([f32::from(gray.get_pixel(x, y)[0]) / 255.0]));
+    let narrow = image::imageops::blur(&levels, sigma);
+    let wide = image::imageops::blur(&levels, sigma * XDOG_K);
+    let threshold = f32::from(threshold) / 255.0;
+    let mut out = GrayImage::new(gray.width(), gray.height());
+    for ((pixel, narrow), wide) in out.pixels_mut().zip(narrow.pixels()).zip(wide.pixels()) {
+        let difference = narrow[0] - XDOG_TAU * wide[0] + threshold;
+        let tone = if difference >= 0.0 { 1.0 } else { 1.0 + (XDOG_SHARPNESS * 255.0 * difference).tanh() };
+        let value = (tone * 255.0).round() as u8;
+        pixel[0] = if bright_lines { 255 - value } else { value };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(gray: &GrayImage) -> Vec<u8> {
+        gray.pixels().map(|pixel| pixel[0]).collect()
+    }
+
+    /// A `width` x `height` image whose pixels read `10 * y + x`.
+    fn numbered(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| Luma([(10 * y + x) as u8])))
+    }
+
+    fn rows(img: &DynamicImage) -> Vec<Vec<u8>> {
+        let gray = img.to_luma8();
+        gray.rows().map(|row| row.map(|pixel| pixel[0]).collect()).collect()
+    }
+
+    #[test]
+    fn crops_within_the_image() {
+        let img = numbered(4, 3);
+        let region = crop(&img, Crop { x: 1, y: 1, width: 2, height: 2 }).unwrap();
+        assert_eq!(rows(&region), [[11, 12], [21, 22]]);
+        // The region is clamped to the image.
+        let region = crop(&img, Crop { x: 2, y: 1, width: 10, height: 10 }).unwrap();
+        assert_eq!(rows(&region), [[12, 13], [22, 23]]);
+    }
+
+    #[test]
+    fn refuses_empty_crops() {
+        let img = numbered(4, 3);
+        assert!(crop(&img, Crop { x: 4, y: 0, width: 2, height: 2 }).is_err());
+        assert!(crop(&img, Crop { x: 0, y: 3, width: 2, height: 2 }).is_err());
+        assert!(crop(&img, Crop { x: 1, y: 1, width: 0, height: 2 }).is_err());
+        assert!(crop(&img, Crop { x: 1, y: 1, width: 2, height: 0 }).is_err());
+    }
+
+    #[test]
+    fn rotates_and_flips() {
+        let turn = |degrees, flip| rows(&rotate_and_flip(numbered(2, 3), degrees, Flip::from_name(flip)));
+        assert_eq!(turn(0, ""), [[0, 1], [10, 11], [20, 21]]);
+        assert_eq!(turn(90, ""), [[20, 10, 0], [21, 11, 1]]);
+        assert_eq!(turn(180, ""), [[21, 20], [11, 10], [1, 0]]);
+        assert_eq!(turn(270, ""), [[1, 11, 21], [0, 10, 20]]);
+        assert_eq!(turn(0, "h"), [[1, 0], [11, 10], [21, 20]]);
+        assert_eq!(turn(0, "v"), [[20, 21], [10, 11], [0, 1]]);
+        assert_eq!(turn(0, "hv"), turn(180, ""));
+        // Mirroring comes after the rotation.
+        assert_eq!(turn(90, "h"), [[0, 10, 20], [1, 11, 21]]);
+        assert_eq!(turn(270, "v"), [[0, 10, 20], [1, 11, 21]]);
+    }
+
+    #[test]
+    fn parses_rotations() {
+        assert_eq!(parse_rotation(" 90 "), 90);
+        assert_eq!(parse_rotation("270"), 270);
+        assert_eq!(parse_rotation("45"), 0);
+        assert_eq!(parse_rotation("-90"), 0);
+    }
+
+    #[test]
+    fn applies_every_exif_orientation() {
+        let upright = |orientation| rows(&apply_exif_orientation(numbered(2, 3), orientation));
+        let expected: [(u32, &[&[u8]]); 10] = [
+            (0, &[&[0, 1], &[10, 11], &[20, 21]]),
+            (1, &[&[0, 1], &[10, 11], &[20, 21]]),
+            (2, &[&[1, 0], &[11, 10], &[21, 20]]),
+            (3, &[&[21, 20], &[11, 10], &[1, 0]]),
+            (4, &[&[20, 21], &[10, 11], &[0, 1]]),
+            // Transposed across the main diagonal.
+            (5, &[&[0, 10, 20], &[1, 11, 21]]),
+            (6, &[&[20, 10, 0], &[21, 11, 1]]),
+            // Transposed across the other diagonal.
+            (7, &[&[21, 11, 1], &[20, 10, 0]]),
+            (8, &[&[1, 11, 21], &[0, 10, 20]]),
+            (9, &[&[0, 1], &[10, 11], &[20, 21]]),
+        ];
+        for (orientation, pixels) in expected {
+            assert_eq!(upright(orientation), pixels, "orientation {orientation}");
+        }
+    }
+
+    #[test]
+    fn weighs_channels_for_grayscale() {
+        let colors = [[200, 100, 50], [255, 0, 0], [0, 255, 0], [0, 0, 255], [1, 2, 2]];
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 1, |x, _| Rgb(colors[x as usize])));
+        let gray = |weighting| values(&grayscale(&img, weighting));
+        // BT.709, 0.2126 R + 0.7152 G + 0.0722 B, rounded down by the image crate.
+        assert_eq!(gray(Grayscale::Luma), [117, 54, 182, 18, 1]);
+        assert_eq!(gray(Grayscale::Average), [117, 85, 85, 85, 2]);
+        assert_eq!(gray(Grayscale::Lightness), [125, 128, 128, 128, 2]);
+        assert_eq!(gray(Grayscale::Red), [200, 255, 0, 0, 1]);
+        assert_eq!(gray(Grayscale::Green), [100, 0, 255, 0, 2]);
+        assert_eq!(gray(Grayscale::Blue), [50, 0, 0, 255, 2]);
+    }
+
+    #[test]
+    fn adaptive_threshold_centres_uniform_images() {
+        let gray = GrayImage::from_pixel(9, 7, Luma([100]));
+        assert!(values(&adaptive_threshold(&gray, LocalThreshold::Mean, 5, 0)).iter().all(|&v| v == 128));
+        // Without contrast Sauvola's threshold is 0.8 of the mean: 100 - 80 + 128.
+        assert!(values(&adaptive_threshold(&gray, LocalThreshold::Sauvola, 5, 0)).iter().all(|&v| v == 148));
+        assert!(values(&adaptive_threshold(&gray, LocalThreshold::Mean, 5, 10)).iter().all(|&v| v == 138));
+    }
+
+    #[test]
+    fn adaptive_threshold_separates_text_from_a_gradient() {
+        let text = |x: u32, y: u32| (9..12).contains(&y) && ((9..12).contains(&x) || (49..52).contains(&x));
+        let gray = GrayImage::from_fn(64, 21, |x, y| Luma([(30 + x * 3) as u8 - if text(x, y) { 50 } else { 0 }]));
+        // No single threshold works: the text on the bright side outshines the dark background.
+        assert!(gray.get_pixel(50, 10)[0] > gray.get_pixel(10, 0)[0]);
+        for method in [LocalThreshold::Mean, LocalThreshold::Sauvola] {
+            let even = adaptive_threshold(&gray, method, 15, 0);
+            let (mut darkest_background, mut brightest_text) = (255, 0);
+            for (x, y, pixel) in even.enumerate_pixels().filter(|&(x, _, _)| (7..57).contains(&x)) {
+                if text(x, y) {
+                    brightest_text = brightest_text.max(pixel[0]);
+                } else {
+                    darkest_background = darkest_background.min(pixel[0]);
+                }
+            }
+            assert!(brightest_text + 20 < darkest_background, "{method:?}: {brightest_text} vs {darkest_background}");
+        }
+    }
+
+    #[test]
+    fn adaptive_threshold_handles_windows_past_the_image() {
+        let single = GrayImage::from_pixel(1, 1, Luma([77]));
+        assert_eq!(values(&adaptive_threshold(&single, LocalThreshold::Mean, 31, 0)), [128]);
+        assert_eq!(values(&adaptive_threshold(&single, LocalThreshold::Sauvola, 31, 0)), [143]);
+        // Every window covers the whole image, so each pixel is measured against the image mean of 40.
+        let gray = GrayImage::from_fn(4, 2, |x, y| Luma([(x * 20 + y * 10) as u8]));
+        let even = adaptive_threshold(&gray, LocalThreshold::Mean, 255, 0);
+        assert_eq!(values(&even), [93, 113, 133, 153, 103, 123, 143, 163]);
+    }
+
+    #[test]
+    fn line_art_leaves_flat_images_blank() {
+        for value in [0, 90, 255] {
+            let gray = GrayImage::from_pixel(12, 8, Luma([value]));
+            let draw = |bright| values(&line_art(&gray, DEFAULT_LINE_SIGMA, DEFAULT_LINE_THRESHOLD, bright));
+            assert!(draw(false).iter().all(|&v| v == 255));
+            assert!(draw(true).iter().all(|&v| v == 0));
+        }
+    }
+
+    #[test]
+    fn line_art_draws_steps() {
+        let gray = GrayImage::from_fn(20, 5, |x, _| Luma([if x < 10 { 60 } else { 200 }]));
+        let lines = line_art(&gray, DEFAULT_LINE_SIGMA, DEFAULT_LINE_THRESHOLD, false);
+        for (x, y, pixel) in lines.enumerate_pixels() {
+            match x {
+                // The line runs along the dark side of the step.
+                8 | 9 => assert!(pixel[0] < 32, "{x},{y} is {}", pixel[0]),
+                7 => {}
+                _ => assert_eq!(pixel[0], 255, "{x},{y}"),
+            }
+        }
+        let bright = line_art(&gray, DEFAULT_LINE_SIGMA, DEFAULT_LINE_THRESHOLD, true);
+        assert!(bright.pixels().zip(lines.pixels()).all(|(a, b)| a[0] == 255 - b[0]));
+    }
+}
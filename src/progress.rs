@@ -0,0 +1,166 @@
+use crate::mapper::RowProgress;
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// --- CONVERSION PROGRESS ---
+
+/// Trackers older than this are dropped even if nobody ever watched them.
+const TRACKER_LIFETIME: Duration = Duration::from_secs(600);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Stage {
+    Waiting = 0,
+    Decoded = 1,
+    Resized = 2,
+    Converting = 3,
+    Done = 4,
+    Failed = 5,
+}
+
+impl Stage {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Stage::Decoded,
+            2 => Stage::Resized,
+            3 => Stage::Converting,
+            4 => Stage::Done,
+            5 => Stage::Failed,
+            _ => Stage::Waiting,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Waiting => "waiting",
+            Stage::Decoded => "decoded",
+            Stage::Resized => "resized",
+            Stage::Converting => "converting",
+            Stage::Done => "done",
+            Stage::Failed => "failed",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, Stage::Done | Stage::Failed)
+    }
+}
+
+/// Progress of a single conversion, updated from the blocking pool and read by SSE streams.
+pub struct Progress {
+    stage: AtomicU8,
+    rows_done: AtomicU32,
+    rows_total: AtomicU32,
+    created: Instant,
+}
+
+impl Progress {
+    fn new() -> Self {
+        Self {
+            stage: AtomicU8::new(Stage::Waiting as u8),
+            rows_done: AtomicU32::new(0),
+            rows_total: AtomicU32::new(0),
+            created: Instant::now(),
+        }
+    }
+
+    pub fn set_stage(&self, stage: Stage) {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::from_u8(self.stage.load(Ordering::Relaxed))
+    }
+
+    /// Overall completion: decoding and resizing count for the first 20%, rows for the rest.
+    pub fn percent(&self) -> u32 {
+        match self.stage() {
+            Stage::Waiting => 0,
+            Stage::Decoded => 10,
+            Stage::Resized => 20,
+            Stage::Converting => {
+                let total = self.rows_total.load(Ordering::Relaxed).max(1);
+                let done = self.rows_done.load(Ordering::Relaxed).min(total);
+                20 + done * 80 / total
+            }
+            Stage::Done | Stage::Failed => 100,
+        }
+    }
+
+    fn event(&self) -> Bytes {
+        let stage = self.stage();
+        let event = if stage.is_finished() { stage.name() } else { "progress" };
+        Bytes::from(format!(
+            "event: {}\ndata: {{\"stage\":\"{}\",\"percent\":{}}}\n\n",
+            event,
+            stage.name(),
+            self.percent()
+        ))
+    }
+}
+
+impl RowProgress for Progress {
+    fn start_rows(&self, total: u32) {
+        self.rows_total.store(total, Ordering::Relaxed);
+        self.rows_done.store(0, Ordering::Relaxed);
+        self.set_stage(Stage::Converting);
+    }
+
+    fn row_done(&self) {
+        self.rows_done.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Job ID to progress tracker map shared between upload handlers and SSE streams.
+#[derive(Default)]
+pub struct ProgressRegistry {
+    trackers: Mutex<HashMap<String, Arc<Progress>>>,
+}
+
+impl ProgressRegistry {
+    /// Job IDs are chosen by the client, so only accept short, URL-safe values.
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }
+
+    /// Returns the tracker for `id`, creating it if neither side has registered it yet.
+    pub fn tracker(&self, id: &str) -> Arc<Progress> {
+        let mut trackers = self.trackers.lock().unwrap();
+        trackers.retain(|_, progress| progress.created.elapsed() < TRACKER_LIFETIME);
+        trackers.entry(id.to_string()).or_insert_with(|| Arc::new(Progress::new())).clone()
+    }
+
+    /// Forgets the tracker for `id`, for conversions that will never report to it.
+    pub fn remove(&self, id: &str) {
+        self.trackers.lock().unwrap().remove(id);
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.trackers.lock().unwrap().is_empty()
+    }
+
+    /// Streams progress events for `id` until the conversion finishes or the tracker expires.
+    pub fn events(self: Arc<Self>, id: String) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        let progress = self.tracker(&id);
+        stream::unfold(Some(()), move |state| {
+            let registry = self.clone();
+            let progress = progress.clone();
+            let id = id.clone();
+            async move {
+                state?;
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let finished = progress.stage().is_finished() || progress.created.elapsed() >= TRACKER_LIFETIME;
+                if finished {
+                    registry.remove(&id);
+                }
+                Some((Ok(progress.event()), if finished { None } else { Some(()) }))
+            }
+        })
+    }
+}
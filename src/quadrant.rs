@@ -0,0 +1,54 @@
+use crate::mapper::{CharMapper, Pixels};
+
+// --- QUADRANT BLOCKS ---
+
+/// Block elements indexed by which quarters are inked: bit 0 is the top left, bit 1 the
+/// top right, bit 2 the bottom left and bit 3 the bottom right.
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Draws every 2x2 group of pixels as one quadrant character. A pixel is inked when it
+/// is brighter than the image's mean brightness (darker with `invert`), so the split
+/// follows the picture's own exposure; transparent pixels are never inked.
+pub struct Quadrant {
+    pub invert: bool,
+}
+
+impl CharMapper for Quadrant {
+    fn cell_size(&self) -> (u32, u32) {
+        (2, 2)
+    }
+
+    fn map_cell(&self, pixels: &Pixels, x: u32, y: u32) -> char {
+        let inked = |dx: u32, dy: u32| pixels.inked(x + dx, y + dy, self.invert) as usize;
+        QUADRANTS[inked(0, 0) | inked(1, 0) << 1 | inked(0, 1) << 2 | inked(1, 1) << 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    fn draw(bright: &[(u32, u32)], (width, height): (u32, u32), mask: Option<&GrayImage>, invert: bool) -> String {
+        let gray = GrayImage::from_fn(width, height, |x, y| Luma([if bright.contains(&(x, y)) { 255 } else { 0 }]));
+        Quadrant { invert }.map(&Pixels::new(&gray, None, mask), None)
+    }
+
+    #[test]
+    fn picks_the_inked_quarters() {
+        assert_eq!(draw(&[(0, 0), (2, 1), (3, 1)], (4, 2), None, false), "▘▄\n");
+        assert_eq!(draw(&[(1, 0), (0, 1)], (2, 2), None, false), "▞\n");
+        assert_eq!(draw(&[(0, 0), (2, 1), (3, 1)], (4, 2), None, true), "▟▀\n");
+    }
+
+    #[test]
+    fn leaves_missing_and_transparent_pixels_blank() {
+        // The third column has no right half.
+        assert_eq!(draw(&[(2, 0), (2, 1)], (3, 2), None, false), " ▌\n");
+        assert_eq!(draw(&[(0, 0)], (3, 2), None, true), "▟▌\n");
+        let mask = GrayImage::from_fn(2, 2, |x, _| Luma([if x == 0 { 0 } else { 255 }]));
+        assert_eq!(draw(&[(0, 0), (1, 0)], (2, 2), Some(&mask), false), "▝\n");
+    }
+}
@@ -0,0 +1,162 @@
+// --- PALETTE QUANTIZATION ---
+
+/// The 16 standard terminal colors in SGR order (xterm defaults): black, red, green,
+/// yellow, blue, magenta, cyan, white, then their bright variants.
+pub const ANSI_16: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// The xterm 256-color palette: the 16 standard colors, a 6x6x6 color cube and a
+/// 24-step gray ramp.
+pub const XTERM_256: [[u8; 3]; 256] = xterm_256();
+
+/// The mIRC 99-color palette: 16 classic colors (mIRC's defaults), then 72 shades in
+/// six rows of hues and an 11-step gray ramp.
+pub const MIRC_99: [[u8; 3]; 99] = [
+    [255, 255, 255], [0, 0, 0], [0, 0, 127], [0, 147, 0], [255, 0, 0], [127, 0, 0],
+    [156, 0, 156], [252, 127, 0], [255, 255, 0], [0, 252, 0], [0, 147, 147], [0, 255, 255],
+    [0, 0, 252], [255, 0, 255], [127, 127, 127], [210, 210, 210],
+
+    [71, 0, 0], [71, 33, 0], [71, 71, 0], [50, 71, 0], [0, 71, 0], [0, 71, 44],
+    [0, 71, 71], [0, 39, 71], [0, 0, 71], [46, 0, 71], [71, 0, 71], [71, 0, 42],
+
+    [116, 0, 0], [116, 58, 0], [116, 116, 0], [81, 116, 0], [0, 116, 0], [0, 116, 73],
+    [0, 116, 116], [0, 64, 116], [0, 0, 116], [75, 0, 116], [116, 0, 116], [116, 0, 69],
+
+    [181, 0, 0], [181, 99, 0], [181, 181, 0], [125, 181, 0], [0, 181, 0], [0, 181, 113],
+    [0, 181, 181], [0, 99, 181], [0, 0, 181], [117, 0, 181], [181, 0, 181], [181, 0, 107],
+
+    [255, 0, 0], [255, 140, 0], [255, 255, 0], [178, 255, 0], [0, 255, 0], [0, 255, 160],
+    [0, 255, 255], [0, 140, 255], [0, 0, 255], [165, 0, 255], [255, 0, 255], [255, 0, 152],
+
+    [255, 89, 89], [255, 180, 89], [255, 255, 113], [207, 255, 96], [111, 255, 111], [101, 255, 201],
+    [109, 255, 255], [89, 180, 255], [89, 89, 255], [196, 89, 255], [255, 102, 255], [255, 89, 188],
+
+    [255, 156, 156], [255, 211, 156], [255, 255, 156], [226, 255, 156], [156, 255, 156], [156, 255, 219],
+    [156, 255, 255], [156, 211, 255], [156, 156, 255], [220, 156, 255], [255, 156, 255], [255, 148, 211],
+
+    [0, 0, 0], [19, 19, 19], [40, 40, 40], [54, 54, 54], [77, 77, 77], [101, 101, 101],
+    [129, 129, 129], [159, 159, 159], [188, 188, 188], [226, 226, 226], [255, 255, 255],
+];
+
+/// Levels of each channel in the color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const fn xterm_256() -> [[u8; 3]; 256] {
+    let mut palette = [[0; 3]; 256];
+    let mut i = 0;
+    while i < 16 {
+        palette[i] = ANSI_16[i];
+        i += 1;
+    }
+    while i < 232 {
+        let cube = i - 16;
+        palette[i] = [CUBE_LEVELS[cube / 36], CUBE_LEVELS[cube / 6 % 6], CUBE_LEVELS[cube % 6]];
+        i += 1;
+    }
+    while i < 256 {
+        let level = 8 + 10 * (i - 232) as u8;
+        palette[i] = [level, level, level];
+        i += 1;
+    }
+    palette
+}
+
+/// Index of the palette entry closest to `color` by squared RGB distance; ties go to
+/// the lower index. `palette` must not be empty.
+pub fn nearest(color: [u8; 3], palette: &[[u8; 3]]) -> usize {
+    let distance = |entry: &[u8; 3]| -> u32 {
+        entry.iter().zip(color).map(|(&a, b)| (i32::from(a) - i32::from(b)).pow(2) as u32).sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Nearest xterm-256 index, skipping the 16 standard colors, which terminals let users
+/// redefine.
+pub fn nearest_256(color: [u8; 3]) -> u8 {
+    (16 + nearest(color, &XTERM_256[16..])) as u8
+}
+
+/// Nearest of the 16 standard colors.
+pub fn nearest_16(color: [u8; 3]) -> u8 {
+    nearest(color, &ANSI_16) as u8
+}
+
+/// Nearest mIRC color code.
+pub fn nearest_mirc(color: [u8; 3]) -> u8 {
+    nearest(color, &MIRC_99) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_layout() {
+        assert_eq!(XTERM_256[15], [255, 255, 255]);
+        assert_eq!(XTERM_256[16], [0, 0, 0]);
+        assert_eq!(XTERM_256[196], [255, 0, 0]);
+        assert_eq!(XTERM_256[231], [255, 255, 255]);
+        assert_eq!(XTERM_256[232], [8, 8, 8]);
+        assert_eq!(XTERM_256[255], [238, 238, 238]);
+    }
+
+    #[test]
+    fn exact_entries_map_to_themselves() {
+        for (index, &color) in ANSI_16.iter().enumerate() {
+            assert_eq!(nearest_16(color) as usize, index);
+        }
+        for index in [21, 46, 100, 196, 226, 240] {
+            assert_eq!(nearest_256(XTERM_256[index]) as usize, index);
+        }
+    }
+
+    #[test]
+    fn picks_closest_color() {
+        assert_eq!(nearest_16([250, 10, 10]), 9);
+        assert_eq!(nearest_16([20, 20, 20]), 0);
+        assert_eq!(nearest_16([140, 140, 140]), 8);
+        assert_eq!(nearest_256([250, 10, 10]), 196);
+        // Grays fall on the ramp, which is finer than the cube's diagonal.
+        assert_eq!(nearest_256([128, 128, 128]), 244);
+    }
+
+    #[test]
+    fn mirc_palette() {
+        assert_eq!(nearest_mirc([255, 255, 255]), 0);
+        assert_eq!(nearest_mirc([0, 0, 0]), 1);
+        assert_eq!(nearest_mirc([250, 5, 5]), 4);
+        assert_eq!(nearest_mirc([0xb5, 0x63, 0x00]), 41);
+        assert_eq!(nearest_mirc([0x4d, 0x4d, 0x4d]), 92);
+    }
+
+    #[test]
+    fn skips_standard_colors_in_256_mode() {
+        assert_eq!(nearest_256([0, 0, 0]), 16);
+        assert_eq!(nearest_256([255, 255, 255]), 231);
+    }
+
+    #[test]
+    fn ties_go_to_lower_index() {
+        assert_eq!(nearest([5, 5, 5], &[[0, 0, 0], [10, 10, 10]]), 0);
+    }
+}
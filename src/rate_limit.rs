@@ -0,0 +1,340 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::{Error, HttpResponse};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// --- PER-IP RATE LIMITING ---
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Suggested wait when a client is at its concurrency limit.
+const BUSY_RETRY_AFTER_SECS: u64 = 5;
+/// Proxies trusted when `ASCII_TRUSTED_PROXIES` is unset: one on the same host.
+pub const DEFAULT_TRUSTED_PROXIES: &str = "127.0.0.1,::1";
+/// The bucket shared by requests that carry no client address, such as those arriving
+/// over a Unix socket without a forwarding header.
+const UNKNOWN_CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+#[derive(Default)]
+struct ClientState {
+    window_start: Option<Instant>,
+    requests: u32,
+    in_flight: u32,
+}
+
+/// Limits conversion requests (every POST) per client IP: a fixed one-minute request
+/// budget plus a cap on simultaneously running conversions. A limit of zero disables it.
+pub struct RateLimiter {
+    requests_per_minute: u32,
+    max_concurrent: u32,
+    trust_proxy_headers: bool,
+    trusted_proxies: Vec<ProxyRange>,
+    clients: Mutex<HashMap<IpAddr, ClientState>>,
+}
+
+/// An address or CIDR range of trusted proxies.
+#[derive(Debug, PartialEq)]
+struct ProxyRange {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl ProxyRange {
+    fn parse(text: &str) -> Option<Self> {
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address.trim().parse::<IpAddr>().ok()?, Some(prefix.trim().parse::<u32>().ok()?)),
+            None => (text.parse::<IpAddr>().ok()?, None),
+        };
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Self { network: address, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (u128::from(u32::from(network)), u128::from(u32::from(ip)), 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let mask = u128::MAX.checked_shl(bits - self.prefix).unwrap_or(0) & (u128::MAX >> (128 - bits));
+        network & mask == ip & mask
+    }
+}
+
+enum Decision {
+    Allowed(InFlightGuard),
+    Limited { retry_after: u64 },
+}
+
+/// Releases the client's concurrency slot when the request finishes.
+struct InFlightGuard {
+    limiter: Arc<RateLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(client) = self.limiter.clients.lock().unwrap().get_mut(&self.ip) {
+            client.in_flight = client.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, max_concurrent: u32, trust_proxy_headers: bool) -> Self {
+        Self {
+            requests_per_minute,
+            max_concurrent,
+            trust_proxy_headers,
+            trusted_proxies: Vec::new(),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Believes `X-Forwarded-For` only from the comma-separated addresses or CIDR ranges in
+    /// `proxies`. Invalid entries are logged and skipped.
+    pub fn with_trusted_proxies(mut self, proxies: &str) -> Self {
+        self.trusted_proxies = proxies
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let range = ProxyRange::parse(entry);
+                if range.is_none() {
+                    tracing::warn!(value = entry, "ignoring invalid trusted proxy entry");
+                }
+                range
+            })
+            .collect();
+        self
+    }
+
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requests_per_minute > 0 || self.max_concurrent > 0
+    }
+
+    /// The client a request counts against. Behind a trusted proxy (or on a Unix socket,
+    /// which only a local proxy can reach) that is the rightmost `X-Forwarded-For` entry not
+    /// itself a trusted proxy: each proxy appends the address it was reached from, so
+    /// everything left of that is whatever the client chose to send.
+    fn client_ip(&self, req: &ServiceRequest) -> IpAddr {
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        if !self.trust_proxy_headers || peer.is_some_and(|peer| !self.is_trusted_proxy(peer)) {
+            return peer.unwrap_or(UNKNOWN_CLIENT);
+        }
+        let mut client = peer.unwrap_or(UNKNOWN_CLIENT);
+        let forwarded = req.headers().get_all("X-Forwarded-For").filter_map(|value| value.to_str().ok());
+        let hops: Vec<&str> = forwarded.flat_map(|value| value.split(',')).map(str::trim).collect();
+        for hop in hops.iter().rev() {
+            // An entry that is not an address ends the chain at the proxy that added it.
+            let Some(ip) = parse_hop(hop) else { break };
+            client = ip;
+            if !self.is_trusted_proxy(ip) {
+                break;
+            }
+        }
+        client
+    }
+
+    fn check(self: &Arc<Self>, ip: IpAddr) -> Decision {
+        let mut clients = self.clients.lock().unwrap();
+        let now = Instant::now();
+        if clients.len() > 10_000 {
+            clients.retain(|_, client| {
+                client.in_flight > 0 || client.window_start.is_some_and(|start| now - start < WINDOW)
+            });
+        }
+
+        let client = clients.entry(ip).or_default();
+        let window_start = match client.window_start {
+            Some(start) if now - start < WINDOW => start,
+            _ => {
+                client.requests = 0;
+                client.window_start = Some(now);
+                now
+            }
+        };
+
+        if self.requests_per_minute > 0 && client.requests >= self.requests_per_minute {
+            let remaining = WINDOW.saturating_sub(now - window_start);
+            return Decision::Limited { retry_after: remaining.as_secs().max(1) };
+        }
+        if self.max_concurrent > 0 && client.in_flight >= self.max_concurrent {
+            return Decision::Limited { retry_after: BUSY_RETRY_AFTER_SECS };
+        }
+
+        client.requests += 1;
+        client.in_flight += 1;
+        Decision::Allowed(InFlightGuard { limiter: self.clone(), ip })
+    }
+}
+
+/// An `X-Forwarded-For` entry: an address, possibly with a port and IPv6 brackets.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .ok()
+        .or_else(|| hop.parse::<std::net::SocketAddr>().ok().map(|socket| socket.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// Middleware factory applying a shared [`RateLimiter`].
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service, limiter: self.limiter.clone() }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if req.method() != Method::POST {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        match self.limiter.check(self.limiter.client_ip(&req)) {
+            Decision::Allowed(guard) => {
+                let fut = self.service.call(req);
+                Box::pin(async move {
+                    let res = fut.await;
+                    drop(guard);
+                    Ok(res?.map_into_left_body())
+                })
+            }
+            Decision::Limited { retry_after } => {
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                    .body("Too many requests, please slow down.");
+                Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, last))
+    }
+
+    #[test]
+    fn limits_requests_per_window() {
+        let limiter = Arc::new(RateLimiter::new(2, 0, false));
+        assert!(matches!(limiter.check(ip(1)), Decision::Allowed(_)));
+        assert!(matches!(limiter.check(ip(1)), Decision::Allowed(_)));
+        assert!(matches!(limiter.check(ip(1)), Decision::Limited { retry_after } if (1..=60).contains(&retry_after)));
+        assert!(matches!(limiter.check(ip(2)), Decision::Allowed(_)));
+    }
+
+    #[test]
+    fn limits_concurrent_requests_until_they_finish() {
+        let limiter = Arc::new(RateLimiter::new(0, 1, false));
+        let running = limiter.check(ip(1));
+        assert!(matches!(limiter.check(ip(1)), Decision::Limited { retry_after: BUSY_RETRY_AFTER_SECS }));
+        drop(running);
+        assert!(matches!(limiter.check(ip(1)), Decision::Allowed(_)));
+    }
+
+    #[test]
+    fn identifies_clients() {
+        let peer = "198.51.100.7:4000".parse().unwrap();
+        let direct = RateLimiter::new(1, 0, false);
+        let proxied = RateLimiter::new(1, 0, true).with_trusted_proxies("198.51.100.0/24");
+        let req = TestRequest::default().peer_addr(peer).insert_header(("X-Forwarded-For", "203.0.113.9")).to_srv_request();
+        assert_eq!(direct.client_ip(&req), peer.ip());
+        assert_eq!(proxied.client_ip(&req), ip(9));
+        // Requests over a Unix socket have no peer address.
+        assert_eq!(direct.client_ip(&TestRequest::default().to_srv_request()), UNKNOWN_CLIENT);
+        assert!(!RateLimiter::new(0, 0, false).is_enabled());
+    }
+
+    #[test]
+    fn ignores_spoofed_forwarding_entries() {
+        let limiter = RateLimiter::new(1, 0, true).with_trusted_proxies("127.0.0.1, 10.0.0.0/8");
+        let via = |peer: Option<&str>, headers: &[&str]| {
+            let mut req = TestRequest::default();
+            if let Some(peer) = peer {
+                req = req.peer_addr(peer.parse().unwrap());
+            }
+            for header in headers {
+                req = req.append_header(("X-Forwarded-For", *header));
+            }
+            limiter.client_ip(&req.to_srv_request())
+        };
+        // The client sent its own entries; the local proxy appended the real address.
+        assert_eq!(via(Some("127.0.0.1:50000"), &["1.2.3.4, 5.6.7.8, 203.0.113.9"]), ip(9));
+        // Two trusted hops.
+        assert_eq!(via(Some("127.0.0.1:50000"), &["6.6.6.6", "203.0.113.9, 10.1.2.3"]), ip(9));
+        // Over a Unix socket, with an address that is not one.
+        assert_eq!(via(None, &["203.0.113.9, unknown, 10.1.2.3"]), "10.1.2.3".parse::<IpAddr>().unwrap());
+        assert_eq!(via(None, &["[2001:db8::1]:443"]), "2001:db8::1".parse::<IpAddr>().unwrap());
+        // The header is not believed from anyone else.
+        assert_eq!(via(Some("192.0.2.1:50000"), &["203.0.113.9"]), "192.0.2.1".parse::<IpAddr>().unwrap());
+        // Every spoofed value the client makes up lands in the same bucket.
+        let buckets: std::collections::HashSet<_> =
+            (0..5).map(|n| via(Some("127.0.0.1:1"), &[&format!("10.9.9.{}, 198.51.100.1", n)])).collect();
+        assert_eq!(buckets.len(), 1);
+    }
+
+    #[test]
+    fn matches_proxy_ranges() {
+        let range = ProxyRange::parse("10.0.0.0/8").unwrap();
+        assert!(range.contains("10.255.0.1".parse().unwrap()));
+        assert!(range.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!range.contains("11.0.0.1".parse().unwrap()));
+        assert!(ProxyRange::parse("::1").unwrap().contains("::1".parse().unwrap()));
+        assert!(ProxyRange::parse("fd00::/8").unwrap().contains("fdab::1".parse().unwrap()));
+        assert!(ProxyRange::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(ProxyRange::parse("10.0.0.0/33").is_none());
+        assert!(ProxyRange::parse("proxy.local").is_none());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::form::UploadForm;
+use actix_web::{delete, get, put, web, HttpResponse};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// --- SAVED RECIPES ---
+
+/// Form field values by field name.
+pub type Fields = BTreeMap<String, String>;
+
+/// Named sets of conversion form fields, applied with `recipe=<name>`. Kept in memory
+/// and, when a file is configured, written back to it as JSON on every change.
+pub struct RecipeStore {
+    recipes: Mutex<BTreeMap<String, Fields>>,
+    file: Option<PathBuf>,
+}
+
+/// A recipe as exported by `GET /api/recipes/{name}`.
+#[derive(Serialize, ToSchema)]
+pub struct Recipe {
+    name: String,
+    /// Form fields and their values, as accepted by `/upload`.
+    #[schema(example = json!({"theme": "light", "preset": "blocks", "full_resolution": "true"}))]
+    fields: Fields,
+}
+
+/// Body of `PUT /api/recipes/{name}`; an exported recipe can be sent back as is.
+#[derive(Deserialize, ToSchema)]
+pub struct RecipeBody {
+    /// Form fields to save; values may be strings, numbers or booleans.
+    #[schema(value_type = Object)]
+    fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl RecipeStore {
+    /// Loads the recipes saved in `file`, which need not exist yet.
+    pub fn open(file: Option<PathBuf>) -> Result<Self> {
+        let recipes = match &file {
+            Some(path) if path.exists() => {
+                let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+                serde_json::from_str(&json).with_context(|| format!("Invalid recipes file {}", path.display()))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Self { recipes: Mutex::new(recipes), file })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Fields> {
+        self.recipes.lock().unwrap().get(name).cloned()
+    }
+
+    fn save(&self, name: &str, fields: Fields) -> Result<()> {
+        let mut recipes = self.recipes.lock().unwrap();
+        recipes.insert(name.to_string(), fields);
+        self.persist(&recipes)
+    }
+
+    fn remove(&self, name: &str) -> Result<bool> {
+        let mut recipes = self.recipes.lock().unwrap();
+        let removed = recipes.remove(name).is_some();
+        if removed {
+            self.persist(&recipes)?;
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the file atomically, so a crash mid-write never loses every recipe.
+    fn persist(&self, recipes: &BTreeMap<String, Fields>) -> Result<()> {
+        let Some(path) = &self.file else { return Ok(()) };
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let temp = tempfile::NamedTempFile::new_in(dir).context("Failed to create the recipes file")?;
+        serde_json::to_writer_pretty(&temp, recipes).context("Failed to write the recipes file")?;
+        temp.persist(path).context("Failed to replace the recipes file")?;
+        Ok(())
+    }
+}
+
+/// Recipe names: up to 64 letters, digits, dashes and underscores.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+/// Names of all saved recipes.
+#[utoipa::path(
+    context_path = "/api",
+    tag = "recipes",
+    responses((status = 200, description = "Saved recipe names", body = Vec<String>)),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[get("/recipes")]
+async fn list_recipes(store: web::Data<RecipeStore>) -> HttpResponse {
+    let names: Vec<String> = store.recipes.lock().unwrap().keys().cloned().collect();
+    HttpResponse::Ok().json(names)
+}
+
+/// Exports a recipe as JSON.
+#[utoipa::path(
+    context_path = "/api",
+    tag = "recipes",
+    params(("name" = String, Path, description = "Recipe name")),
+    responses(
+        (status = 200, description = "The recipe", body = Recipe),
+        (status = 404, description = "No such recipe"),
+    ),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[get("/recipes/{name}")]
+async fn get_recipe(name: web::Path<String>, store: web::Data<RecipeStore>) -> HttpResponse {
+    match store.get(&name) {
+        Some(fields) => HttpResponse::Ok().json(Recipe { name: name.into_inner(), fields }),
+        None => HttpResponse::NotFound().body("No such recipe."),
+    }
+}
+
+/// Saves or replaces a recipe. The fields are checked like a form submission, so a
+/// recipe that saves is one that applies.
+#[utoipa::path(
+    context_path = "/api",
+    tag = "recipes",
+    params(("name" = String, Path, description = "Recipe name: letters, digits, dashes and underscores")),
+    request_body = RecipeBody,
+    responses(
+        (status = 200, description = "Saved", body = Recipe),
+        (status = 400, description = "Invalid name, unknown field or invalid value"),
+    ),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[put("/recipes/{name}")]
+async fn put_recipe(
+    name: web::Path<String>,
+    body: web::Json<RecipeBody>,
+    store: web::Data<RecipeStore>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_valid_name(&name) {
+        return Ok(HttpResponse::BadRequest().body("Recipe names are letters, digits, dashes and underscores."));
+    }
+    let fields: Fields = body
+        .into_inner()
+        .fields
+        .into_iter()
+        .map(|(field, value)| {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                other => other.to_string(),
+            };
+            (field, value)
+        })
+        .collect();
+    // Checking may load an installed font from disk.
+    let checked = fields.clone();
+    if let Err(err) = web::block(move || UploadForm::check_recipe(&checked)).await? {
+        return Ok(HttpResponse::BadRequest().body(err.to_string()));
+    }
+    let saved = {
+        let (store, name, fields) = (store.clone(), name.clone(), fields.clone());
+        web::block(move || store.save(&name, fields)).await?
+    };
+    if let Err(err) = saved {
+        tracing::error!(error = %format!("{:#}", err), "failed to save recipe");
+        return Ok(HttpResponse::InternalServerError().body("Failed to save the recipe."));
+    }
+    Ok(HttpResponse::Ok().json(Recipe { name: name.into_inner(), fields }))
+}
+
+#[utoipa::path(
+    context_path = "/api",
+    tag = "recipes",
+    params(("name" = String, Path, description = "Recipe name")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "No such recipe"),
+    ),
+    security((), ("bearer" = []), ("api_key" = [])),
+)]
+#[delete("/recipes/{name}")]
+async fn delete_recipe(name: web::Path<String>, store: web::Data<RecipeStore>) -> Result<HttpResponse, actix_web::Error> {
+    let removed = web::block(move || store.remove(&name)).await?;
+    Ok(match removed {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body("No such recipe."),
+        Err(err) => {
+            tracing::error!(error = %format!("{:#}", err), "failed to delete recipe");
+            HttpResponse::InternalServerError().body("Failed to delete the recipe.")
+        }
+    })
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_recipes).service(get_recipe).service(put_recipe).service(delete_recipe);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use serde_json::json;
+
+    #[test]
+    fn checks_names() {
+        assert!(is_valid_name("night-mode_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../recipes"));
+        assert!(!is_valid_name("with space"));
+        assert!(!is_valid_name(&"a".repeat(65)));
+    }
+
+    #[actix_web::test]
+    async fn exports_what_was_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("recipes.json");
+        let store = web::Data::new(RecipeStore::open(Some(file.clone())).unwrap());
+        let app = test::init_service(App::new().app_data(store).configure(configure)).await;
+
+        let body = json!({"fields": {"theme": "light", "full_resolution": true, "adaptive_block": 15}});
+        let req = TestRequest::put().uri("/recipes/poster").set_json(&body).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let exported: serde_json::Value =
+            test::call_and_read_body_json(&app, TestRequest::get().uri("/recipes/poster").to_request()).await;
+        assert_eq!(
+            exported,
+            json!({"name": "poster", "fields": {"adaptive_block": "15", "full_resolution": "true", "theme": "light"}})
+        );
+
+        // An export can be sent back as is, and survives a restart.
+        let req = TestRequest::put().uri("/recipes/copy").set_json(&exported).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let reopened = RecipeStore::open(Some(file)).unwrap();
+        assert_eq!(reopened.get("copy"), reopened.get("poster"));
+        assert_eq!(reopened.get("copy").unwrap()["adaptive_block"], "15");
+    }
+
+    #[actix_web::test]
+    async fn refuses_invalid_recipes() {
+        let store = web::Data::new(RecipeStore::open(None).unwrap());
+        let app = test::init_service(App::new().app_data(store.clone()).configure(configure)).await;
+        for (name, fields) in [
+            ("bad name", json!({"theme": "light"})),
+            ("unknown", json!({"no_such_field": "1"})),
+            ("nested", json!({"recipe": "other"})),
+        ] {
+            let req = TestRequest::put()
+                .uri(&format!("/recipes/{}", name.replace(' ', "%20")))
+                .set_json(json!({ "fields": fields }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST, "{name}");
+        }
+        assert!(store.recipes.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::braille;
+use crate::font::UserFont;
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use font8x8::{UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS};
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use std::fmt::Write;
+use std::io::Cursor;
+
+// --- BITMAP RENDERING ---
+
+const GLYPH_WIDTH: u32 = 8;
+/// Glyph rows are drawn twice: the converter assumes characters twice as tall as wide.
+const CELL_HEIGHT: u32 = 16;
+/// Refuse to render bitmaps larger than this (about 48 MiB of RGB).
+const MAX_PIXELS: u64 = 16 * 1024 * 1024;
+
+/// Renders the art into a PNG with the theme colors, using `font` when given and the
+/// built-in 8x8 bitmap font otherwise.
+pub fn render_png(ascii_art: &str, background: &str, foreground: &str, font: Option<&UserFont>) -> Result<Vec<u8>> {
+    let img = render_bitmap(ascii_art, background, foreground, font)?;
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageOutputFormat::Png).context("Failed to encode PNG")?;
+    Ok(png.into_inner())
+}
+
+/// Draws the art like [`render_png`] without encoding it.
+pub fn render_bitmap(ascii_art: &str, background: &str, foreground: &str, font: Option<&UserFont>) -> Result<RgbImage> {
+    let (cell_width, cell_height) = font.map_or((GLYPH_WIDTH, CELL_HEIGHT), UserFont::cell_size);
+    let (columns, rows) = grid_size(ascii_art);
+    let (width, height) = (columns.max(1) * cell_width, rows.max(1) * cell_height);
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        bail!("The art is too large to render as an image ({}x{} characters)", columns, rows);
+    }
+
+    let foreground = parse_hex_color(foreground)?;
+    let mut img = RgbImage::from_pixel(width, height, parse_hex_color(background)?);
+    for (row, line) in ascii_art.lines().enumerate() {
+        for (column, ch) in line.chars().enumerate() {
+            let (x0, y0) = (column as u32 * cell_width, row as u32 * cell_height);
+            if let Some(font) = font {
+                font.draw(&mut img, ch, x0, y0, foreground);
+                continue;
+            }
+            let Some(glyph) = glyph(ch) else { continue };
+            for (glyph_row, bits) in glyph.iter().enumerate() {
+                for bit in 0..GLYPH_WIDTH {
+                    if bits & (1 << bit) != 0 {
+                        let y = y0 + glyph_row as u32 * 2;
+                        img.put_pixel(x0 + bit, y, foreground);
+                        img.put_pixel(x0 + bit, y + 1, foreground);
+                    }
+                }
+            }
+        }
+    }
+    Ok(img)
+}
+
+/// Family name the embedded font is registered under in SVG output.
+const SVG_FONT_FAMILY: &str = "ascii-art-font";
+
+/// Renders the art as an SVG document with one `<text>` element per row. A given `font`
+/// is embedded, so the file looks the same wherever it is opened; otherwise the viewer's
+/// monospace font is used. Rows are stretched to exact cell widths either way.
+pub fn render_svg(ascii_art: &str, background: &str, foreground: &str, font: Option<&UserFont>) -> String {
+    let (cell_width, cell_height) = font.map_or((GLYPH_WIDTH, CELL_HEIGHT), UserFont::cell_size);
+    let (columns, rows) = grid_size(ascii_art);
+    let (width, height) = (columns.max(1) * cell_width, rows.max(1) * cell_height);
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let family = match font {
+        Some(font) => {
+            let format = if font.data().starts_with(b"OTTO") { "otf" } else { "ttf" };
+            let data = base64::engine::general_purpose::STANDARD.encode(font.data());
+            let _ = write!(
+                svg,
+                r#"<style>@font-face {{ font-family: "{SVG_FONT_FAMILY}"; src: url(data:font/{format};base64,{data}); }}</style>"#
+            );
+            format!("'{}', monospace", SVG_FONT_FAMILY)
+        }
+        None => "monospace".to_string(),
+    };
+    let _ = write!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, background);
+    let _ = write!(
+        svg,
+        r#"<g font-family="{}" font-size="{}" fill="{}" xml:space="preserve">"#,
+        family,
+        // Monospace glyphs are about 0.6em wide, so this fits the bitmap font's 8x16 cells.
+        if font.is_some() { cell_height as f32 } else { cell_width as f32 / 0.6 },
+        foreground
+    );
+    for (row, line) in ascii_art.lines().enumerate() {
+        let length = line.chars().count() as u32 * cell_width;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let baseline = row as f32 * cell_height as f32 + font.map_or(cell_height as f32 * 0.8, UserFont::ascent);
+        let _ = write!(
+            svg,
+            r#"<text x="0" y="{}" textLength="{}" lengthAdjust="spacingAndGlyphs">{}</text>"#,
+            baseline,
+            length,
+            xml_escape(line)
+        );
+    }
+    svg.push_str("</g></svg>\n");
+    svg
+}
+
+/// Columns and rows of the art.
+fn grid_size(ascii_art: &str) -> (u32, u32) {
+    let columns = ascii_art.lines().map(|line| line.chars().count()).max().unwrap_or(0) as u32;
+    (columns, ascii_art.lines().count() as u32)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// The 8x8 bitmap of `ch`, one byte per row with the leftmost pixel in the lowest bit.
+pub fn glyph(ch: char) -> Option<[u8; 8]> {
+    BASIC_FONTS
+        .get(ch)
+        .or_else(|| LATIN_FONTS.get(ch))
+        .or_else(|| BOX_FONTS.get(ch))
+        .or_else(|| BLOCK_FONTS.get(ch))
+        .or_else(|| GREEK_FONTS.get(ch))
+        .or_else(|| braille::glyph(ch))
+}
+
+/// Parses `#rrggbb`.
+pub fn parse_hex_color(color: &str) -> Result<Rgb<u8>> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+    let Some(value) = value else { bail!("Invalid color {:?}", color) };
+    Ok(Rgb([(value >> 16) as u8, (value >> 8) as u8, value as u8]))
+}
+
+/// Formats a color as `#rrggbb`.
+pub fn hex_color(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Relative brightness (Rec. 601 weights), 0-255.
+pub fn luminance(color: Rgb<u8>) -> f32 {
+    let [r, g, b] = color.0;
+    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+}
@@ -0,0 +1,112 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// --- SAUCE METADATA ---
+
+/// End-of-file marker that separates the art from its SAUCE record; viewers stop there.
+const EOF: u8 = 0x1a;
+/// SAUCE `DataType` 1 (character) with `FileType` 1 (ANSI).
+const DATA_TYPE_CHARACTER: u8 = 1;
+const FILE_TYPE_ANSI: u8 = 1;
+/// Font the art is meant for, which also tells viewers it is 8 pixels per column.
+const FONT: &str = "IBM VGA";
+
+/// The descriptive fields of a SAUCE record.
+#[derive(Default)]
+pub struct Sauce {
+    pub title: String,
+    pub author: String,
+    pub group: String,
+}
+
+impl Sauce {
+    /// Appends the end-of-file marker and the 128-byte SAUCE 00 record describing `art`,
+    /// the `width` by `height` characters written so far.
+    pub fn append_to(&self, art: &mut Vec<u8>, width: u32, height: u32) {
+        let file_size = u32::try_from(art.len()).unwrap_or(u32::MAX);
+        art.push(EOF);
+        art.extend_from_slice(b"SAUCE00");
+        push_field(art, &self.title, 35);
+        push_field(art, &self.author, 20);
+        push_field(art, &self.group, 20);
+        art.extend_from_slice(today().as_bytes());
+        art.extend_from_slice(&file_size.to_le_bytes());
+        art.extend_from_slice(&[DATA_TYPE_CHARACTER, FILE_TYPE_ANSI]);
+        art.extend_from_slice(&u16::try_from(width).unwrap_or(u16::MAX).to_le_bytes());
+        art.extend_from_slice(&u16::try_from(height).unwrap_or(u16::MAX).to_le_bytes());
+        // TInfo3 and TInfo4 are unused for ANSI, then no comment lines and no flags.
+        art.extend_from_slice(&[0; 6]);
+        let mut font = FONT.as_bytes().to_vec();
+        font.resize(22, 0);
+        art.extend_from_slice(&font);
+    }
+}
+
+/// Space-padded to `len` bytes. SAUCE text is CP437, so anything outside ASCII becomes `?`.
+fn push_field(out: &mut Vec<u8>, value: &str, len: usize) {
+    let mut field: Vec<u8> = value
+        .chars()
+        .filter(|ch| !ch.is_control())
+        .map(|ch| if ch.is_ascii() { ch as u8 } else { b'?' })
+        .take(len)
+        .collect();
+    field.resize(len, b' ');
+    out.extend_from_slice(&field);
+}
+
+/// The current UTC date as `CCYYMMDD`.
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() / 86_400) as i64;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_a_128_byte_record() {
+        let mut art = b"\x1b[31m#\x1b[0m\r\n".to_vec();
+        let len = art.len();
+        let sauce = Sauce { title: "Sunset".to_string(), author: "Anna Lüthi".to_string(), group: String::new() };
+        sauce.append_to(&mut art, 80, 25);
+        let record = &art[len + 1..];
+        assert_eq!(art[len], EOF);
+        assert_eq!(record.len(), 128);
+        assert_eq!(&record[..7], b"SAUCE00");
+        assert_eq!(&record[7..42], format!("{:35}", "Sunset").as_bytes());
+        assert_eq!(&record[42..62], format!("{:20}", "Anna L?thi").as_bytes());
+        assert_eq!(&record[62..82], [b' '; 20]);
+        assert_eq!(u32::from_le_bytes(record[90..94].try_into().unwrap()), len as u32);
+        assert_eq!(&record[94..96], [DATA_TYPE_CHARACTER, FILE_TYPE_ANSI]);
+        assert_eq!(u16::from_le_bytes([record[96], record[97]]), 80);
+        assert_eq!(u16::from_le_bytes([record[98], record[99]]), 25);
+        assert!(record[106..].starts_with(FONT.as_bytes()));
+    }
+
+    #[test]
+    fn cuts_long_fields() {
+        let mut field = Vec::new();
+        push_field(&mut field, &"x".repeat(50), 35);
+        assert_eq!(field, [b'x'; 35]);
+    }
+
+    #[test]
+    fn dates_are_ccyymmdd() {
+        let date = today();
+        assert_eq!(date.len(), 8);
+        assert!(date.starts_with("20") && date.bytes().all(|byte| byte.is_ascii_digit()));
+        let month: u32 = date[4..6].parse().unwrap();
+        let day: u32 = date[6..].parse().unwrap();
+        assert!((1..=12).contains(&month) && (1..=31).contains(&day));
+    }
+}
@@ -0,0 +1,338 @@
+#[cfg(feature = "heic")]
+use crate::heic;
+use crate::animation::{self, AnimatedFormat, FrameSequence};
+use crate::{multipage, svg};
+use actix_multipart::Field;
+use actix_web::error::ErrorPayloadTooLarge;
+use actix_web::Error;
+use futures_util::stream::StreamExt;
+use image::{DynamicImage, ImageFormat, ImageResult};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+// --- UPLOAD SPOOLING ---
+
+/// Bytes inspected to recognize formats the image crate cannot guess.
+const SNIFF_LEN: usize = 1024;
+
+/// An uploaded file body, kept in memory while small and spooled to disk once it grows
+/// past the configured threshold. Clones share the bytes and read them independently.
+#[derive(Clone)]
+pub struct UploadBody {
+    storage: Storage,
+    digest: [u8; 32],
+    len: u64,
+}
+
+#[derive(Clone)]
+enum Storage {
+    Memory(Arc<Vec<u8>>),
+    Spooled(Arc<File>),
+}
+
+/// Reads a spooled body at its own position instead of the file's offset, which every
+/// clone of the file shares, so clones can be read at the same time.
+struct SpoolReader<'a> {
+    file: &'a File,
+    len: u64,
+    position: u64,
+}
+
+impl<'a> SpoolReader<'a> {
+    fn new(file: &'a File, len: u64) -> Self {
+        Self { file, len, position: 0 }
+    }
+}
+
+impl Read for SpoolReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SpoolReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the body"))?;
+        Ok(self.position)
+    }
+}
+
+impl UploadBody {
+    /// Reads a multipart field to completion, switching to an anonymous temp file as soon
+    /// as more than `threshold` bytes have been received. Bodies larger than `limit` are
+    /// refused with 413; a `limit` of 0 accepts any size.
+    pub async fn read_field(field: &mut Field, threshold: usize, limit: u64) -> Result<Option<Self>, Error> {
+        let mut buffer = Vec::new();
+        let mut spool: Option<tokio::fs::File> = None;
+        let mut hasher = Sha256::new();
+        let mut len = 0u64;
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            len += chunk.len() as u64;
+            if limit > 0 && len > limit {
+                return Err(ErrorPayloadTooLarge(format!("The image is larger than {} MiB.", limit / 1024 / 1024)));
+            }
+            match spool.as_mut() {
+                Some(file) => file.write_all(&chunk).await?,
+                None if buffer.len() + chunk.len() > threshold => {
+                    let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+                    file.write_all(&buffer).await?;
+                    file.write_all(&chunk).await?;
+                    buffer = Vec::new();
+                    spool = Some(file);
+                }
+                None => buffer.extend_from_slice(&chunk),
+            }
+        }
+
+        let storage = match spool {
+            Some(mut file) => {
+                file.flush().await?;
+                Storage::Spooled(Arc::new(file.into_std().await))
+            }
+            None if buffer.is_empty() => return Ok(None),
+            None => Storage::Memory(Arc::new(buffer)),
+        };
+        let digest = hasher.finalize().into();
+        Ok(Some(UploadBody { storage, digest, len }))
+    }
+
+    /// Size of the upload in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// SHA-256 of the uploaded bytes.
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    /// The first bytes of the body, for recognizing formats the image crate cannot guess.
+    fn head(&self) -> std::io::Result<Vec<u8>> {
+        match &self.storage {
+            Storage::Memory(data) => Ok(data[..data.len().min(SNIFF_LEN)].to_vec()),
+            Storage::Spooled(file) => {
+                let mut head = Vec::with_capacity(SNIFF_LEN);
+                SpoolReader::new(file, self.len).take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+                Ok(head)
+            }
+        }
+    }
+
+    /// Media type of the body when it is a format browsers display, for showing the upload
+    /// next to its art.
+    pub fn browser_content_type(&self) -> Option<&'static str> {
+        let head = self.head().ok()?;
+        if svg::is_svg(&head) {
+            return Some("image/svg+xml");
+        }
+        match image::guess_format(&head).ok()? {
+            ImageFormat::Png => Some("image/png"),
+            ImageFormat::Jpeg => Some("image/jpeg"),
+            ImageFormat::Gif => Some("image/gif"),
+            ImageFormat::WebP => Some("image/webp"),
+            ImageFormat::Bmp => Some("image/bmp"),
+            ImageFormat::Avif => Some("image/avif"),
+            _ => None,
+        }
+    }
+
+    /// The whole body.
+    pub fn into_bytes(self) -> std::io::Result<Vec<u8>> {
+        match self.storage {
+            Storage::Memory(data) => Ok(Arc::unwrap_or_clone(data)),
+            Storage::Spooled(file) => {
+                let mut data = Vec::with_capacity(self.len as usize);
+                SpoolReader::new(&file, self.len).read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// The same body in a temporary file, for keeping it around without holding it in memory.
+    pub fn into_spooled(self) -> std::io::Result<Self> {
+        let Storage::Memory(data) = &self.storage else { return Ok(self) };
+        let mut file = tempfile::tempfile()?;
+        std::io::Write::write_all(&mut file, data)?;
+        Ok(UploadBody { storage: Storage::Spooled(Arc::new(file)), digest: self.digest, len: self.len })
+    }
+
+    /// Number of pages: the images in a multi-page TIFF, otherwise 1.
+    pub fn page_count(&self) -> ImageResult<usize> {
+        if !multipage::is_tiff(&self.head()?) {
+            return Ok(1);
+        }
+        match &self.storage {
+            Storage::Memory(data) => multipage::page_count(Cursor::new(data.as_slice())),
+            Storage::Spooled(file) => multipage::page_count(BufReader::new(SpoolReader::new(file, self.len))),
+        }
+    }
+
+    /// All frames of an animated GIF, APNG or WebP; `None` for anything else.
+    pub fn decode_animation(&self) -> ImageResult<Option<FrameSequence>> {
+        let Some(format) = AnimatedFormat::sniff(&self.head()?) else {
+            return Ok(None);
+        };
+        match &self.storage {
+            Storage::Memory(data) => animation::decode(Cursor::new(data.as_slice()), format),
+            Storage::Spooled(file) => animation::decode(BufReader::new(SpoolReader::new(file, self.len)), format),
+        }
+    }
+
+    /// The EXIF orientation tag (1-8), if the image carries one.
+    pub fn exif_orientation(&self) -> Option<u32> {
+        // libheif already turns HEIC images upright.
+        #[cfg(feature = "heic")]
+        if self.head().is_ok_and(|head| heic::is_heic(&head)) {
+            return None;
+        }
+        let exif = match &self.storage {
+            Storage::Memory(data) => exif::Reader::new().read_from_container(&mut Cursor::new(data.as_slice())),
+            Storage::Spooled(file) => {
+                exif::Reader::new().read_from_container(&mut BufReader::new(SpoolReader::new(file, self.len)))
+            }
+        };
+        exif.ok()?
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+            .value
+            .get_uint(0)
+    }
+
+    /// Decodes the body, guessing the image format from its contents. SVG documents are
+    /// rasterized, and HEIC is decoded with libheif when the `heic` feature is enabled.
+    /// `page` selects an image of a multi-page TIFF and must be below [`Self::page_count`].
+    pub fn decode(self, page: usize) -> ImageResult<DynamicImage> {
+        if page > 0 {
+            return match self.storage {
+                Storage::Memory(data) => multipage::decode_page(Cursor::new(data.as_slice()), page),
+                Storage::Spooled(file) => multipage::decode_page(BufReader::new(SpoolReader::new(&file, self.len)), page),
+            };
+        }
+        let head = self.head()?;
+        if svg::is_svg(&head) {
+            return svg::rasterize(&self.into_bytes()?);
+        }
+        #[cfg(feature = "heic")]
+        if heic::is_heic(&head) {
+            return heic::decode(&self.into_bytes()?);
+        }
+
+        match self.storage {
+            Storage::Memory(data) => image::load_from_memory(&data),
+            Storage::Spooled(file) => image::io::Reader::new(BufReader::new(SpoolReader::new(&file, self.len)))
+                .with_guessed_format()?
+                .decode(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl UploadBody {
+    /// A body held in memory, for tests elsewhere.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let storage = Storage::Memory(Arc::new(data.to_vec()));
+        UploadBody { storage, digest: Sha256::digest(data).into(), len: data.len() as u64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn png() -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::from_pixel(3, 2, image::Rgb([200, 10, 10]))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn body(data: &[u8], spooled: bool) -> UploadBody {
+        let storage = if spooled {
+            let mut file = tempfile::tempfile().unwrap();
+            file.write_all(data).unwrap();
+            Storage::Spooled(Arc::new(file))
+        } else {
+            Storage::Memory(Arc::new(data.to_vec()))
+        };
+        UploadBody { storage, digest: Sha256::digest(data).into(), len: data.len() as u64 }
+    }
+
+    #[test]
+    fn reads_memory_and_spooled_bodies_alike() {
+        let data = png();
+        for spooled in [false, true] {
+            let body = body(&data, spooled);
+            assert_eq!(body.browser_content_type(), Some("image/png"));
+            assert_eq!(body.page_count().unwrap(), 1);
+            assert_eq!(body.clone().into_bytes().unwrap(), data);
+            assert_eq!(body.decode(0).unwrap().to_rgb8().get_pixel(2, 1).0, [200, 10, 10]);
+        }
+    }
+
+    #[test]
+    fn clones_read_independently() {
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let body = body(&data, true);
+        let clone = body.clone();
+        let (Storage::Spooled(first), Storage::Spooled(second)) = (&body.storage, &clone.storage) else { unreachable!() };
+        let mut first = SpoolReader::new(first, body.len);
+        let mut second = SpoolReader::new(second, clone.len);
+        let (mut a, mut b) = (vec![0; 700], vec![0; 300]);
+        let mut read = (Vec::new(), Vec::new());
+        loop {
+            let n = first.read(&mut a).unwrap();
+            read.0.extend_from_slice(&a[..n]);
+            let m = second.read(&mut b).unwrap();
+            read.1.extend_from_slice(&b[..m]);
+            if n == 0 && m == 0 {
+                break;
+            }
+        }
+        assert_eq!(read.0, data);
+        assert_eq!(read.1, data);
+    }
+
+    #[test]
+    fn clones_share_the_bytes() {
+        let body = body(&png(), false);
+        let clone = body.clone();
+        let (Storage::Memory(first), Storage::Memory(second)) = (&body.storage, &clone.storage) else { unreachable!() };
+        assert!(Arc::ptr_eq(first, second));
+    }
+
+    #[test]
+    fn seeks_within_the_body() {
+        let body = body(b"0123456789", true);
+        let Storage::Spooled(file) = &body.storage else { unreachable!() };
+        let mut reader = SpoolReader::new(file, body.len);
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "789");
+        assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+    }
+
+    #[test]
+    fn spools_memory_bodies() {
+        let data = png();
+        let spooled = body(&data, false).into_spooled().unwrap();
+        assert!(matches!(spooled.storage, Storage::Spooled(_)));
+        assert_eq!(spooled.digest(), &<[u8; 32]>::from(Sha256::digest(&data)));
+        assert_eq!(spooled.into_bytes().unwrap(), data);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::concurrency::{ConversionLimiter, Priority};
+use crate::metrics::METRICS;
+use crate::{AsciiConfig, AsciiConverter, ColorTheme};
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use futures_util::stream::StreamExt;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, ImageResult};
+use serde::Deserialize;
+
+// --- WEBCAM STREAMING ---
+
+const DEFAULT_STREAM_WIDTH: u32 = 100;
+const MAX_STREAM_WIDTH: u32 = 300;
+/// Upper bound for a single incoming JPEG frame.
+const MAX_FRAME_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    width: Option<u32>,
+    theme: Option<String>,
+    detailed: Option<bool>,
+}
+
+/// Converts a stream of same-sized frames, reusing the computed output dimensions and a
+/// cheaper resize filter so each frame can be turned around in real time.
+struct FrameConverter {
+    converter: AsciiConverter,
+    cached_dimensions: Option<((u32, u32), (u32, u32))>,
+}
+
+impl FrameConverter {
+    fn new(config: AsciiConfig) -> Self {
+        Self { converter: AsciiConverter::new(config), cached_dimensions: None }
+    }
+
+    fn convert(&mut self, jpeg: &[u8]) -> ImageResult<String> {
+        let gray = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg)?.to_luma8();
+        let source = gray.dimensions();
+        let (width, height) = match self.cached_dimensions {
+            Some((cached_source, target)) if cached_source == source => target,
+            _ => {
+                let target = self.converter.target_dimensions(source.0, source.1);
+                self.cached_dimensions = Some((source, target));
+                target
+            }
+        };
+        let resized = imageops::resize(&gray, width, height, FilterType::Triangle);
+        Ok(self.converter.render_rows(&resized, None, None))
+    }
+}
+
+/// Accepts binary JPEG frames and answers each one with a text message holding its ASCII
+/// frame. Each frame takes a conversion slot; when the backlog is full the frame is
+/// dropped and answered with an error instead.
+#[get("/ws/stream")]
+async fn ws_stream(
+    req: HttpRequest,
+    body: web::Payload,
+    query: web::Query<StreamQuery>,
+    limiter: web::Data<ConversionLimiter>,
+) -> Result<HttpResponse, Error> {
+    let theme = ColorTheme::from_name(query.theme.as_deref().unwrap_or_default());
+    let config = AsciiConfig {
+        width: query.width.unwrap_or(DEFAULT_STREAM_WIDTH).clamp(1, MAX_STREAM_WIDTH),
+        ..AsciiConfig::new(theme, query.detailed.unwrap_or(false))
+    };
+
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(run_session(session, messages.max_frame_size(MAX_FRAME_SIZE), config, limiter));
+    Ok(response)
+}
+
+async fn run_session(
+    mut session: Session,
+    mut messages: MessageStream,
+    config: AsciiConfig,
+    limiter: web::Data<ConversionLimiter>,
+) {
+    let mut frame_converter = Some(FrameConverter::new(config));
+
+    while let Some(Ok(message)) = messages.next().await {
+        match message {
+            Message::Binary(frame) => {
+                let slot = match limiter.acquire(Priority::Interactive).await {
+                    Ok(slot) => slot,
+                    Err(overloaded) => {
+                        METRICS.errors.with_label_values(&["overloaded"]).inc();
+                        if session.text(format!("error: {}", overloaded)).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                let Some(mut converter) = frame_converter.take() else { break };
+                let result = web::block(move || {
+                    let _slot = slot;
+                    let ascii = converter.convert(&frame);
+                    (converter, ascii)
+                })
+                .await;
+                let Ok((converter, ascii)) = result else { break };
+                frame_converter = Some(converter);
+
+                let reply = match ascii {
+                    Ok(ascii) => session.text(ascii).await,
+                    Err(err) => session.text(format!("error: {}", err)).await,
+                };
+                if reply.is_err() {
+                    return;
+                }
+            }
+            Message::Ping(bytes) if session.pong(&bytes).await.is_err() => return,
+            Message::Close(reason) => {
+                let _ = session.close(reason).await;
+                return;
+            }
+            _ => (),
+        }
+    }
+    let _ = session.close(None).await;
+}
@@ -0,0 +1,106 @@
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::sync::{Arc, LazyLock};
+
+// --- SVG RASTERIZATION ---
+
+/// Vector images are rasterized so their longer side has this many pixels, which leaves
+/// plenty of detail for any output width short of full resolution.
+const RASTER_SIZE: f32 = 2048.0;
+
+/// System fonts, loaded once, for SVGs that contain text.
+static FONTS: LazyLock<Arc<usvg::fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = usvg::fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+
+/// Sniffs for an SVG document (optionally behind an XML declaration or comments).
+pub fn is_svg(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<!--") || head.starts_with("<svg") || head.starts_with("<!DOCTYPE"))
+        && head.contains("<svg")
+}
+
+/// The file and face index of an installed font, looked up by family name for
+/// rendering with it.
+pub fn system_font(family: &str) -> Option<(Vec<u8>, u32)> {
+    let query = usvg::fontdb::Query { families: &[usvg::fontdb::Family::Name(family)], ..Default::default() };
+    let id = FONTS.query(&query)?;
+    FONTS.with_face_data(id, |data, index| (data.to_vec(), index))
+}
+
+/// Only `data:` images are embedded. usvg would otherwise read any other `href` as a path
+/// on the server's disk and draw that file into the art.
+pub fn rasterize(data: &[u8]) -> ImageResult<DynamicImage> {
+    let image_href_resolver = usvg::ImageHrefResolver {
+        resolve_string: Box::new(|_, _| None),
+        ..Default::default()
+    };
+    let options = usvg::Options { fontdb: FONTS.clone(), image_href_resolver, ..Default::default() };
+    let tree = usvg::Tree::from_data(data, &options)
+        .map_err(|err| ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("SVG".to_string()), err)))?;
+
+    let size = tree.size();
+    let scale = RASTER_SIZE / size.width().max(size.height());
+    let (width, height) = ((size.width() * scale).round().max(1.0) as u32, (size.height() * scale).round().max(1.0) as u32);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)))?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let img = RgbaImage::from_raw(width, height, pixmap.take_demultiplied()).expect("pixmap size matches");
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use image::{ImageFormat, Rgba};
+    use std::io::Cursor;
+
+    fn red_png() -> Vec<u8> {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn center(svg: &str) -> Rgba<u8> {
+        let img = rasterize(svg.as_bytes()).unwrap().into_rgba8();
+        *img.get_pixel(img.width() / 2, img.height() / 2)
+    }
+
+    #[test]
+    fn file_hrefs_are_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.png");
+        std::fs::write(&path, red_png()).unwrap();
+        let svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4"><image href="{}" width="4" height="4"/></svg>"#,
+            path.display()
+        );
+        assert_eq!(center(&svg)[3], 0);
+    }
+
+    #[test]
+    fn data_hrefs_are_drawn() {
+        let data = base64::engine::general_purpose::STANDARD.encode(red_png());
+        let svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4"><image href="data:image/png;base64,{}" width="4" height="4"/></svg>"#,
+            data
+        );
+        assert_eq!(center(&svg), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn sniffs_svg() {
+        assert!(is_svg(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(is_svg(b"\xef\xbb\xbf <svg width=\"1\"/>"));
+        assert!(!is_svg(b"<html><body></body></html>"));
+        assert!(!is_svg(b"\x89PNG\r\n"));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::output::AsciiGrid;
+use crate::render;
+use anyhow::{bail, Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{ImageOutputFormat, RgbImage};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref};
+use serde::Deserialize;
+use std::io::{Cursor, Write};
+use utoipa::IntoParams;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// --- TILING ---
+
+/// Panel size in columns and rows by default: about the shape of an A4 page in the
+/// built-in font.
+pub const DEFAULT_TILE_SIZE: (u32, u32) = (100, 70);
+const MIN_TILE_CELLS: u32 = 20;
+const MAX_TILE_CELLS: u32 = 500;
+/// Cells each panel repeats from its neighbour, so the printed panels can be lined up.
+const OVERLAP: u32 = 2;
+/// Length of the marks in the margin showing where the overlap starts.
+const MARK_LENGTH: u32 = 8;
+/// Space around the art of each panel, and below it for the label, in pixels.
+const MARGIN: u32 = 16;
+const LABEL_HEIGHT: u32 = 16;
+/// A4 in points, the page size of the PDF.
+const PAGE_SIZE: (f32, f32) = (595.0, 842.0);
+const PAGE_MARGIN: f32 = 36.0;
+/// Pixels drawn across all panels; each panel is encoded and dropped before the next is
+/// drawn, so this bounds the time spent rather than the memory.
+const MAX_TOTAL_PIXELS: u64 = 256 * 1024 * 1024;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TileQuery {
+    /// Columns per panel of the `zip` and `pdf` formats, 20-500 (default 100).
+    tile_columns: Option<u32>,
+    /// Rows per panel of the `zip` and `pdf` formats, 20-500 (default 70).
+    tile_rows: Option<u32>,
+}
+
+impl TileQuery {
+    pub fn size(&self) -> (u32, u32) {
+        let clamp = |cells: Option<u32>, default: u32| cells.unwrap_or(default).clamp(MIN_TILE_CELLS, MAX_TILE_CELLS);
+        (clamp(self.tile_columns, DEFAULT_TILE_SIZE.0), clamp(self.tile_rows, DEFAULT_TILE_SIZE.1))
+    }
+}
+
+/// One panel of the art.
+struct Tile {
+    /// Position among the panels, from zero.
+    row: u32,
+    column: u32,
+    art: String,
+    /// Cells shared with the neighbouring panel on the left, top, right and bottom.
+    overlap: [u32; 4],
+}
+
+/// Start and end of each panel along one axis of `length` cells.
+fn spans(length: u32, size: u32) -> Vec<(u32, u32)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(length);
+        spans.push((start, end));
+        if end == length {
+            return spans;
+        }
+        start = end - OVERLAP;
+    }
+}
+
+/// Cuts the art into panels of at most `tile_columns` by `tile_rows` cells, row by row; art that
+/// fits is a single panel.
+fn split(art: &str, (columns, rows): (u32, u32), (tile_columns, tile_rows): (u32, u32)) -> Vec<Tile> {
+    let lines: Vec<Vec<char>> = art.lines().map(|line| line.chars().collect()).collect();
+    let column_spans = spans(columns.max(1), tile_columns);
+    let row_spans = spans(rows.max(1), tile_rows);
+    let overlap = |spans: &[(u32, u32)], index: usize| {
+        let before = index.checked_sub(1).map_or(0, |previous| spans[previous].1 - spans[index].0);
+        let after = spans.get(index + 1).map_or(0, |next| spans[index].1 - next.0);
+        (before, after)
+    };
+
+    let mut tiles = Vec::new();
+    for (row, &(top, bottom)) in row_spans.iter().enumerate() {
+        for (column, &(left, right)) in column_spans.iter().enumerate() {
+            let art = lines[top as usize..(bottom as usize).min(lines.len())]
+                .iter()
+                .map(|line| line.iter().skip(left as usize).take((right - left) as usize).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let (overlap_left, overlap_right) = overlap(&column_spans, column);
+            let (overlap_top, overlap_bottom) = overlap(&row_spans, row);
+            tiles.push(Tile {
+                row: row as u32,
+                column: column as u32,
+                art,
+                overlap: [overlap_left, overlap_top, overlap_right, overlap_bottom],
+            });
+        }
+    }
+    tiles
+}
+
+/// Draws a panel in the grid's colors with a label below it and, in the margin, marks
+/// where it overlaps its neighbours.
+fn render_tile(grid: &AsciiGrid, tile: &Tile, number: usize, count: usize) -> Result<RgbImage> {
+    let art = render::render_bitmap(&tile.art, grid.background_color, grid.text_color, grid.font)?;
+    let columns = tile.art.lines().map(|line| line.chars().count()).max().unwrap_or(0).max(1) as u32;
+    let rows = tile.art.lines().count().max(1) as u32;
+    let (cell_width, cell_height) = (art.width() / columns, art.height() / rows);
+    let background = render::parse_hex_color(grid.background_color)?;
+    let foreground = render::parse_hex_color(grid.text_color)?;
+
+    let (width, height) = (art.width() + 2 * MARGIN, art.height() + 2 * MARGIN + LABEL_HEIGHT);
+    let mut img = RgbImage::from_pixel(width, height, background);
+    image::imageops::replace(&mut img, &art, i64::from(MARGIN), i64::from(MARGIN));
+
+    let [left, top, right, bottom] = tile.overlap;
+    let (art_right, art_bottom) = (MARGIN + art.width(), MARGIN + art.height());
+    let mut vertical_mark = |x: u32| {
+        for offset in 0..MARK_LENGTH {
+            img.put_pixel(x, MARGIN - 1 - offset, foreground);
+            img.put_pixel(x, art_bottom + offset, foreground);
+        }
+    };
+    if left > 0 {
+        vertical_mark(MARGIN + left * cell_width);
+    }
+    if right > 0 {
+        vertical_mark(art_right - right * cell_width);
+    }
+    let mut horizontal_mark = |y: u32| {
+        for offset in 0..MARK_LENGTH {
+            img.put_pixel(MARGIN - 1 - offset, y, foreground);
+            img.put_pixel(art_right + offset, y, foreground);
+        }
+    };
+    if top > 0 {
+        horizontal_mark(MARGIN + top * cell_height);
+    }
+    if bottom > 0 {
+        horizontal_mark(art_bottom - bottom * cell_height);
+    }
+
+    let label = format!("{}/{}  row {} column {}", number, count, tile.row + 1, tile.column + 1);
+    let label = render::render_bitmap(&label, grid.background_color, grid.text_color, None)?;
+    image::imageops::replace(&mut img, &label, i64::from(MARGIN), i64::from(art_bottom + MARGIN));
+    Ok(img)
+}
+
+/// The number of panels, and each panel rendered in turn with the name it is saved under,
+/// refusing art whose panels would take too long to draw. The first panel is the largest.
+fn rendered_tiles<'a>(grid: &'a AsciiGrid) -> (usize, impl Iterator<Item = Result<(String, Tile, RgbImage)>> + 'a) {
+    let tiles = split(grid.art, grid.dimensions, grid.tile_size);
+    let count = tiles.len();
+    let rendered = tiles.into_iter().enumerate().map(move |(index, tile)| {
+        let img = render_tile(grid, &tile, index + 1, count)?;
+        if index == 0 && u64::from(img.width()) * u64::from(img.height()) * count as u64 > MAX_TOTAL_PIXELS {
+            bail!("The art is too large to render as panels ({} panels of {}x{} pixels)", count, img.width(), img.height());
+        }
+        let name = format!("{:02}-row{}-column{}", index + 1, tile.row + 1, tile.column + 1);
+        Ok((name, tile, img))
+    });
+    (count, rendered)
+}
+
+/// A ZIP archive of the panels, each as a PNG and as plain text.
+pub fn zip(grid: &AsciiGrid) -> Result<Vec<u8>> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    // PNGs are compressed already.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for rendered in rendered_tiles(grid).1 {
+        let (name, tile, img) = rendered?;
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageOutputFormat::Png).context("Failed to encode PNG")?;
+        archive.start_file(format!("{}.png", name), stored)?;
+        archive.write_all(png.get_ref())?;
+        archive.start_file(format!("{}.txt", name), deflated)?;
+        archive.write_all(tile.art.as_bytes())?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+/// A PDF with one panel per A4 page, turned to landscape for wide panels.
+pub fn pdf(grid: &AsciiGrid) -> Result<Vec<u8>> {
+    let (count, tiles) = rendered_tiles(grid);
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    // Each page takes three objects: the page, its contents and its image.
+    let page_ids: Vec<Ref> = (0..count as i32).map(|index| Ref::new(3 + index * 3)).collect();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id).kids(page_ids.iter().copied()).count(count as i32);
+
+    for (rendered, &page_id) in tiles.zip(&page_ids) {
+        let (_, _, img) = rendered?;
+        let (content_id, image_id) = (Ref::new(page_id.get() + 1), Ref::new(page_id.get() + 2));
+        let (page_width, page_height) = if img.width() > img.height() { (PAGE_SIZE.1, PAGE_SIZE.0) } else { PAGE_SIZE };
+        let (image_width, image_height) = (img.width() as f32, img.height() as f32);
+        let scale = ((page_width - 2.0 * PAGE_MARGIN) / image_width).min((page_height - 2.0 * PAGE_MARGIN) / image_height);
+        let (width, height) = (image_width * scale, image_height * scale);
+
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, page_width, page_height));
+        page.parent(tree_id);
+        page.contents(content_id);
+        page.resources().x_objects().pair(Name(b"Tile"), image_id);
+        page.finish();
+
+        let mut content = Content::new();
+        content.save_state();
+        content.transform([width, 0.0, 0.0, height, (page_width - width) / 2.0, (page_height - height) / 2.0]);
+        content.x_object(Name(b"Tile"));
+        content.restore_state();
+        pdf.stream(content_id, &content.finish());
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(img.as_raw())?;
+        let pixels = encoder.finish()?;
+        let mut image = pdf.image_xobject(image_id, &pixels);
+        image.filter(Filter::FlateDecode);
+        image.width(img.width() as i32);
+        image.height(img.height() as i32);
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.finish();
+    }
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_overlap_their_neighbours() {
+        assert_eq!(spans(50, 100), vec![(0, 50)]);
+        assert_eq!(spans(100, 100), vec![(0, 100)]);
+        assert_eq!(spans(250, 100), vec![(0, 100), (98, 198), (196, 250)]);
+    }
+
+    #[test]
+    fn splits_row_by_row() {
+        let line = "0123456789".repeat(3);
+        let art = format!("{}\n", line).repeat(25);
+        let tiles = split(&art, (30, 25), (20, 20));
+        assert_eq!(tiles.len(), 4);
+        assert_eq!((tiles[1].row, tiles[1].column), (0, 1));
+        assert_eq!((tiles[2].row, tiles[2].column), (1, 0));
+        assert_eq!(tiles[0].overlap, [0, 0, 2, 2]);
+        assert_eq!(tiles[3].overlap, [2, 2, 0, 0]);
+        let first_line = tiles[1].art.lines().next().unwrap();
+        assert_eq!(first_line, &line[18..]);
+        assert_eq!(tiles[2].art.lines().count(), 7);
+    }
+
+    #[test]
+    fn single_panel_when_the_art_fits() {
+        let tiles = split("ab\ncd\n", (2, 2), DEFAULT_TILE_SIZE);
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].art, "ab\ncd");
+        assert_eq!(tiles[0].overlap, [0; 4]);
+    }
+}
@@ -0,0 +1,55 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+// --- TLS ---
+
+/// Builds a rustls configuration from PEM-encoded certificate chain and private key files.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<rustls::ServerConfig> {
+    let mut cert_reader = BufReader::new(
+        File::open(cert_path).with_context(|| format!("Failed to open certificate {}", cert_path.display()))?,
+    );
+    let certs = rustls_pemfile::certs(&mut cert_reader)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificate {}", cert_path.display()))?;
+
+    let mut key_reader = BufReader::new(
+        File::open(key_path).with_context(|| format!("Failed to open private key {}", key_path.display()))?,
+    );
+    let key = rustls_pemfile::private_key(&mut key_reader)
+        .with_context(|| format!("Failed to parse private key {}", key_path.display()))?
+        .with_context(|| format!("No private key found in {}", key_path.display()))?;
+
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid certificate/key pair")
+}
+
+/// Port the HTTPS listener is reachable on, used when redirecting plain HTTP requests.
+#[derive(Clone, Copy)]
+pub struct HttpsPort(pub u16);
+
+/// Catch-all handler for the plain HTTP listener when TLS is enabled.
+pub async fn redirect_to_https(req: HttpRequest, https_port: web::Data<HttpsPort>) -> HttpResponse {
+    let connection_info = req.connection_info();
+    let host = strip_port(connection_info.host());
+    let port = match https_port.0 {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, format!("https://{}{}{}", host, port, path)))
+        .finish()
+}
+
+fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.strip_prefix('[').and_then(|rest| rest.find(']')) {
+        return &host[..end + 2];
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
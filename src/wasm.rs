@@ -0,0 +1,151 @@
+use crate::mapper::{Pixels, Threshold};
+use crate::preprocess::{self, Grayscale, LocalThreshold};
+use crate::{charset, render, ColorTheme, Mode, DEFAULT_ASPECT_RATIO, DETAILED_CHARS, SIMPLE_CHARS};
+use image::imageops::FilterType;
+use wasm_bindgen::prelude::*;
+
+// --- WEBASSEMBLY BINDINGS ---
+
+/// Preview width in characters; low enough to redraw as soon as a file is picked.
+const DEFAULT_COLUMNS: u32 = 80;
+/// Widest preview; beyond this the server is the better place to convert.
+const MAX_COLUMNS: u32 = 300;
+
+/// Converts images in the browser with the server's mappers and character sets, built up
+/// like the form: `new Converter().with_theme("light").with_mode("edges").convert(bytes)`.
+/// EXIF orientation, animations and fonts are left to the server.
+#[wasm_bindgen]
+pub struct Converter {
+    columns: u32,
+    theme: ColorTheme,
+    mode: Mode,
+    grayscale: Grayscale,
+    threshold: Threshold,
+    dither: bool,
+    adaptive: Option<(LocalThreshold, u32, i16)>,
+    line_art: Option<(f32, u8)>,
+    chars: Vec<char>,
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            columns: DEFAULT_COLUMNS,
+            theme: ColorTheme::Dark,
+            mode: Mode::Ascii,
+            grayscale: Grayscale::Luma,
+            threshold: Threshold::Otsu,
+            dither: false,
+            adaptive: None,
+            line_art: None,
+            chars: SIMPLE_CHARS.chars().collect(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Converter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_columns(mut self, columns: u32) -> Self {
+        self.columns = columns.clamp(1, MAX_COLUMNS);
+        self
+    }
+
+    /// `dark` or `light`.
+    pub fn with_theme(mut self, name: &str) -> Self {
+        self.theme = ColorTheme::from_name(name);
+        self
+    }
+
+    /// One of the form's `mode` values, such as `braille` or `edges`.
+    pub fn with_mode(mut self, name: &str) -> Self {
+        self.mode = Mode::from_name(name);
+        self
+    }
+
+    /// One of the form's `grayscale` values, such as `average` or `red`.
+    pub fn with_grayscale(mut self, name: &str) -> Self {
+        self.grayscale = Grayscale::from_name(name);
+        self
+    }
+
+    /// The `stencil` mode's threshold: 0-255, or `otsu`.
+    pub fn with_threshold(mut self, value: &str) -> Result<Converter, JsError> {
+        self.threshold = Threshold::parse(value).ok_or_else(|| JsError::new(&format!("Invalid threshold '{}'", value)))?;
+        Ok(self)
+    }
+
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// The form's `adaptive` pass: `mean` or `sauvola` (anything else turns it off), over
+    /// blocks of `block` pixels with `offset` added.
+    pub fn with_adaptive(mut self, method: &str, block: u32, offset: i16) -> Self {
+        self.adaptive = LocalThreshold::from_name(method).map(|method| (method, block, offset));
+        self
+    }
+
+    /// The form's `line_art` filter with its `line_sigma` and `line_threshold`.
+    pub fn with_line_art(mut self, sigma: f32, threshold: u8) -> Self {
+        self.line_art = Some((sigma, threshold));
+        self
+    }
+
+    pub fn with_detailed(mut self, detailed: bool) -> Self {
+        self.chars = if detailed { DETAILED_CHARS } else { SIMPLE_CHARS }.chars().collect();
+        self
+    }
+
+    /// A named preset from `GET /api/charsets`.
+    pub fn with_preset(mut self, name: &str) -> Result<Converter, JsError> {
+        let preset = charset::preset(name).ok_or_else(|| JsError::new(&format!("Unknown preset '{}'", name)))?;
+        self.chars = preset.chars.chars().collect();
+        Ok(self)
+    }
+
+    /// A custom ramp, sorted by ink density unless `keep_order`, like the form's `charset` field.
+    pub fn with_charset(mut self, text: &str, keep_order: bool) -> Result<Converter, JsError> {
+        let mut chars = charset::parse(text).ok_or_else(|| JsError::new("A charset needs at least two distinct characters"))?;
+        if !keep_order {
+            charset::sort_by_density(&mut chars, None);
+        }
+        self.chars = chars;
+        Ok(self)
+    }
+
+    /// Decodes an encoded image (PNG, JPEG, GIF, WebP, ...) and returns the art.
+    pub fn convert(&self, bytes: &[u8]) -> Result<String, JsError> {
+        let img = image::load_from_memory(bytes).map_err(|err| JsError::new(&format!("Failed to decode image: {}", err)))?;
+        let (background, _, invert) = self.theme.colors();
+        let img = if img.color().has_alpha() {
+            preprocess::flatten_alpha(&img, render::parse_hex_color(background).expect("theme colors are valid"))
+        } else {
+            img
+        };
+
+        let mapper = self.mode.mapper(&self.chars, invert, self.threshold, self.dither);
+        let (cell_width, cell_height) = mapper.cell_size();
+        // Emoji are square and twice as wide as characters, as on the server.
+        let (columns, aspect_ratio) =
+            if self.mode == Mode::Emoji { (self.columns.div_ceil(2), 1.0) } else { (self.columns, DEFAULT_ASPECT_RATIO) };
+        let (columns, rows) = crate::output_size(img.width(), img.height(), columns, aspect_ratio);
+        // A cheaper filter than the server's Lanczos; a preview is redrawn often.
+        let sampled = img.resize_exact(columns * cell_width, rows * cell_height, FilterType::Triangle);
+
+        let mut gray = preprocess::grayscale(&sampled, self.grayscale);
+        if let Some((method, block, offset)) = self.adaptive {
+            gray = preprocess::adaptive_threshold(&gray, method, block, offset);
+        }
+        if let Some((sigma, threshold)) = self.line_art {
+            gray = preprocess::line_art(&gray, sigma, threshold, !invert);
+        }
+        let rgb = mapper.needs_colors().then(|| sampled.to_rgb8());
+        Ok(mapper.map(&Pixels::new(&gray, rgb.as_ref(), None), None))
+    }
+}
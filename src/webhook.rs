@@ -0,0 +1,319 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{IpAddr, ToSocketAddrs};
+use ureq::config::Config;
+use ureq::unversioned::resolver::{DefaultResolver, ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{DefaultConnector, NextTimeout};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// --- JOB WEBHOOKS ---
+
+/// Header carrying `sha256=<hex>`, the HMAC-SHA256 of the body under the server's secret.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+/// Time each delivery attempt gets, connecting included.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Waits before the second and third attempt; a delivery is given up after that.
+const RETRY_DELAYS: [Duration; 2] = [Duration::from_secs(1), Duration::from_secs(5)];
+
+/// Where to report a job's outcome, captured when it is submitted.
+pub struct Callback {
+    pub url: String,
+    /// Scheme and host the job was submitted to, for absolute result links.
+    pub base_url: String,
+}
+
+/// The JSON body POSTed to a job's callback URL once it finishes.
+#[derive(Serialize)]
+pub struct Payload {
+    pub id: String,
+    /// `done` or `failed`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why the result is smaller than asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+    pub status_url: String,
+    /// The result in each format it can be drawn in, by format name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub results: BTreeMap<&'static str, String>,
+    /// Seconds since the Unix epoch, so receivers can reject replayed deliveries.
+    pub timestamp: u64,
+}
+
+impl Payload {
+    pub fn new(id: &str, callback: &Callback, error: Option<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            status: if error.is_some() { "failed" } else { "done" },
+            error,
+            notice: None,
+            status_url: format!("{}/jobs/{}", callback.base_url, id),
+            results: BTreeMap::new(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+}
+
+/// Signs and delivers job callbacks. Callbacks are refused without a secret, since
+/// receivers could not tell them from forgeries.
+pub struct Webhooks {
+    secret: Option<Vec<u8>>,
+    agent: ureq::Agent,
+}
+
+impl Webhooks {
+    pub fn new(secret: Option<&str>) -> Self {
+        // A redirect could lead a delivery to an address the resolver refuses, and through a
+        // proxy the destination would be resolved where it cannot be checked.
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .max_redirects(0)
+            .proxy(None)
+            .build();
+        let agent = ureq::Agent::with_parts(config, DefaultConnector::default(), PublicResolver::default());
+        Self { secret: secret.map(|secret| secret.as_bytes().to_vec()), agent }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Posts `payload` to `url`, retrying failed attempts. Blocks until it is delivered or
+    /// given up, so call it off the async workers.
+    pub fn deliver(&self, url: &str, payload: &Payload) {
+        let Some(secret) = &self.secret else { return };
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(err) => return tracing::warn!(error = %err, "failed to serialize webhook payload"),
+        };
+        let signature = sign(secret, &body);
+
+        for attempt in 0..=RETRY_DELAYS.len() {
+            if attempt > 0 {
+                std::thread::sleep(RETRY_DELAYS[attempt - 1]);
+            }
+            let sent = self
+                .agent
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .send(&body[..]);
+            match sent {
+                Ok(_) => {
+                    tracing::info!(job.id = %payload.id, url, attempt = attempt + 1, "webhook delivered");
+                    return;
+                }
+                Err(err) => tracing::warn!(job.id = %payload.id, url, attempt = attempt + 1, error = %err, "webhook delivery failed"),
+            }
+        }
+        tracing::warn!(job.id = %payload.id, url, "giving up on webhook");
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body`.
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let mut signature = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        let _ = write!(signature, "{:02x}", byte);
+    }
+    signature
+}
+
+/// Accepts absolute `http` and `https` URLs with a host.
+pub fn is_valid_url(url: &str) -> bool {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+    rest.is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/') && !url.chars().any(char::is_whitespace))
+}
+
+/// Resolves like ureq's own resolver, but fails unless every address is public. The
+/// connection is made to exactly the addresses checked here, so a host that resolves
+/// differently the next time cannot slip an internal address in between.
+#[derive(Debug, Default)]
+struct PublicResolver(DefaultResolver);
+
+impl Resolver for PublicResolver {
+    fn resolve(
+        &self,
+        uri: &ureq::http::Uri,
+        config: &Config,
+        timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let addresses = self.0.resolve(uri, config, timeout)?;
+        match addresses.iter().find(|address| !is_public(address.ip())) {
+            Some(address) => Err(ureq::Error::Other(format!("{} is not a public address", address.ip()).into())),
+            None => Ok(addresses),
+        }
+    }
+}
+
+/// Resolves the URL's host and refuses it unless every address it has is public, so
+/// callbacks cannot reach the server's own network. Blocks on the DNS lookup. Deliveries
+/// are checked again as they connect.
+pub fn check_destination(url: &str) -> Result<(), String> {
+    let uri: ureq::http::Uri = url.parse().map_err(|_| format!("Invalid callback URL {:?}.", url))?;
+    let Some(host) = uri.host() else {
+        return Err(format!("Invalid callback URL {:?}.", url));
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+    let addresses: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| format!("The callback host {:?} cannot be resolved: {}.", host, err))?
+        .collect();
+    if addresses.is_empty() {
+        return Err(format!("The callback host {:?} cannot be resolved.", host));
+    }
+    match addresses.iter().find(|address| !is_public(address.ip())) {
+        Some(address) => Err(format!("The callback host {:?} is not a public address ({}).", host, address.ip())),
+        None => Ok(()),
+    }
+}
+
+/// Whether `ip` is reachable on the internet: not loopback, private, link-local (cloud
+/// metadata services live there), shared, reserved, unspecified, broadcast or multicast,
+/// nor an IPv6 address that reaches IPv4 through a translator or tunnel.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // 100.64.0.0/10, carrier-grade NAT.
+                || (a == 100 && (b & 0xc0) == 64)
+                // 192.0.0.0/24, protocol assignments.
+                || (a == 192 && b == 0 && c == 0)
+                // 198.18.0.0/15, benchmarking.
+                || (a == 198 && (b & 0xfe) == 18)
+                // 240.0.0.0/4, reserved, including the broadcast address.
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            // IPv4-mapped and the deprecated IPv4-compatible addresses; `::1` becomes
+            // 0.0.0.1 and `::` 0.0.0.0, which are refused as well.
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let [first, second, third, ..] = ip.segments();
+                !(ip.is_multicast()
+                    // fc00::/7, unique local addresses.
+                    || (first & 0xfe00) == 0xfc00
+                    // fe80::/10, link-local addresses.
+                    || (first & 0xffc0) == 0xfe80
+                    // 64:ff9b::/96 and 64:ff9b:1::/48, NAT64.
+                    || (first == 0x64 && second == 0xff9b && (third <= 1))
+                    // 2002::/16, 6to4.
+                    || first == 0x2002
+                    // 2001::/32 Teredo and 2001:db8::/32 documentation.
+                    || (first == 0x2001 && (second == 0 || second == 0xdb8))
+                    // 100::/64, discard.
+                    || (first == 0x100 && ip.segments()[1..4] == [0, 0, 0]))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn validates_url_shape() {
+        assert!(is_valid_url("https://example.com/hooks"));
+        assert!(is_valid_url("http://example.com"));
+        assert!(!is_valid_url("ftp://example.com/"));
+        assert!(!is_valid_url("https://"));
+        assert!(!is_valid_url("https:///path"));
+        assert!(!is_valid_url("https://example.com/a b"));
+    }
+
+    #[test]
+    fn refuses_internal_destinations() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://localhost:8080/hook",
+            "http://10.1.2.3/",
+            "http://192.168.0.10:9000/",
+            "http://172.16.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://0.0.0.0/",
+            "http://100.64.0.1/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(check_destination(url).is_err(), "{} was accepted", url);
+        }
+    }
+
+    #[test]
+    fn refuses_non_public_ranges() {
+        for ip in [
+            "198.18.0.1",
+            "198.19.255.254",
+            "240.0.0.1",
+            "255.255.255.255",
+            "192.0.0.8",
+            "192.0.2.1",
+            "::1",
+            "::",
+            "::127.0.0.1",
+            "::ffff:10.0.0.1",
+            "fc00::1",
+            "fe80::1",
+            "ff02::1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::1",
+            "2002:7f00:1::1",
+            "2001:0:4136:e378::1",
+            "2001:db8::1",
+            "100::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} counted as public", ip);
+        }
+        for ip in ["198.17.0.1", "198.20.0.1", "192.0.1.1", "223.0.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{} counted as internal", ip);
+        }
+    }
+
+    #[test]
+    fn deliveries_connect_only_to_checked_addresses() {
+        let resolver = PublicResolver::default();
+        let config = Config::default();
+        let timeout = || NextTimeout { after: TIMEOUT.into(), reason: ureq::Timeout::Global };
+        let resolve = |url: &str| resolver.resolve(&url.parse().unwrap(), &config, timeout());
+        assert!(resolve("http://127.0.0.1:9/").is_err());
+        assert!(resolve("http://[64:ff9b::7f00:1]/").is_err());
+        assert_eq!(resolve("https://93.184.216.34/").unwrap()[0], "93.184.216.34:443".parse().unwrap());
+
+        let webhooks = Webhooks::new(Some("secret"));
+        let err = webhooks.agent.post("http://127.0.0.1:9/").send(&b"{}"[..]).unwrap_err();
+        assert!(err.to_string().contains("not a public address"), "{}", err);
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        assert!(check_destination("https://93.184.216.34/hook").is_ok());
+        assert!(check_destination("http://[2606:4700::1111]:8080/").is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+const fileInput = document.getElementById('file-input');
+const fileNameDisplay = document.getElementById('file-name');
+const submitButton = document.getElementById('submit-button');
+const uploadArea = document.getElementById('upload-area');
+const uploadForm = document.getElementById('upload-form');
+const jobIdInput = document.getElementById('job-id');
+const progress = document.getElementById('progress');
+const progressBar = document.getElementById('progress-bar');
+const progressLabel = document.getElementById('progress-label');
+
+const cropArea = document.getElementById('crop-area');
+const cropPreview = document.getElementById('crop-preview');
+const cropSelection = document.getElementById('crop-selection');
+const cropHint = document.getElementById('crop-hint');
+const cropInputs = ['x', 'y', 'w', 'h'].map(name => document.getElementById(`crop-${name}`));
+
+// Disabled inputs are not submitted, so the theme colors apply unless the box is checked.
+const customColors = document.getElementById('custom-colors-checkbox');
+const colorInputs = ['background-color', 'text-color'].map(id => document.getElementById(id));
+customColors.addEventListener('change', () => {
+    colorInputs.forEach(input => input.disabled = !customColors.checked);
+});
+
+fileInput.addEventListener('change', () => {
+    clearRerun();
+    clearCrop();
+    loadPreview(fileInput.files[0]);
+    if (fileInput.files.length > 0) {
+        const fileName = fileInput.files[0].name;
+        // Several files are combined into one collage.
+        fileNameDisplay.textContent = fileInput.files.length > 1
+            ? `Selected: ${fileInput.files.length} images for a collage`
+            : `Selected: ${fileName}`;
+        submitButton.disabled = false;
+        if (cropPreview.src) URL.revokeObjectURL(cropPreview.src);
+        cropPreview.src = URL.createObjectURL(fileInput.files[0]);
+    } else {
+        fileNameDisplay.textContent = '';
+        submitButton.disabled = true;
+        cropArea.style.display = 'none';
+    }
+});
+
+// Drag-select a region on the preview. The browser shows the image upright, which
+// matches the server applying the EXIF orientation before cropping.
+cropPreview.addEventListener('load', () => { cropArea.style.display = 'block'; });
+cropPreview.addEventListener('error', () => { cropArea.style.display = 'none'; });
+cropPreview.addEventListener('dragstart', (e) => e.preventDefault());
+
+let dragStart = null;
+const pointInPreview = (e) => {
+    const rect = cropPreview.getBoundingClientRect();
+    return {
+        x: Math.min(Math.max(e.clientX - rect.left, 0), rect.width),
+        y: Math.min(Math.max(e.clientY - rect.top, 0), rect.height),
+    };
+};
+
+cropArea.addEventListener('mousedown', (e) => {
+    dragStart = pointInPreview(e);
+});
+window.addEventListener('mousemove', (e) => {
+    if (dragStart) drawSelection(dragStart, pointInPreview(e));
+});
+window.addEventListener('mouseup', (e) => {
+    if (!dragStart) return;
+    const end = pointInPreview(e);
+    const start = dragStart;
+    dragStart = null;
+    if (Math.abs(end.x - start.x) < 4 || Math.abs(end.y - start.y) < 4) {
+        clearCrop();
+        return;
+    }
+    drawSelection(start, end);
+    const scale = cropPreview.naturalWidth / cropPreview.clientWidth;
+    const values = [
+        Math.min(start.x, end.x), Math.min(start.y, end.y),
+        Math.abs(end.x - start.x), Math.abs(end.y - start.y),
+    ].map(value => Math.round(value * scale));
+    cropInputs.forEach((input, i) => { input.value = values[i]; });
+    cropHint.textContent = `Converting ${values[2]}x${values[3]} pixels at (${values[0]}, ${values[1]}). Click the image to reset.`;
+});
+
+function drawSelection(start, end) {
+    const offsetX = cropPreview.offsetLeft;
+    const offsetY = cropPreview.offsetTop;
+    cropSelection.style.display = 'block';
+    cropSelection.style.left = `${offsetX + Math.min(start.x, end.x)}px`;
+    cropSelection.style.top = `${offsetY + Math.min(start.y, end.y)}px`;
+    cropSelection.style.width = `${Math.abs(end.x - start.x)}px`;
+    cropSelection.style.height = `${Math.abs(end.y - start.y)}px`;
+}
+
+function clearCrop() {
+    cropInputs.forEach(input => { input.value = ''; });
+    cropSelection.style.display = 'none';
+    cropHint.textContent = 'Drag on the image to convert only part of it.';
+}
+
+// Instant low-resolution preview, drawn by the WebAssembly build of the conversion core.
+// The page works without it when the package has not been built into /static/pkg.
+const asciiPreview = document.getElementById('ascii-preview');
+const themeSelect = document.getElementById('theme-select');
+const modeSelect = document.getElementById('mode-select');
+const grayscaleSelect = document.getElementById('grayscale-select');
+const thresholdInput = document.getElementById('threshold-input');
+const ditherCheckbox = document.getElementById('dither-checkbox');
+const adaptiveSelect = document.getElementById('adaptive-select');
+const adaptiveBlockInput = document.getElementById('adaptive-block-input');
+const adaptiveOffsetInput = document.getElementById('adaptive-offset-input');
+const lineArtCheckbox = document.getElementById('line-art-checkbox');
+const lineSigmaInput = document.getElementById('line-sigma-input');
+const lineThresholdInput = document.getElementById('line-threshold-input');
+const detailedCheckbox = document.getElementById('detailed-checkbox');
+const presetSelect = document.getElementById('preset-select');
+const charsetInput = document.getElementById('charset-input');
+const keepOrderCheckbox = document.getElementById('keep-order-checkbox');
+const core = import('/static/pkg/png_to_ascii.js')
+    .then(async module => { await module.default(); return module; })
+    .catch(() => null);
+let previewBytes = null;
+
+async function loadPreview(file) {
+    previewBytes = file ? new Uint8Array(await file.arrayBuffer()) : null;
+    drawPreview();
+}
+
+async function drawPreview() {
+    const wasm = await core;
+    if (!wasm || !previewBytes) {
+        asciiPreview.style.display = 'none';
+        return;
+    }
+    try {
+        let converter = new wasm.Converter()
+            .with_theme(themeSelect.value)
+            .with_mode(modeSelect.value)
+            .with_grayscale(grayscaleSelect.value)
+            .with_threshold(thresholdInput.value)
+            .with_dither(ditherCheckbox.checked)
+            .with_adaptive(adaptiveSelect.value, Number(adaptiveBlockInput.value) || 31, Number(adaptiveOffsetInput.value) || 0)
+            .with_detailed(detailedCheckbox.checked);
+        if (lineArtCheckbox.checked) {
+            const threshold = lineThresholdInput.value === '' ? 2 : Number(lineThresholdInput.value);
+            converter = converter.with_line_art(Number(lineSigmaInput.value) || 1.0, threshold);
+        }
+        if (presetSelect.value) converter = converter.with_preset(presetSelect.value);
+        if (charsetInput.value) converter = converter.with_charset(charsetInput.value, keepOrderCheckbox.checked);
+        asciiPreview.textContent = converter.convert(previewBytes);
+        asciiPreview.classList.toggle('light', themeSelect.value === 'light');
+        asciiPreview.style.display = 'block';
+    } catch (err) {
+        // Formats the browser build cannot decode (HEIC, SVG, ...) are still converted on submit.
+        asciiPreview.style.display = 'none';
+    }
+}
+
+[themeSelect, modeSelect, grayscaleSelect, thresholdInput, ditherCheckbox, adaptiveSelect,
+    adaptiveBlockInput, adaptiveOffsetInput, lineArtCheckbox, lineSigmaInput, lineThresholdInput, detailedCheckbox, presetSelect, charsetInput, keepOrderCheckbox].forEach(input => {
+    input.addEventListener('input', drawPreview);
+});
+
+// Follow conversion progress over Server-Sent Events while the form submits.
+const stageLabels = {
+    waiting: 'Uploading...',
+    decoded: 'Image decoded',
+    resized: 'Image resized',
+    converting: 'Converting rows',
+    done: 'Done, loading result...',
+    failed: 'Conversion failed',
+};
+
+uploadForm.addEventListener('submit', () => {
+    const jobId = window.crypto && crypto.randomUUID
+        ? crypto.randomUUID()
+        : Date.now().toString(36) + '-' + Math.random().toString(36).slice(2);
+    jobIdInput.value = jobId;
+    submitButton.disabled = true;
+    progress.style.display = 'block';
+
+    const events = new EventSource(`/progress/${jobId}`);
+    const update = (e) => {
+        const data = JSON.parse(e.data);
+        progressBar.style.width = `${data.percent}%`;
+        progressLabel.textContent = data.stage === 'converting'
+            ? `${stageLabels.converting}: ${data.percent}%`
+            : stageLabels[data.stage];
+    };
+    events.addEventListener('progress', update);
+    ['done', 'failed'].forEach(eventName => {
+        events.addEventListener(eventName, (e) => { update(e); events.close(); });
+    });
+});
+
+// Recent conversions of this browser session, kept by the server. Each can be downloaded
+// again, or converted again with other options without uploading the image a second time.
+const historySection = document.getElementById('history');
+const historyList = document.getElementById('history-list');
+const historyClear = document.getElementById('history-clear');
+const rerunInput = document.getElementById('rerun-id');
+const rerunNote = document.getElementById('rerun-note');
+const rerunName = document.getElementById('rerun-name');
+const formatLabels = { text: '.txt', html: '.html', grid: 'grid JSON' };
+
+async function loadHistory() {
+    try {
+        const response = await fetch('/history');
+        if (!response.ok) return;
+        const { entries } = await response.json();
+        historyList.replaceChildren(...entries.map(historyItem));
+        historySection.style.display = entries.length > 0 ? 'block' : 'none';
+    } catch (err) {
+        historySection.style.display = 'none';
+    }
+}
+
+function historyItem(entry) {
+    const item = document.createElement('li');
+    const name = document.createElement('strong');
+    name.textContent = entry.name;
+    const meta = document.createElement('div');
+    meta.className = 'history-meta';
+    meta.textContent = `${entry.options.mode}, ${entry.columns}x${entry.rows} characters, ${new Date(entry.created * 1000).toLocaleString()}`;
+    const actions = document.createElement('div');
+    actions.className = 'history-actions';
+    entry.formats.forEach(format => {
+        const link = document.createElement('a');
+        link.href = `/history/${entry.id}?format=${format}`;
+        link.textContent = formatLabels[format] || `.${format}`;
+        if (format !== 'html') link.download = '';
+        actions.append(link);
+    });
+    if (entry.rerun) {
+        const rerun = document.createElement('button');
+        rerun.type = 'button';
+        rerun.className = 'link-button';
+        rerun.textContent = 'Convert again';
+        rerun.addEventListener('click', () => startRerun(entry));
+        actions.append(rerun);
+    }
+    item.append(name, meta, actions);
+    return item;
+}
+
+// Starts from the options the conversion used; the image stays on the server.
+function startRerun(entry) {
+    Object.entries(entry.options).forEach(([name, value]) => {
+        const input = uploadForm.elements.namedItem(name);
+        if (!input || input.type === 'hidden' || input.type === 'file') return;
+        if (input.type === 'checkbox') {
+            input.checked = value === true;
+        } else {
+            input.value = value === null ? '' : String(value);
+        }
+    });
+    fileInput.value = '';
+    fileInput.required = false;
+    fileNameDisplay.textContent = '';
+    cropArea.style.display = 'none';
+    clearCrop();
+    loadPreview(null);
+    rerunInput.value = entry.id;
+    rerunName.textContent = entry.name;
+    rerunNote.style.display = 'block';
+    submitButton.disabled = false;
+    uploadForm.scrollIntoView({ behavior: 'smooth' });
+}
+
+function clearRerun() {
+    if (!rerunInput.value) return;
+    rerunInput.value = '';
+    fileInput.required = true;
+    rerunNote.style.display = 'none';
+    submitButton.disabled = fileInput.files.length === 0;
+}
+
+document.getElementById('rerun-cancel').addEventListener('click', clearRerun);
+historyClear.addEventListener('click', async () => {
+    await fetch('/history', { method: 'DELETE' });
+    clearRerun();
+    loadHistory();
+});
+// Also when coming back from the result page through the browser's page cache.
+window.addEventListener('pageshow', loadHistory);
+
+// Optional: Visual feedback for drag-and-drop
+['dragenter', 'dragover', 'dragleave', 'drop'].forEach(eventName => {
+    uploadArea.addEventListener(eventName, preventDefaults, false);
+});
+
+function preventDefaults(e) {
+    e.preventDefault();
+    e.stopPropagation();
+}
+
+['dragenter', 'dragover'].forEach(eventName => {
+    uploadArea.addEventListener(eventName, () => uploadArea.style.backgroundColor = '#e9ecef', false);
+});
+
+['dragleave', 'drop'].forEach(eventName => {
+    uploadArea.addEventListener(eventName, () => uploadArea.style.backgroundColor = '#fff', false);
+});
+
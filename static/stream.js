@@ -0,0 +1,53 @@
+const video = document.getElementById('video');
+const canvas = document.getElementById('canvas');
+const output = document.getElementById('ascii-frame');
+const statusText = document.getElementById('status');
+const startButton = document.getElementById('start-button');
+
+// Frames are captured at a modest size; the server downsamples further to the ASCII width.
+const CAPTURE_WIDTH = 320;
+let socket = null;
+
+function sendFrame() {
+    if (!socket || socket.readyState !== WebSocket.OPEN) return;
+    const context = canvas.getContext('2d');
+    context.drawImage(video, 0, 0, canvas.width, canvas.height);
+    canvas.toBlob(blob => {
+        if (blob && socket.readyState === WebSocket.OPEN) socket.send(blob);
+    }, 'image/jpeg', 0.7);
+}
+
+async function start() {
+    const stream = await navigator.mediaDevices.getUserMedia({ video: true, audio: false });
+    video.srcObject = stream;
+    await video.play();
+    canvas.width = CAPTURE_WIDTH;
+    canvas.height = Math.round(CAPTURE_WIDTH * video.videoHeight / video.videoWidth);
+
+    const width = document.getElementById('width-input').value;
+    const detailed = document.getElementById('detailed-checkbox').checked;
+    const protocol = location.protocol === 'https:' ? 'wss:' : 'ws:';
+    socket = new WebSocket(`${protocol}//${location.host}/ws/stream?width=${width}&detailed=${detailed}`);
+
+    // Only send the next frame once the previous one has been answered.
+    socket.onopen = () => { statusText.textContent = 'Streaming...'; sendFrame(); };
+    socket.onmessage = (e) => { output.textContent = e.data; sendFrame(); };
+    socket.onclose = () => {
+        statusText.textContent = 'Connection closed.';
+        stream.getTracks().forEach(track => track.stop());
+        startButton.textContent = 'Start Camera';
+        socket = null;
+    };
+}
+
+startButton.addEventListener('click', () => {
+    if (socket) {
+        socket.close();
+        return;
+    }
+    startButton.textContent = 'Stop Camera';
+    start().catch(err => {
+        statusText.textContent = `Could not start camera: ${err.message}`;
+        startButton.textContent = 'Start Camera';
+    });
+});